@@ -52,4 +52,10 @@ extern crate pumpkinscript;
 
 extern crate uuid;
 
-extern crate num_cpus;
\ No newline at end of file
+extern crate num_cpus;
+
+#[cfg(feature = "mod_string")]
+extern crate unicode_normalization;
+
+#[cfg(feature = "mod_regex")]
+extern crate regex;
@@ -0,0 +1,186 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Capabilities
+//!
+//! Environments can be restricted from executing certain instructions by
+//! scheduling them with [`EnvOptions::capabilities`](../struct.EnvOptions.html).
+//! [`StandardDispatcher`](../dispatcher/struct.StandardDispatcher.html) fails
+//! such instructions with a `PermissionDenied` error. Environments spawned by
+//! a restricted environment (including its timers) are restricted in the same
+//! way. So are [persistent timers](../timer/index.html), even after they
+//! have been restored from the database.
+//!
+//! Restrictions are combined from named profiles:
+//!
+//! * `all` doesn't restrict anything
+//! * `read-only` denies writing to the database
+//! * `no-messaging` denies publishing and receiving messages, as well as
+//!   `DEBUG`, which is driven over messaging
+//! * `no-clock-observe` denies advancing the clock with `HLC/OBSERVE`
+
+use pumpkinscript::{binparser, ParseResult};
+
+use std::collections::BTreeSet;
+
+#[cfg(feature = "mod_storage")]
+use super::mod_storage::{WRITE, ASSOC, COMMIT, PROCEDURE_INSTALL, PROCEDURE_DROP, TIMER_RECORD, TIMER_DROP};
+#[cfg(feature = "mod_msg")]
+use super::mod_msg::{PUBLISH, SUBSCRIBE, UNSUBSCRIBE, RECEIVE, RECEIVE_TIMEOUT, DEBUG};
+#[cfg(feature = "mod_hlc")]
+use super::mod_hlc::HLC_OBSERVE;
+
+/// Names of all profiles
+const PROFILES: &'static [&'static str] = &["all", "read-only", "no-messaging", "no-clock-observe"];
+
+/// Returns instructions a named profile denies
+fn denied(profile: &str) -> Option<Vec<&'static [u8]>> {
+    let mut denied = Vec::new();
+    match profile {
+        "all" => (),
+        "read-only" => {
+            #[cfg(feature = "mod_storage")]
+            {
+                denied.extend_from_slice(&[WRITE, ASSOC, COMMIT, PROCEDURE_INSTALL, PROCEDURE_DROP,
+                                           TIMER_RECORD, TIMER_DROP]);
+            }
+        }
+        "no-messaging" => {
+            // the debugger is driven over messaging
+            #[cfg(feature = "mod_msg")]
+            {
+                denied.extend_from_slice(&[PUBLISH, SUBSCRIBE, UNSUBSCRIBE, RECEIVE, RECEIVE_TIMEOUT,
+                                           DEBUG]);
+            }
+        }
+        "no-clock-observe" => {
+            #[cfg(feature = "mod_hlc")]
+            {
+                denied.push(HLC_OBSERVE);
+            }
+        }
+        _ => return None,
+    }
+    Some(denied)
+}
+
+/// Instructions an environment is not allowed to execute
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    denied: BTreeSet<&'static [u8]>,
+}
+
+impl Capabilities {
+    /// Creates unrestricted capabilities
+    pub fn new() -> Self {
+        Capabilities::default()
+    }
+
+    /// Creates capabilities of a named profile
+    pub fn profile(name: &str) -> Option<Self> {
+        denied(name).map(|denied| Capabilities { denied: denied.into_iter().collect() })
+    }
+
+    /// Combines comma-separated named profiles, returns the name
+    /// of the first unknown profile as an error
+    pub fn profiles(names: &str) -> Result<Self, String> {
+        let mut capabilities = Capabilities::new();
+        for name in names.split(',').map(|name| name.trim()).filter(|name| name.len() > 0) {
+            match Capabilities::profile(name) {
+                Some(profile) => capabilities.denied.extend(profile.denied),
+                None => return Err(String::from(name)),
+            }
+        }
+        Ok(capabilities)
+    }
+
+    /// Returns `true` if the instruction is allowed
+    #[inline]
+    pub fn permits(&self, instruction: &[u8]) -> bool {
+        self.denied.is_empty() || !self.denied.contains(instruction)
+    }
+
+    /// Denies everything `other` denies as well
+    pub fn restrict(&mut self, other: &Capabilities) {
+        self.denied.extend(other.denied.iter());
+    }
+
+    /// Encodes capabilities as a sequence of denied instructions
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        for instruction in self.denied.iter() {
+            result.extend_from_slice(instruction);
+        }
+        result
+    }
+
+    /// Decodes capabilities encoded with `encode`, returns `None` if
+    /// any of the instructions can't be denied by a profile
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let known: BTreeSet<&'static [u8]> = PROFILES.iter()
+            .flat_map(|name| denied(name).unwrap_or_default())
+            .collect();
+        let mut capabilities = Capabilities::new();
+        let mut input = bytes;
+        while input.len() > 0 {
+            match binparser::instruction(input) {
+                ParseResult::Done(rest, instruction) => {
+                    match known.iter().find(|known| **known == instruction) {
+                        Some(known) => capabilities.denied.insert(*known),
+                        None => return None,
+                    };
+                    input = rest;
+                }
+                _ => return None,
+            }
+        }
+        Some(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Capabilities;
+
+    #[test]
+    fn profiles() {
+        assert!(Capabilities::new().permits(b"\x85WRITE"));
+        assert_eq!(Capabilities::profiles("all"), Ok(Capabilities::new()));
+        assert_eq!(Capabilities::profiles(""), Ok(Capabilities::new()));
+
+        let capabilities = Capabilities::profiles("read-only, no-clock-observe").unwrap();
+        assert!(!capabilities.permits(b"\x85WRITE"));
+        assert!(!capabilities.permits(b"\x8BHLC/OBSERVE"));
+        assert!(capabilities.permits(b"\x84READ"));
+        assert!(capabilities.permits(b"\x87PUBLISH"));
+
+        let capabilities = Capabilities::profiles("no-messaging").unwrap();
+        assert!(!capabilities.permits(b"\x87PUBLISH"));
+        assert!(!capabilities.permits(b"\x85DEBUG"));
+
+        assert_eq!(Capabilities::profiles("read-only,write-only"), Err(String::from("write-only")));
+    }
+
+    #[test]
+    fn encoding() {
+        let capabilities = Capabilities::profiles("read-only, no-messaging").unwrap();
+        assert_eq!(Capabilities::decode(&capabilities.encode()), Some(capabilities));
+        assert_eq!(Capabilities::decode(b""), Some(Capabilities::new()));
+        assert_eq!(Capabilities::decode(b"\x84DROP"), None);
+        assert_eq!(Capabilities::decode(b"\x85WRI"), None);
+    }
+
+    #[test]
+    fn restrict() {
+        let mut capabilities = Capabilities::profiles("read-only").unwrap();
+        capabilities.restrict(&Capabilities::profiles("no-clock-observe").unwrap());
+        assert_eq!(capabilities, Capabilities::profiles("read-only,no-clock-observe").unwrap());
+        capabilities.restrict(&Capabilities::new());
+        assert!(!capabilities.permits(b"\x8BHLC/OBSERVE"));
+    }
+
+}
@@ -0,0 +1,348 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Step debugger
+//!
+//! A closure can be evaluated under a debugger with `DEBUG` (see
+//! [mod_msg](../mod_msg/index.html)), which subscribes to a given topic
+//! to receive debugger commands. Before every pass, the scheduler asks the
+//! debugger whether the environment may proceed; while it is paused, the
+//! environment is skipped and other environments keep running.
+//!
+//! Commands are published to the topic as a sequence of data items, the
+//! command itself followed by its argument, if any:
+//!
+//! * `STEP` executes the next item and pauses again
+//! * `CONTINUE` runs until a breakpoint is reached
+//! * `BREAK` adds a breakpoint at an instruction (or a dictionary word)
+//! * `STACK`, `QUEUE`, `DICTIONARY` and `TRANSACTIONS` report the
+//!   respective part of the environment
+//!
+//! Every command is answered with an event, which is delivered to the
+//! environment's published message callback (the same way messages it
+//! subscribed to are), prefixed with the topic. An event is two data items:
+//! its name (`PAUSED`, `BREAKPOINTS`, `STACK`, `QUEUE`, `DICTIONARY`,
+//! `TRANSACTIONS` or `UNKNOWN`) and its payload.
+//!
+//! `PAUSED` payload is the item the environment is paused at, followed
+//! by its [trace](../trace/index.html). Internal instructions are never
+//! paused at.
+
+use pumpkinscript::{binparser, ParseResult};
+use num_bigint::BigUint;
+use num_traits::FromPrimitive;
+
+use std::sync::mpsc;
+
+use super::{Env, offset_by_size};
+use super::trace;
+
+#[cfg(feature = "mod_storage")]
+use super::mod_storage::{READ_END, WRITE_END};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Waiting for a command, `announced` is set once `PAUSED`
+    /// has been sent
+    Paused { announced: bool },
+    /// Execute one item and pause
+    Step,
+    /// Execute one item and keep running
+    Continue,
+    /// Run until a breakpoint
+    Running,
+}
+
+/// Debugger attached to an environment
+pub struct Debugger {
+    topic: Vec<u8>,
+    /// Subscription to the topic
+    pub subscription: Vec<u8>,
+    /// Number of `TRY`s the environment was in when the debugger was attached
+    pub depth: usize,
+    commands: mpsc::Receiver<(Vec<u8>, Vec<u8>)>,
+    breakpoints: Vec<Vec<u8>>,
+    state: State,
+}
+
+macro_rules! write_item {
+    ($item: expr, $vec: expr) => {{
+        write_size_header!($item, $vec);
+        $vec.extend_from_slice($item);
+    }};
+}
+
+impl Debugger {
+    /// Creates a paused debugger
+    pub fn new(topic: &[u8], subscription: Vec<u8>, depth: usize,
+               commands: mpsc::Receiver<(Vec<u8>, Vec<u8>)>) -> Self {
+        Debugger {
+            topic: Vec::from(topic),
+            subscription: subscription,
+            depth: depth,
+            commands: commands,
+            breakpoints: Vec::new(),
+            state: State::Paused { announced: false },
+        }
+    }
+
+    fn send(&self, env: &Env, event: &[u8], payload: &[u8]) {
+        if let Some(callback) = env.published_message_callback() {
+            let mut message = self.topic.clone();
+            write_item!(event, message);
+            write_item!(payload, message);
+            callback.call(&self.topic, &message);
+        }
+    }
+
+    fn paused<'a>(&self, env: &Env<'a>, item: &'a [u8]) {
+        let mut payload = Vec::new();
+        write_item!(item, payload);
+        write_item!(&trace::encode(&trace::trace(env, item)), payload);
+        self.send(env, b"PAUSED", &payload);
+    }
+
+    fn command(&mut self, env: &Env, command: &[u8]) {
+        let (name, argument) = match binparser::data(command) {
+            ParseResult::Done(rest, name) => {
+                let argument = match binparser::data(rest) {
+                    ParseResult::Done(_, argument) => &argument[offset_by_size(argument.len())..],
+                    _ => &[][..],
+                };
+                (&name[offset_by_size(name.len())..], argument)
+            }
+            _ => (command, &[][..]),
+        };
+        let mut payload = Vec::new();
+        if name == b"STEP" {
+            self.state = State::Step;
+        } else if name == b"CONTINUE" {
+            self.state = State::Continue;
+        } else if name == b"BREAK" {
+            if argument.len() > 0 {
+                self.breakpoints.push(Vec::from(argument));
+            }
+            for breakpoint in self.breakpoints.iter() {
+                write_item!(breakpoint, payload);
+            }
+            self.send(env, b"BREAKPOINTS", &payload);
+        } else if name == b"STACK" {
+            for item in env.stack() {
+                write_item!(item, payload);
+            }
+            self.send(env, b"STACK", &payload);
+        } else if name == b"QUEUE" {
+            for item in env.queue() {
+                write_item!(item, payload);
+            }
+            self.send(env, b"QUEUE", &payload);
+        } else if name == b"DICTIONARY" {
+            for (scope, word, definition) in definitions(env) {
+                let scope = BigUint::from_usize(scope).unwrap().to_bytes_be();
+                let mut entry = Vec::new();
+                write_item!(&scope, entry);
+                write_item!(word, entry);
+                write_item!(definition, entry);
+                write_item!(&entry, payload);
+            }
+            self.send(env, b"DICTIONARY", &payload);
+        } else if name == b"TRANSACTIONS" {
+            for transaction in transactions(env) {
+                write_item!(transaction, payload);
+            }
+            self.send(env, b"TRANSACTIONS", &payload);
+        } else {
+            self.send(env, b"UNKNOWN", name);
+        }
+    }
+
+    fn ready<'a>(&mut self, env: &Env<'a>) -> bool {
+        while let Ok((_, command)) = self.commands.try_recv() {
+            self.command(env, &command);
+        }
+        let item = match next(env) {
+            Some(item) => item,
+            None => return true,
+        };
+        // internal instructions and instructions skipped by an aborting TRY
+        // are executed without pausing
+        if (item.len() > 1 && item[0] == 0x80) || !env.aborting_try.is_empty() {
+            return true;
+        }
+        match self.state {
+            State::Paused { announced: false } => {
+                self.paused(env, item);
+                self.state = State::Paused { announced: true };
+                false
+            }
+            State::Paused { announced: true } => false,
+            State::Step => {
+                self.state = State::Paused { announced: false };
+                true
+            }
+            State::Continue => {
+                self.state = State::Running;
+                true
+            }
+            State::Running => {
+                if self.breakpoints.iter().any(|breakpoint| breakpoint.as_slice() == item) {
+                    self.paused(env, item);
+                    self.state = State::Paused { announced: true };
+                    false
+                } else {
+                    true
+                }
+            }
+        }
+    }
+}
+
+/// Returns the item that will be executed next
+fn next<'a>(env: &Env<'a>) -> Option<&'a [u8]> {
+    match env.program.last() {
+        Some(program) if program.len() > 0 => {
+            if let ParseResult::Done(_, data) = binparser::data(program) {
+                Some(data)
+            } else if let ParseResult::Done(_, instruction) =
+                binparser::instruction_or_internal_instruction(program) {
+                Some(instruction)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "scoped_dictionary")]
+fn definitions<'a>(env: &Env<'a>) -> Vec<(usize, &'a [u8], &'a [u8])> {
+    env.dictionary.iter().enumerate()
+        .flat_map(|(scope, d)| d.iter().map(move |(k, v)| (scope, *k, *v)))
+        .collect()
+}
+
+#[cfg(not(feature = "scoped_dictionary"))]
+fn definitions<'a>(env: &Env<'a>) -> Vec<(usize, &'a [u8], &'a [u8])> {
+    env.dictionary.iter().map(|(k, v)| (0, *k, *v)).collect()
+}
+
+/// Returns open transactions, innermost first
+#[cfg(feature = "mod_storage")]
+fn transactions(env: &Env) -> Vec<&'static [u8]> {
+    env.program.iter().rev()
+        .filter_map(|code| if *code == WRITE_END {
+            Some(&b"WRITE"[..])
+        } else if *code == READ_END {
+            Some(&b"READ"[..])
+        } else {
+            None
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "mod_storage"))]
+fn transactions(_: &Env) -> Vec<&'static [u8]> {
+    vec![]
+}
+
+/// Returns `true` if the environment can proceed with the next pass.
+///
+/// Processes pending debugger commands first, if there is a debugger attached.
+pub fn ready(env: &mut Env) -> bool {
+    match env.debugger.take() {
+        None => true,
+        Some(mut debugger) => {
+            let ready = debugger.ready(env);
+            env.debugger = Some(debugger);
+            ready
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pumpkinscript::parse;
+    use script::Env;
+    use std::sync::mpsc;
+    use super::{ready, Debugger};
+
+    fn env_with_program<'a>(program: &[u8]) -> Env<'a> {
+        let mut env = Env::new().unwrap();
+        {
+            let slice = env.alloc(program.len()).unwrap();
+            slice.copy_from_slice(program);
+            env.origin = slice;
+        }
+        let origin = env.origin;
+        env.program.push(origin);
+        env
+    }
+
+    fn command(name: &str, argument: &[u8]) -> Vec<u8> {
+        let mut command = Vec::new();
+        write_size_header!(name, command);
+        command.extend_from_slice(name.as_bytes());
+        if argument.len() > 0 {
+            write_size_header!(argument, command);
+            command.extend_from_slice(argument);
+        }
+        command
+    }
+
+    #[test]
+    fn step() {
+        let program = parse("1 DUP").unwrap();
+        let mut env = env_with_program(&program);
+        let origin = env.origin;
+        let (events, received) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+        env.set_published_message_callback(Box::new(events));
+        let (commands, receiver) = mpsc::channel();
+        env.debugger = Some(Debugger::new(b"DEBUG", vec![], 0, receiver));
+
+        // paused at the beginning
+        assert!(!ready(&mut env));
+        let (_, event) = received.try_recv().unwrap();
+        assert_eq!(&event[..12], b"DEBUG\x06PAUSED");
+        assert!(!ready(&mut env));
+
+        commands.send((vec![], command("STEP", b""))).unwrap();
+        assert!(ready(&mut env));
+        // `1` has been executed
+        env.program = vec![&origin[2..]];
+        assert!(!ready(&mut env));
+        let (_, event) = received.try_recv().unwrap();
+        assert_eq!(&event[..12], b"DEBUG\x06PAUSED");
+        // payload header, followed by `DUP`
+        assert_eq!(&event[12..18], b"\x0f\x04\x83DUP");
+    }
+
+    #[test]
+    fn breakpoint() {
+        let program = parse("1 DUP DROP").unwrap();
+        let mut env = env_with_program(&program);
+        let origin = env.origin;
+        let (events, received) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+        env.set_published_message_callback(Box::new(events));
+        let (commands, receiver) = mpsc::channel();
+        env.debugger = Some(Debugger::new(b"DEBUG", vec![], 0, receiver));
+
+        commands.send((vec![], command("BREAK", b"\x84DROP"))).unwrap();
+        commands.send((vec![], command("CONTINUE", b""))).unwrap();
+        assert!(ready(&mut env));
+        let (_, event) = received.try_recv().unwrap();
+        assert_eq!(&event[..17], b"DEBUG\x0bBREAKPOINTS");
+
+        env.program = vec![&origin[2..]];
+        assert!(ready(&mut env));
+
+        env.program = vec![&origin[6..]];
+        assert!(!ready(&mut env));
+        let (_, event) = received.try_recv().unwrap();
+        assert_eq!(&event[..12], b"DEBUG\x06PAUSED");
+    }
+
+}
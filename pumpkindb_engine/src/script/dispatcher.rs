@@ -6,28 +6,105 @@
 
 use super::*;
 use std::iter::Iterator;
+use std::collections::HashMap;
+use std::collections::hash_map;
 
 pub trait Dispatcher<'a> {
+    /// Prepares a new environment, the environment fails
+    /// with the error returned
     #[allow(unused_variables)]
-    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) {}
+    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) -> Result<(), Error> {
+        Ok(())
+    }
     #[allow(unused_variables)]
     fn done(&mut self, env: &mut Env<'a>, pid: EnvId) {}
+    /// Called once the environment has terminated (successfully or not),
+    /// right after `done`
+    #[allow(unused_variables)]
+    fn terminated(&mut self, env: &mut Env<'a>, pid: EnvId) {}
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a>;
+    /// Returns instruction's signature, if it is known
+    #[allow(unused_variables)]
+    fn signature(&self, instruction: &[u8]) -> Option<Signature> {
+        None
+    }
 }
 
 include!("macros.rs");
 
+/// Instruction handler function of a dispatcher `D`
+pub type Handle<'a, D> = fn(&mut D, &mut Env<'a>, &'a [u8], EnvId) -> PassResult<'a>;
+
+/// `DispatchTable` maps instructions to their handlers so that a handler
+/// can be found with a single lookup instead of trying every handler in turn.
+///
+/// Tables are typically built once, when a dispatcher is constructed,
+/// using `dispatch_table!`
+pub struct DispatchTable<'a, D> {
+    handlers: HashMap<&'static [u8], Handle<'a, D>>,
+    signatures: HashMap<&'static [u8], Signature>,
+}
+
+impl<'a, D> DispatchTable<'a, D> {
+    pub fn new() -> Self {
+        DispatchTable { handlers: HashMap::new(), signatures: HashMap::new() }
+    }
+
+    /// Registers a handler for an instruction. If the instruction
+    /// has been registered already, the first registration wins.
+    pub fn insert(&mut self, instruction: &'static [u8], handler: Handle<'a, D>) {
+        self.handlers.entry(instruction).or_insert(handler);
+    }
+
+    #[inline]
+    pub fn get(&self, instruction: &[u8]) -> Option<Handle<'a, D>> {
+        self.handlers.get(instruction).map(|handler| *handler)
+    }
+
+    pub fn iter<'b>(&'b self) -> hash_map::Iter<'b, &'static [u8], Handle<'a, D>> {
+        self.handlers.iter()
+    }
+
+    /// Records instruction's signature
+    pub fn insert_signature(&mut self, instruction: &'static [u8], signature: Signature) {
+        self.signatures.entry(instruction).or_insert(signature);
+    }
+
+    #[inline]
+    pub fn signature(&self, instruction: &[u8]) -> Option<Signature> {
+        self.signatures.get(instruction).map(|signature| *signature)
+    }
+
+    pub fn signatures<'b>(&'b self) -> hash_map::Iter<'b, &'static [u8], Signature> {
+        self.signatures.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
 impl<'a> Dispatcher<'a> for Vec<Box<Dispatcher<'a>>> {
-    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) {
+    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) -> Result<(), Error> {
         for mut disp in self.into_iter() {
-            disp.init(env, pid);
+            disp.init(env, pid)?;
         }
+        Ok(())
     }
     fn done(&mut self, env: &mut Env<'a>, pid: EnvId) {
         for mut disp in self.into_iter() {
             disp.done(env, pid);
         }
     }
+    fn terminated(&mut self, env: &mut Env<'a>, pid: EnvId) {
+        for mut disp in self.into_iter() {
+            disp.terminated(env, pid);
+        }
+    }
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
         let mut iter = self.into_iter();
         loop {
@@ -44,6 +121,9 @@ impl<'a> Dispatcher<'a> for Vec<Box<Dispatcher<'a>>> {
         }
         Err(Error::UnknownInstruction)
     }
+    fn signature(&self, instruction: &[u8]) -> Option<Signature> {
+        self.iter().filter_map(|disp| disp.signature(instruction)).next()
+    }
 }
 
 macro_rules! for_each_dispatcher {
@@ -108,15 +188,90 @@ macro_rules! for_each_dispatcher {
             let ref mut $module = $dispatcher.string;
             $expr
         }
+        #[cfg(feature="mod_regex")]
+        {
+            let ref mut $module = $dispatcher.regex;
+            $expr
+        }
+        #[cfg(feature="mod_encoding")]
+        {
+            let ref mut $module = $dispatcher.encoding;
+            $expr
+        }
+        #[cfg(feature="mod_random")]
+        {
+            let ref mut $module = $dispatcher.random;
+            $expr
+        }
+        #[cfg(feature="mod_error")]
+        {
+            let ref mut $module = $dispatcher.error;
+            $expr
+        }
     }};
 }
 
 use super::super::nvmem::NonVolatileMemory;
 
+/// Points to the handler of an instruction within one of
+/// `StandardDispatcher`'s modules
+enum Route<'a, P: 'a, S: 'a, N: 'a, T>
+    where P : messaging::Publisher, S : messaging::Subscriber,
+          N : NonVolatileMemory, T : AsRef<storage::Storage<'a>> + 'a
+{
+    #[cfg(feature = "mod_core")]
+    Core(Handle<'a, mod_core::Handler<'a>>),
+    #[cfg(feature = "mod_stack")]
+    Stack(Handle<'a, mod_stack::Handler<'a>>),
+    #[cfg(feature = "mod_queue")]
+    Queue(Handle<'a, mod_queue::Handler<'a>>),
+    #[cfg(feature = "mod_binaries")]
+    Binaries(Handle<'a, mod_binaries::Handler<'a>>),
+    #[cfg(feature = "mod_numbers")]
+    Numbers(Handle<'a, mod_numbers::Handler<'a>>),
+    #[cfg(feature = "mod_storage")]
+    Storage(Handle<'a, mod_storage::Handler<'a, T, N>>),
+    #[cfg(feature = "mod_hash")]
+    Hash(Handle<'a, mod_hash::Handler<'a>>),
+    #[cfg(feature = "mod_hlc")]
+    Hlc(Handle<'a, mod_hlc::Handler<'a, N>>),
+    #[cfg(feature = "mod_json")]
+    Json(Handle<'a, mod_json::Handler<'a>>),
+    #[cfg(feature = "mod_msg")]
+    Msg(Handle<'a, mod_msg::Handler<'a, P, S>>),
+    #[cfg(feature = "mod_uuid")]
+    Uuid(Handle<'a, mod_uuid::Handler<'a>>),
+    #[cfg(feature = "mod_string")]
+    String(Handle<'a, mod_string::Handler<'a>>),
+    #[cfg(feature = "mod_regex")]
+    Regex(Handle<'a, mod_regex::Handler<'a>>),
+    #[cfg(feature = "mod_encoding")]
+    Encoding(Handle<'a, mod_encoding::Handler<'a>>),
+    #[cfg(feature = "mod_random")]
+    Random(Handle<'a, mod_random::Handler<'a>>),
+    #[cfg(feature = "mod_error")]
+    Error(Handle<'a, mod_error::Handler<'a>>),
+}
+
+macro_rules! route {
+    ($dispatcher: expr, $module: ident, $route: path) => {{
+        for (instruction, handler) in $dispatcher.$module.table.iter() {
+            $dispatcher.routes.entry(*instruction).or_insert($route(*handler));
+        }
+        for (instruction, signature) in $dispatcher.$module.table.signatures() {
+            $dispatcher.signatures.entry(*instruction).or_insert(*signature);
+        }
+    }};
+}
+
+/// Dispatches instructions to the standard modules, denying those the
+/// environment lacks [capabilities](../capabilities/index.html) for
 pub struct StandardDispatcher<'a, P: 'a, S: 'a, N: 'a, T>
     where P : messaging::Publisher, S : messaging::Subscriber,
           N : NonVolatileMemory, T : AsRef<storage::Storage<'a>> + 'a
 {
+    routes: HashMap<&'static [u8], Route<'a, P, S, N, T>>,
+    signatures: HashMap<&'static [u8], Signature>,
     #[cfg(feature = "mod_core")]
     core: mod_core::Handler<'a>,
     #[cfg(feature = "mod_stack")]
@@ -140,7 +295,15 @@ pub struct StandardDispatcher<'a, P: 'a, S: 'a, N: 'a, T>
     #[cfg(feature = "mod_uuid")]
     uuid: mod_uuid::Handler<'a>,
     #[cfg(feature = "mod_string")]
-    string: mod_string::Handler<'a>
+    string: mod_string::Handler<'a>,
+    #[cfg(feature = "mod_regex")]
+    regex: mod_regex::Handler<'a>,
+    #[cfg(feature = "mod_encoding")]
+    encoding: mod_encoding::Handler<'a>,
+    #[cfg(feature = "mod_random")]
+    random: mod_random::Handler<'a>,
+    #[cfg(feature = "mod_error")]
+    error: mod_error::Handler<'a>,
 }
 
 
@@ -152,7 +315,9 @@ impl<'a, P: 'a, S: 'a, N: 'a, T> StandardDispatcher<'a, P, S, N, T>
                publisher: P, subscriber: S,
                timestamp_state: Arc<timestamp::Timestamp<N>>)
                -> Self {
-        StandardDispatcher {
+        let mut dispatcher = StandardDispatcher {
+                routes: HashMap::new(),
+                signatures: HashMap::new(),
                 #[cfg(feature = "mod_core")]
                     core: mod_core::Handler::new(),
                 #[cfg(feature = "mod_stack")]
@@ -177,27 +342,115 @@ impl<'a, P: 'a, S: 'a, N: 'a, T> StandardDispatcher<'a, P, S, N, T>
                     uuid: mod_uuid::Handler::new(),
                 #[cfg(feature = "mod_string")]
                     string: mod_string::Handler::new(),
-        }
+                #[cfg(feature = "mod_regex")]
+                    regex: mod_regex::Handler::new(),
+                #[cfg(feature = "mod_encoding")]
+                    encoding: mod_encoding::Handler::new(),
+                #[cfg(feature = "mod_random")]
+                    random: mod_random::Handler::new(),
+                #[cfg(feature = "mod_error")]
+                    error: mod_error::Handler::new(),
+        };
+        dispatcher.build_routes();
+        dispatcher
+    }
+
+    /// Collects dispatch tables (and signatures) of all modules into a single table,
+    /// so that any instruction can be routed to its handler with one lookup.
+    /// Should the same instruction be handled by more than one module,
+    /// the module that comes first takes precedence.
+    fn build_routes(&mut self) {
+        #[cfg(feature = "mod_core")]
+        route!(self, core, Route::Core);
+        #[cfg(feature = "mod_stack")]
+        route!(self, stack, Route::Stack);
+        #[cfg(feature = "mod_queue")]
+        route!(self, queue, Route::Queue);
+        #[cfg(feature = "mod_binaries")]
+        route!(self, binaries, Route::Binaries);
+        #[cfg(feature = "mod_numbers")]
+        route!(self, numbers, Route::Numbers);
+        #[cfg(feature = "mod_storage")]
+        route!(self, storage, Route::Storage);
+        #[cfg(feature = "mod_hash")]
+        route!(self, hash, Route::Hash);
+        #[cfg(feature = "mod_hlc")]
+        route!(self, hlc, Route::Hlc);
+        #[cfg(feature = "mod_json")]
+        route!(self, json, Route::Json);
+        #[cfg(feature = "mod_msg")]
+        route!(self, msg, Route::Msg);
+        #[cfg(feature = "mod_uuid")]
+        route!(self, uuid, Route::Uuid);
+        #[cfg(feature = "mod_string")]
+        route!(self, string, Route::String);
+        #[cfg(feature = "mod_regex")]
+        route!(self, regex, Route::Regex);
+        #[cfg(feature = "mod_encoding")]
+        route!(self, encoding, Route::Encoding);
+        #[cfg(feature = "mod_random")]
+        route!(self, random, Route::Random);
+        #[cfg(feature = "mod_error")]
+        route!(self, error, Route::Error);
     }
 }
 
 impl<'a, P: 'a, S: 'a, N: 'a, T> Dispatcher<'a> for StandardDispatcher<'a, P, S, N, T>
     where P : messaging::Publisher, S : messaging::Subscriber, N : NonVolatileMemory,
           T : AsRef<storage::Storage<'a>> + 'a {
-    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) {
-        for_each_dispatcher!(disp, self, disp.init(env, pid));
+    fn init(&mut self, env: &mut Env<'a>, pid: EnvId) -> Result<(), Error> {
+        for_each_dispatcher!(disp, self, disp.init(env, pid)?);
+        Ok(())
     }
     fn done(&mut self, env: &mut Env<'a>, pid: EnvId) {
         for_each_dispatcher!(disp, self, disp.done(env, pid));
     }
+    fn terminated(&mut self, env: &mut Env<'a>, pid: EnvId) {
+        for_each_dispatcher!(disp, self, disp.terminated(env, pid));
+    }
+    #[inline]
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        for_each_dispatcher!(disp, self, {
-           let result = disp.handle(env, instruction, pid);
-           if !result.is_unhandled() {
-              return result;
-           }
-        });
-        Err(Error::UnknownInstruction)
+        if !env.capabilities.permits(instruction) {
+            return Err(error_permission_denied!(instruction));
+        }
+        match self.routes.get(instruction) {
+            #[cfg(feature = "mod_core")]
+            Some(&Route::Core(handler)) => handler(&mut self.core, env, instruction, pid),
+            #[cfg(feature = "mod_stack")]
+            Some(&Route::Stack(handler)) => handler(&mut self.stack, env, instruction, pid),
+            #[cfg(feature = "mod_queue")]
+            Some(&Route::Queue(handler)) => handler(&mut self.queue, env, instruction, pid),
+            #[cfg(feature = "mod_binaries")]
+            Some(&Route::Binaries(handler)) => handler(&mut self.binaries, env, instruction, pid),
+            #[cfg(feature = "mod_numbers")]
+            Some(&Route::Numbers(handler)) => handler(&mut self.numbers, env, instruction, pid),
+            #[cfg(feature = "mod_storage")]
+            Some(&Route::Storage(handler)) => handler(&mut self.storage, env, instruction, pid),
+            #[cfg(feature = "mod_hash")]
+            Some(&Route::Hash(handler)) => handler(&mut self.hash, env, instruction, pid),
+            #[cfg(feature = "mod_hlc")]
+            Some(&Route::Hlc(handler)) => handler(&mut self.hlc, env, instruction, pid),
+            #[cfg(feature = "mod_json")]
+            Some(&Route::Json(handler)) => handler(&mut self.json, env, instruction, pid),
+            #[cfg(feature = "mod_msg")]
+            Some(&Route::Msg(handler)) => handler(&mut self.msg, env, instruction, pid),
+            #[cfg(feature = "mod_uuid")]
+            Some(&Route::Uuid(handler)) => handler(&mut self.uuid, env, instruction, pid),
+            #[cfg(feature = "mod_string")]
+            Some(&Route::String(handler)) => handler(&mut self.string, env, instruction, pid),
+            #[cfg(feature = "mod_regex")]
+            Some(&Route::Regex(handler)) => handler(&mut self.regex, env, instruction, pid),
+            #[cfg(feature = "mod_encoding")]
+            Some(&Route::Encoding(handler)) => handler(&mut self.encoding, env, instruction, pid),
+            #[cfg(feature = "mod_random")]
+            Some(&Route::Random(handler)) => handler(&mut self.random, env, instruction, pid),
+            #[cfg(feature = "mod_error")]
+            Some(&Route::Error(handler)) => handler(&mut self.error, env, instruction, pid),
+            _ => Err(Error::UnknownInstruction),
+        }
+    }
+    fn signature(&self, instruction: &[u8]) -> Option<Signature> {
+        self.signatures.get(instruction).map(|signature| *signature)
     }
 }
 
@@ -208,7 +461,7 @@ mod tests {
   use pumpkinscript::parse;
   use script::{Env, EnvId, PassResult,
                Scheduler, SchedulerHandle, Error, RequestMessage, ResponseMessage,
-               Dispatcher, TryInstruction};
+               Dispatcher, DispatchTable, TryInstruction};
   use std::sync::mpsc;
   use crossbeam;
 
@@ -252,7 +505,7 @@ mod tests {
           let (sender0, _) = mpsc::channel();
           sender.schedule_env(EnvId::new(), script.clone(), callback, Box::new(sender0));
           match receiver.recv() {
-              Ok(ResponseMessage::EnvTerminated(_, stack, stack_size)) => {
+              Ok(ResponseMessage::EnvTerminated(_, stack, stack_size, _, _)) => {
                   // terminated without an error
                   let mut stack_ = Vec::with_capacity(stack.len());
                   for i in 0..(&stack).len() {
@@ -262,7 +515,7 @@ mod tests {
                   let val = script_env.pop().unwrap();
                   assert_eq!(val, b"TEST");
               },
-              Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size)) => {
+              Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size, _, _, _)) => {
                   let _ = sender.send(RequestMessage::Shutdown);
                   panic!("error: {:?}", err);
               }
@@ -276,4 +529,20 @@ mod tests {
     });
   }
 
+  #[test]
+  pub fn dispatch_table() {
+      let mut table: DispatchTable<MyDispatcher> = DispatchTable::new();
+      assert!(table.get(b"\x84TEST").is_none());
+      table.insert(b"\x84TEST", MyDispatcher::handle_test);
+      table.insert(b"\x84TEST", |_, _, _, _| Err(Error::Reschedule));
+      assert_eq!(table.len(), 1);
+
+      let mut dispatcher = MyDispatcher::new();
+      let mut env = Env::new().unwrap();
+      let handler = table.get(b"\x84TEST").unwrap();
+      // first registration wins
+      assert!(handler(&mut dispatcher, &mut env, b"\x84TEST", EnvId::new()).is_ok());
+      assert_eq!(env.pop().unwrap(), b"TEST");
+  }
+
 }
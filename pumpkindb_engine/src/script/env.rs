@@ -5,12 +5,23 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use super::Error;
+use super::trace::Failure;
+use super::debugger::Debugger;
+use super::profiler::Profile;
+use super::mailbox::Mailbox;
+use super::park::Park;
+use super::spawn::Children;
+use super::recording::Recording;
+use super::capabilities::Capabilities;
 use super::envheap::EnvHeap;
 use super::super::messaging;
 
+use rand::{thread_rng, Rng, SeedableRng, OsRng};
+use rand::chacha::ChaChaRng;
+
 use std::collections::BTreeMap;
 
-/// Initial heap size
+/// Default heap chunk size
 pub const HEAP_SIZE: usize = 32_768;
 
 use std::collections::VecDeque;
@@ -20,6 +31,8 @@ use std::collections::VecDeque;
 /// used by [`Scheduler`](struct.Scheduler.html)
 pub struct Env<'a> {
     pub program: Vec<&'a [u8]>,
+    // program the environment was scheduled with
+    pub origin: &'a [u8],
     stack: VecDeque<Vec<&'a [u8]>>,
     queue: VecDeque<&'a [u8]>,
     heap: EnvHeap,
@@ -27,9 +40,32 @@ pub struct Env<'a> {
     pub dictionary: Vec<BTreeMap<&'a [u8], &'a [u8]>>,
     #[cfg(not(feature = "scoped_dictionary"))]
     pub dictionary: BTreeMap<&'a [u8], &'a [u8]>,
+    // stored procedures loaded when the environment was created
+    pub procedures: BTreeMap<&'a [u8], &'a [u8]>,
     // current TRY status
     pub tracking_errors: usize,
     pub aborting_try: Vec<Error>,
+    // last error's failure, to build its trace from
+    pub trace: Option<Failure<'a>>,
+    pub debugger: Option<Debugger>,
+    // active profiles, outermost first
+    pub profiles: Vec<Profile>,
+    // messages received on subscribed topics
+    pub mailbox: Mailbox,
+    // condition the environment is waiting for, if it is parked
+    pub parked: Option<Park>,
+    // spawned environments that haven't been joined yet
+    pub children: Children,
+    // results of non-deterministic instructions, if they are being recorded
+    pub recording: Option<Recording>,
+    // results of non-deterministic instructions left to replay, if replaying
+    pub replay: Option<Recording>,
+    // instructions the environment is allowed to execute
+    pub capabilities: Capabilities,
+    // random number generator, created on first use unless seeded
+    rng: Option<ChaChaRng>,
+    // whether the random number generator was seeded explicitly
+    seeded: bool,
     published_message_callback: Option<Box<messaging::PublishedMessageCallback + Send>>,
 }
 
@@ -57,6 +93,16 @@ impl<'a> Env<'a> {
     /// This function is useful for working with result stacks received from
     /// [Scheduler](struct.Scheduler.html)
     pub fn new_with_stack(stack: Vec<&'a [u8]>) -> Result<Self, Error> {
+        Env::new_with_stack_and_heap(stack, EnvHeap::new(HEAP_SIZE))
+    }
+
+    /// Creates an environment with an empty stack, allocating
+    /// off the given heap
+    pub fn new_with_heap(heap: EnvHeap) -> Result<Self, Error> {
+        Env::new_with_stack_and_heap(vec![], heap)
+    }
+
+    fn new_with_stack_and_heap(stack: Vec<&'a [u8]>, heap: EnvHeap) -> Result<Self, Error> {
         #[cfg(feature = "scoped_dictionary")]
         let dictionary = vec![BTreeMap::new()];
         #[cfg(not(feature = "scoped_dictionary"))]
@@ -65,12 +111,25 @@ impl<'a> Env<'a> {
         stacks.push_front(stack);
         Ok(Env {
             program: vec![],
+            origin: _EMPTY,
             stack: stacks,
             queue: VecDeque::new(),
-            heap: EnvHeap::new(HEAP_SIZE),
+            heap: heap,
             dictionary: dictionary,
+            procedures: BTreeMap::new(),
             tracking_errors: 0,
             aborting_try: Vec::new(),
+            trace: None,
+            debugger: None,
+            profiles: Vec::new(),
+            mailbox: Mailbox::new(),
+            parked: None,
+            children: Children::new(),
+            recording: None,
+            replay: None,
+            capabilities: Capabilities::new(),
+            rng: None,
+            seeded: false,
             published_message_callback: None,
         })
     }
@@ -115,6 +174,12 @@ impl<'a> Env<'a> {
         self.queue.pop_front()
     }
 
+    /// Returns the entire queue, front first
+    #[inline]
+    pub fn queue(&self) -> Vec<&'a [u8]> {
+        self.queue.iter().map(|v| *v).collect()
+    }
+
     /// Returns the entire stack
     #[inline]
     pub fn stack(&self) -> &[&'a [u8]] {
@@ -157,6 +222,62 @@ impl<'a> Env<'a> {
         Ok(unsafe { mem::transmute::<&mut [u8], &'a mut [u8]>(self.heap.alloc(len)) })
     }
 
+    /// Replaces slices referenced by the environment (its program, stacks,
+    /// queue and dictionary) that satisfy `pred` with copies allocated off
+    /// the heap, building the trace of the last error first if it refers
+    /// to any of them
+    ///
+    /// This allows to keep values borrowed from memory that is about to
+    /// be released, such as values read within a read transaction.
+    pub fn relocate<F: Fn(&[u8]) -> bool>(&mut self, pred: F) {
+        // the trace can't be built once the code it refers to has moved
+        if let Some(failure) = self.trace.take() {
+            self.trace = Some(if failure.references(&pred) {
+                failure.settle(self)
+            } else {
+                failure
+            });
+        }
+        let heap = &mut self.heap;
+        let mut relocate = |slice: &'a [u8]| -> &'a [u8] {
+            if !pred(slice) {
+                return slice;
+            }
+            if slice.len() == 0 {
+                return _EMPTY;
+            }
+            let copy = unsafe { mem::transmute::<&mut [u8], &'a mut [u8]>(heap.alloc(slice.len())) };
+            copy.copy_from_slice(slice);
+            copy
+        };
+        for item in self.program.iter_mut() {
+            *item = relocate(*item);
+        }
+        for stack in self.stack.iter_mut() {
+            for item in stack.iter_mut() {
+                *item = relocate(*item);
+            }
+        }
+        for item in self.queue.iter_mut() {
+            *item = relocate(*item);
+        }
+        #[cfg(feature = "scoped_dictionary")]
+        for dictionary in self.dictionary.iter_mut() {
+            *dictionary = mem::replace(dictionary, BTreeMap::new()).into_iter()
+                .map(|(k, v)| (relocate(k), relocate(v))).collect();
+        }
+        #[cfg(not(feature = "scoped_dictionary"))]
+        {
+            self.dictionary = mem::replace(&mut self.dictionary, BTreeMap::new()).into_iter()
+                .map(|(k, v)| (relocate(k), relocate(v))).collect();
+        }
+    }
+
+    /// Destroys the environment, returning its heap
+    /// so that it can be reused
+    pub fn into_heap(self) -> EnvHeap {
+        self.heap
+    }
 
     #[cfg(feature = "scoped_dictionary")]
     pub fn push_dictionary(&mut self) {
@@ -182,4 +303,34 @@ impl<'a> Env<'a> {
             Some(ref cb) => Some(cb.cloned())
         }
     }
-}
\ No newline at end of file
+
+    /// Seeds the random number generator, so that the environment
+    /// gets the same random numbers every time
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Some(ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32]));
+        self.seeded = true;
+    }
+
+    /// Draws a seed for an environment spawned by this one (or for a timer
+    /// registered by it), if this environment has been seeded, so that
+    /// the whole run stays reproducible
+    pub fn child_seed(&mut self) -> Option<u64> {
+        if self.seeded {
+            Some(self.rng().gen())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the random number generator, seeding it from the operating
+    /// system on first use if it hasn't been seeded explicitly
+    pub fn rng(&mut self) -> &mut ChaChaRng {
+        if self.rng.is_none() {
+            self.rng = Some(match OsRng::new() {
+                Ok(mut os_rng) => os_rng.gen(),
+                Err(_) => thread_rng().gen(),
+            });
+        }
+        self.rng.as_mut().unwrap()
+    }
+}
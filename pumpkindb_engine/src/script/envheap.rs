@@ -18,8 +18,11 @@ use std::slice;
 /// references.
 ///
 /// EnvHeap accomplishes that by adding new chunks instead
-/// of resizing existing ones.
+/// of resizing existing ones. Allocations that are larger than
+/// the chunk size get a chunk of their own, so that a single large
+/// value doesn't make every subsequent chunk oversized.
 pub struct EnvHeap {
+    chunk_size: usize,
     chunks: Vec<(usize, RawVec<u8>)>,
 }
 
@@ -27,39 +30,103 @@ impl EnvHeap {
     /// Creates new EnvHeap with a certain chunk size, which
     /// can't be changed later
     pub fn new(chunk_size: usize) -> Self {
-        EnvHeap { chunks: vec![(0, RawVec::with_capacity(chunk_size))] }
+        EnvHeap::with_chunk(chunk_size, RawVec::with_capacity(chunk_size))
+    }
+
+    fn with_chunk(chunk_size: usize, chunk: RawVec<u8>) -> Self {
+        EnvHeap {
+            chunk_size: chunk_size,
+            chunks: vec![(0, chunk)],
+        }
+    }
+
+    /// Returns the chunk size
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
     }
 
     /// Allocates a new mutable slice
     pub fn alloc(&mut self, size: usize) -> &mut [u8] {
-        let nchunks = self.chunks.len();
-        //Look for chunks with enough free space.
-        for i in 0..nchunks {
-            let cap = self.chunks[i].1.cap();
-            let ptr = self.chunks[i].0;
-            if ptr + size > cap {
-                if i == (nchunks - 1) {
-                    self.chunks.push((0, RawVec::with_capacity(cmp::max(cap, size))));
-                    return self.alloc(size)
-                } else {
-                    continue;
-                }
-            } else {
-                let (mut ptr, chunk) = self.chunks.pop().unwrap();
-                let slice_ptr = unsafe { chunk.ptr().offset(ptr as isize) };
-                ptr += size;
-                self.chunks.push((ptr, chunk));
-                return unsafe { slice::from_raw_parts_mut(slice_ptr, size) }
+        // Only the last chunk is allocated from, all other chunks are full
+        // or dedicated to a single allocation
+        let last = self.chunks.len() - 1;
+        let (ptr, cap) = (self.chunks[last].0, self.chunks[last].1.cap());
+        if ptr + size <= cap {
+            self.chunks[last].0 += size;
+            return unsafe { slice::from_raw_parts_mut(self.chunks[last].1.ptr().offset(ptr as isize), size) };
+        }
+        if size > self.chunk_size {
+            // keep allocating from the current chunk afterwards
+            let chunk = RawVec::with_capacity(size);
+            let slice_ptr = chunk.ptr();
+            self.chunks.insert(last, (size, chunk));
+            return unsafe { slice::from_raw_parts_mut(slice_ptr, size) };
+        }
+        self.chunks.push((0, RawVec::with_capacity(cmp::max(self.chunk_size, size))));
+        self.alloc(size)
+    }
+}
+
+/// Pool of heap chunks to be reused by subsequent environments
+/// (typically, on the same scheduler)
+///
+/// Only chunks of the pool's chunk size are kept, up to a limit.
+pub struct Pool {
+    chunk_size: usize,
+    limit: usize,
+    chunks: Vec<RawVec<u8>>,
+}
+
+impl Pool {
+    /// Creates an empty pool that keeps up to `limit` chunks
+    /// of `chunk_size` bytes
+    pub fn new(chunk_size: usize, limit: usize) -> Self {
+        Pool {
+            chunk_size: chunk_size,
+            limit: limit,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Returns the default chunk size
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the number of chunks available for reuse
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Creates a heap, reusing a chunk if one of the requested
+    /// size (pool's chunk size if `None`) is available
+    pub fn heap(&mut self, chunk_size: Option<usize>) -> EnvHeap {
+        let chunk_size = chunk_size.unwrap_or(self.chunk_size);
+        if chunk_size == self.chunk_size {
+            if let Some(chunk) = self.chunks.pop() {
+                return EnvHeap::with_chunk(chunk_size, chunk);
+            }
+        }
+        EnvHeap::new(chunk_size)
+    }
+
+    /// Takes chunks of a heap that is no longer used
+    pub fn recycle(&mut self, heap: EnvHeap) {
+        for (_, chunk) in heap.chunks {
+            if self.chunks.len() >= self.limit {
+                break;
+            }
+            if chunk.cap() == self.chunk_size {
+                self.chunks.push(chunk);
             }
         }
-        unreachable!();
     }
 }
 
 #[cfg(test)]
 #[allow(unused_variables, unused_must_use, unused_mut)]
 mod tests {
-    use script::envheap::EnvHeap;
+    use script::envheap::{EnvHeap, Pool};
 
     #[test]
     fn alloc() {
@@ -75,4 +142,39 @@ mod tests {
         }
         assert_eq!(50, heap.chunks.len());
     }
+
+    #[test]
+    fn alloc_oversized() {
+        let mut heap = EnvHeap::new(1_024);
+        heap.alloc(1_000);
+        heap.alloc(10_000);
+        heap.alloc(20);
+        assert_eq!(2, heap.chunks.len());
+        assert_eq!(1_024, heap.chunks[1].1.cap());
+        heap.alloc(100);
+        assert_eq!(3, heap.chunks.len());
+        assert_eq!(1_024, heap.chunks[2].1.cap());
+    }
+
+    #[test]
+    fn pool() {
+        let mut pool = Pool::new(1_024, 2);
+        let mut heap = pool.heap(None);
+        assert_eq!(1_024, heap.chunk_size());
+        heap.alloc(1_000);
+        heap.alloc(10_000);
+        heap.alloc(1_000);
+        heap.alloc(1_000);
+        pool.recycle(heap);
+        // oversized chunks aren't kept, and neither are chunks over the limit
+        assert_eq!(2, pool.len());
+        let heap = pool.heap(None);
+        assert_eq!(1, pool.len());
+        let other = pool.heap(Some(2_048));
+        assert_eq!(2_048, other.chunk_size());
+        assert_eq!(1, pool.len());
+        pool.recycle(heap);
+        pool.recycle(other);
+        assert_eq!(2, pool.len());
+    }
 }
@@ -49,6 +49,39 @@ macro_rules! handle_builtins {
     };
 }
 
+/// `dispatch_table!` builds a [`DispatchTable`](script/dispatcher/struct.DispatchTable.html)
+/// out of `INSTRUCTION => handler,` pairs, recording instruction signatures
+/// along the way. Pairs can be annotated with attributes (such as `#[cfg(...)]`).
+#[macro_export]
+macro_rules! dispatch_table {
+    ($($(#[$attr: meta])* $instruction: ident => $handler: expr,)*) => {{
+        let mut table = $crate::script::DispatchTable::new();
+        $(
+          $(#[$attr])*
+          {
+              table.insert($instruction, $handler);
+              if let Some(signature) = $instruction::SIGNATURE {
+                  table.insert_signature($instruction, signature);
+              }
+          }
+        )*
+        table
+    }};
+}
+
+/// `dispatch!` looks up a handler in the dispatcher's `table` and invokes it,
+/// yielding `Error::UnknownInstruction` if the instruction is not registered.
+#[macro_export]
+macro_rules! dispatch {
+    ($dispatcher: expr, $env: expr, $instruction: expr, $pid: expr) => {{
+        let handler = $dispatcher.table.get($instruction);
+        match handler {
+            Some(handler) => handler($dispatcher, $env, $instruction, $pid),
+            None => Err(Error::UnknownInstruction),
+        }
+    }};
+}
+
 #[macro_export]
 macro_rules! handle_error {
     ($env: expr, $err: expr) => {
@@ -188,6 +221,64 @@ macro_rules! error_no_value {
     }}
 }
 
+#[macro_export]
+macro_rules! error_permission_denied {
+    ($instruction: expr) => {{
+        error_program!(
+            "Permission denied".as_bytes(),
+            $instruction,
+            ERROR_PERMISSION_DENIED
+        )
+    }}
+}
+
+#[macro_export]
+macro_rules! error_division_by_zero {
+    () => {{
+        let vec = Vec::new();
+        error_program!(
+            "Division by zero".as_bytes(),
+            &vec,
+            ERROR_DIVISION_BY_ZERO
+        )
+    }}
+}
+
+#[macro_export]
+macro_rules! error_overflow {
+    () => {{
+        let vec = Vec::new();
+        error_program!(
+            "Overflow".as_bytes(),
+            &vec,
+            ERROR_OVERFLOW
+        )
+    }}
+}
+
+#[macro_export]
+macro_rules! error_underflow {
+    () => {{
+        let vec = Vec::new();
+        error_program!(
+            "Underflow".as_bytes(),
+            &vec,
+            ERROR_UNDERFLOW
+        )
+    }}
+}
+
+#[macro_export]
+macro_rules! error_replay_diverged {
+    ($instruction: expr) => {{
+        error_program!(
+            "Replay diverged".as_bytes(),
+            $instruction,
+            ERROR_REPLAY_DIVERGED
+        )
+    }}
+}
+
 #[macro_export]
 macro_rules! error_unknown_instruction {
     ($instruction: expr) => { {
@@ -273,7 +364,7 @@ macro_rules! eval {
                 sender.schedule_env(EnvId::new(),
                                     script.clone(), callback, Box::new($sender));
                 match receiver.recv() {
-                   Ok(ResponseMessage::EnvTerminated(_, stack, stack_size)) => {
+                   Ok(ResponseMessage::EnvTerminated(_, stack, stack_size, _, _)) => {
                       sender.shutdown();
                       messaging_accessor.shutdown();
                       let $result = Ok::<(), Error>(());
@@ -284,7 +375,7 @@ macro_rules! eval {
                       let mut $env = Env::new_with_stack(stack_).unwrap();
                       $expr;
                    }
-                   Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size)) => {
+                   Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size, _, _, _)) => {
                       sender.shutdown();
                       messaging_accessor.shutdown();
                       let $result = Err::<(), Error>(err);
@@ -358,8 +449,8 @@ macro_rules! bench_eval {
                     let _ = senders.clone().schedule_env(EnvId::new(),
                                            script.clone(), callback, Box::new(sender0));
                     match receiver.recv() {
-                       Ok(ResponseMessage::EnvTerminated(_, stack, stack_size)) => (),
-                       Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size)) => {
+                       Ok(ResponseMessage::EnvTerminated(_, stack, stack_size, _, _)) => (),
+                       Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size, _, _, _)) => {
                           senders.shutdown();
                           messaging_accessor.shutdown();
                           panic!("error: {:?}", err);
@@ -0,0 +1,161 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Mailbox
+//!
+//! Messages published to the topics an environment has subscribed to
+//! (see [mod_msg](../mod_msg/index.html)) are delivered both to the
+//! environment's published message callback and to its mailbox, where
+//! they are kept until they are received with `RECEIVE`.
+
+use super::super::messaging::PublishedMessageCallback;
+
+use std::collections::VecDeque;
+use std::sync::mpsc;
+
+type Message = (Vec<u8>, Vec<u8>);
+
+/// Delivers messages to a callback (if any) and to a mailbox
+struct Delivery {
+    callback: Option<Box<PublishedMessageCallback + Send>>,
+    mailbox: mpsc::Sender<Message>,
+}
+
+impl PublishedMessageCallback for Delivery {
+    fn call(&self, topic: &[u8], message: &[u8]) {
+        if let Some(ref callback) = self.callback {
+            callback.call(topic, message);
+        }
+        let _ = self.mailbox.send((Vec::from(topic), Vec::from(message)));
+    }
+
+    fn cloned(&self) -> Box<PublishedMessageCallback + Send> {
+        Box::new(Delivery {
+            callback: self.callback.as_ref().map(|callback| callback.cloned()),
+            mailbox: self.mailbox.clone(),
+        })
+    }
+}
+
+/// Environment's mailbox
+pub struct Mailbox {
+    channel: Option<(mpsc::Sender<Message>, mpsc::Receiver<Message>)>,
+    /// Messages delivered but not yet received, oldest first
+    pending: VecDeque<Message>,
+    /// Subscription identifiers and their topics
+    subscriptions: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Mailbox {
+    /// Creates an empty mailbox
+    pub fn new() -> Self {
+        Mailbox {
+            channel: None,
+            pending: VecDeque::new(),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    /// Returns a callback for a new subscription that delivers
+    /// messages to the mailbox and to the `callback`, if any
+    pub fn callback(&mut self, callback: Option<Box<PublishedMessageCallback + Send>>)
+                    -> Box<PublishedMessageCallback + Send> {
+        if self.channel.is_none() {
+            self.channel = Some(mpsc::channel());
+        }
+        let sender = match self.channel {
+            Some((ref sender, _)) => sender.clone(),
+            None => unreachable!(),
+        };
+        Box::new(Delivery {
+            callback: callback,
+            mailbox: sender,
+        })
+    }
+
+    /// Registers a subscription
+    pub fn subscribed(&mut self, identifier: &[u8], topic: &[u8]) {
+        self.subscriptions.push((Vec::from(identifier), Vec::from(topic)));
+    }
+
+    /// Removes a subscription, dropping messages that can't be received anymore
+    pub fn unsubscribed(&mut self, identifier: &[u8]) {
+        self.fetch();
+        self.subscriptions.retain(|&(ref id, _)| id.as_slice() != identifier);
+        let subscriptions = &self.subscriptions;
+        self.pending.retain(|&(ref topic, _)| subscriptions.iter().any(|&(_, ref t)| t == topic));
+    }
+
+    /// Returns `true` if there's a subscription to the topic
+    pub fn is_subscribed(&self, topic: &[u8]) -> bool {
+        self.subscriptions.iter().any(|&(_, ref t)| t.as_slice() == topic)
+    }
+
+    /// Moves delivered messages to the pending queue
+    fn fetch(&mut self) {
+        if let Some((_, ref receiver)) = self.channel {
+            while let Ok(message) = receiver.try_recv() {
+                self.pending.push_back(message);
+            }
+        }
+    }
+
+    /// Returns `true` if there's a message on the topic
+    pub fn has_message(&mut self, topic: &[u8]) -> bool {
+        self.fetch();
+        self.pending.iter().any(|&(ref t, _)| t.as_slice() == topic)
+    }
+
+    /// Takes the oldest message on the topic
+    pub fn take(&mut self, topic: &[u8]) -> Option<Vec<u8>> {
+        self.fetch();
+        let index = self.pending.iter().position(|&(ref t, _)| t.as_slice() == topic);
+        match index {
+            Some(index) => self.pending.remove(index).map(|(_, message)| message),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::mpsc;
+    use messaging::PublishedMessageCallback;
+    use super::Mailbox;
+
+    #[test]
+    fn delivery() {
+        let mut mailbox = Mailbox::new();
+        let (sender, receiver) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+        let sender: Box<PublishedMessageCallback + Send> = Box::new(sender);
+        let callback = mailbox.callback(Some(sender));
+        mailbox.subscribed(b"1", b"topic");
+        callback.call(b"topic", b"hello");
+        callback.cloned().call(b"topic", b"world");
+
+        assert_eq!(receiver.try_recv().unwrap(), (Vec::from("topic"), Vec::from("hello")));
+        assert!(mailbox.has_message(b"topic"));
+        assert!(!mailbox.has_message(b"other"));
+        assert_eq!(mailbox.take(b"topic"), Some(Vec::from("hello")));
+        assert_eq!(mailbox.take(b"topic"), Some(Vec::from("world")));
+        assert_eq!(mailbox.take(b"topic"), None);
+    }
+
+    #[test]
+    fn unsubscribed() {
+        let mut mailbox = Mailbox::new();
+        let callback = mailbox.callback(None);
+        mailbox.subscribed(b"1", b"topic");
+        callback.call(b"topic", b"hello");
+        assert!(mailbox.is_subscribed(b"topic"));
+
+        mailbox.unsubscribed(b"1");
+        assert!(!mailbox.is_subscribed(b"topic"));
+        assert!(!mailbox.has_message(b"topic"));
+    }
+
+}
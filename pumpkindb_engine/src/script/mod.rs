@@ -54,37 +54,70 @@
 //! * Binary and text (human readable & writable) forms
 //! * No types, just byte arrays
 //! * Dynamic code evaluation
-//! * Zero-copy interpretation (where feasible; currently does not apply to the most
-//!   important part, the storage itself as transactional model of LMDB precludes us
-//!   from carrying these references outside of the scope of the transaction)
+//! * Zero-copy interpretation (where feasible; values retrieved from the storage
+//!   are borrowed only within read transactions, as transactional model of LMDB
+//!   precludes us from carrying these references outside of the scope of the
+//!   transaction, see [mod_storage](mod_storage/index.html))
 //!
 
 pub mod envheap;
 pub mod dispatcher;
-pub use self::dispatcher::Dispatcher;
+pub use self::dispatcher::{Dispatcher, DispatchTable};
 
 use super::messaging;
 
 const _EMPTY: &'static [u8] = b"";
   
+/// Stack effect of an instruction, as declared in its `instruction!` definition:
+/// names of the items it consumes and of the items it produces
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signature {
+    pub inputs: &'static [&'static str],
+    pub outputs: &'static [&'static str],
+}
+
 /// `instruction!` macro is used to define a built-in instruction, its signature (if applicable)
 /// and representation
+///
+/// Alongside with the instruction constant, it defines a module of the same name
+/// containing `SIGNATURE` (`None` if the signature was not specified), which is
+/// used by [verifier](verifier/index.html).
 macro_rules! instruction {
     ($name : ident,
     ($($input : ident),* => $($output : ident),*),
     $ident : expr) =>
     (
-     instruction!($name, $ident);
+     pub(crate) const $name : &'static[u8] = $ident;
+     #[allow(non_snake_case, dead_code)]
+     pub(crate) mod $name {
+         pub const SIGNATURE: Option<$crate::script::Signature> = Some($crate::script::Signature {
+             inputs: &[$(stringify!($input)),*],
+             outputs: &[$(stringify!($output)),*],
+         });
+     }
     );
     ($name : ident,
     $ident : expr) =>
     (
      pub(crate) const $name : &'static[u8] = $ident;
+     #[allow(non_snake_case, dead_code)]
+     pub(crate) mod $name {
+         pub const SIGNATURE: Option<$crate::script::Signature> = None;
+     }
     )
 }
 
 instruction!(TRY, b"\x83TRY");
 instruction!(TRY_END, b"\x80\x83TRY"); // internal instruction
+instruction!(SPAWN, b"\x85SPAWN");
+instruction!(SPAWN_DETACHED, b"\x8eSPAWN/DETACHED");
+instruction!(JOIN, b"\x84JOIN");
+instruction!(JOIN_TIMEOUT, b"\x8cJOIN/TIMEOUT");
+instruction!(AFTER, b"\x85AFTER");
+instruction!(EVERY, b"\x85EVERY");
+instruction!(EVERY_AS, b"\x88EVERY/AS");
+instruction!(EVERY_AS_RESTRICTED, b"\x93EVERY/AS/RESTRICTED");
+instruction!(CANCEL, b"\x86CANCEL");
 
 include!("macros.rs");
 
@@ -125,6 +158,10 @@ pub enum Error {
     UnknownInstruction,
     /// An internal scheduler's error to indicate that currently
     /// executed environment should be rescheduled from the same point
+    ///
+    /// The instruction is executed again, so it should leave the stack
+    /// intact. It may [park](park/index.html) the environment to avoid
+    /// being retried until it is ready to proceed.
     Reschedule,
     /// Program Error
     ProgramError(Vec<u8>),
@@ -167,9 +204,40 @@ use std;
 
 pub type EnvId = ProcessUniqueId;
 
+/// Options an environment is scheduled with
+#[derive(Debug, Clone, Default)]
+pub struct EnvOptions {
+    /// Collect a [profile](profiler/index.html) of the execution
+    pub profile: bool,
+    /// Record results of non-deterministic instructions
+    /// (see [recording](recording/index.html))
+    pub record: bool,
+    /// Replay a recording instead of executing
+    /// non-deterministic instructions
+    pub replay: Option<recording::Recording>,
+    /// Share the top-level dictionary scope with other
+    /// environments of the [session](session/index.html)
+    pub session: Option<session::Session>,
+    /// Heap chunk size, scheduler's default if `None`
+    /// (see [`Scheduler::set_heap`](struct.Scheduler.html#method.set_heap))
+    pub heap_size: Option<usize>,
+    /// Instructions the environment is allowed to execute
+    /// (see [capabilities](capabilities/index.html))
+    pub capabilities: capabilities::Capabilities,
+    /// Seed of the random number generator used by `RANDOM/*`
+    /// instructions, for reproducible runs
+    pub random_seed: Option<u64>,
+}
+
 pub trait SchedulerHandle {
     fn schedule_env(&self, env_id: EnvId, program: Vec<u8>, response_sender: Sender<ResponseMessage>,
-                    published_message_callback: Box<messaging::PublishedMessageCallback + Send>);
+                    published_message_callback: Box<messaging::PublishedMessageCallback + Send>) {
+        self.schedule_env_with_options(env_id, program, EnvOptions::default(), response_sender,
+                                       published_message_callback)
+    }
+    fn schedule_env_with_options(&self, env_id: EnvId, program: Vec<u8>, options: EnvOptions,
+                                 response_sender: Sender<ResponseMessage>,
+                                 published_message_callback: Box<messaging::PublishedMessageCallback + Send>);
     fn shutdown(&self);
 }
 
@@ -177,9 +245,11 @@ pub type Sender<T> = mpsc::Sender<T>;
 pub type Receiver<T> = mpsc::Receiver<T>;
 
 impl SchedulerHandle for Sender<RequestMessage> {
-    fn schedule_env(&self, env_id: EnvId, program: Vec<u8>, response_sender: Sender<ResponseMessage>,
-                    published_message_callback: Box<messaging::PublishedMessageCallback + Send>) {
-        let _ = self.send(RequestMessage::ScheduleEnv(env_id, program, response_sender, published_message_callback));
+    fn schedule_env_with_options(&self, env_id: EnvId, program: Vec<u8>, options: EnvOptions,
+                                 response_sender: Sender<ResponseMessage>,
+                                 published_message_callback: Box<messaging::PublishedMessageCallback + Send>) {
+        let _ = self.send(RequestMessage::ScheduleEnv(env_id, program, options, response_sender,
+                                                      published_message_callback));
     }
 
     fn shutdown(&self) {
@@ -190,12 +260,15 @@ impl SchedulerHandle for Sender<RequestMessage> {
 use rand::{thread_rng, Rng};
 
 impl<T : SchedulerHandle> SchedulerHandle for Vec<T> {
-    fn schedule_env(&self, env_id: EnvId, program: Vec<u8>, response_sender: Sender<ResponseMessage>, published_message_callback: Box<messaging::PublishedMessageCallback + Send>) {
+    fn schedule_env_with_options(&self, env_id: EnvId, program: Vec<u8>, options: EnvOptions,
+                                 response_sender: Sender<ResponseMessage>,
+                                 published_message_callback: Box<messaging::PublishedMessageCallback + Send>) {
         let mut rng = thread_rng();
-        let index: usize = rng.gen_range(0, self.len() - 1);
+        let index: usize = rng.gen_range(0, self.len());
         match self.get(index) {
             None => panic!("no available schedulers"),
-            Some(scheduler) => scheduler.schedule_env(env_id, program, response_sender, published_message_callback)
+            Some(scheduler) => scheduler.schedule_env_with_options(env_id, program, options, response_sender,
+                                                                   published_message_callback)
         }
     }
 
@@ -209,9 +282,15 @@ impl<T : SchedulerHandle> SchedulerHandle for Vec<T> {
 /// Communication messages used to talk with the [Scheduler](struct.Scheduler.html) thread.
 pub enum RequestMessage {
     /// Requests scheduling a new environment with a given
-    /// id and a program.
-    ScheduleEnv(EnvId, Vec<u8>, Sender<ResponseMessage>,
+    /// id, a program and options.
+    ScheduleEnv(EnvId, Vec<u8>, EnvOptions, Sender<ResponseMessage>,
                 Box<messaging::PublishedMessageCallback + Send>),
+    /// Sets schedulers that environments spawned
+    /// by this scheduler can be scheduled on
+    Peers(Vec<Sender<RequestMessage>>),
+    /// Requests cancellation of a periodic [timer](timer/index.html)
+    /// with a given handle
+    CancelTimer(Vec<u8>),
     /// Requests Scheduler shutdown
     Shutdown,
 }
@@ -220,11 +299,17 @@ pub enum RequestMessage {
 #[derive(Debug)]
 pub enum ResponseMessage {
     /// Notifies of successful environment termination with
-    /// an id, stack and top of the stack pointer.
-    EnvTerminated(EnvId, Vec<Vec<u8>>, usize),
+    /// an id, stack, top of the stack pointer and a
+    /// [profile](profiler/index.html) and a [recording](recording/index.html),
+    /// if they were requested.
+    EnvTerminated(EnvId, Vec<Vec<u8>>, usize, Option<profiler::Profile>, Option<recording::Recording>),
     /// Notifies of abnormal environment termination with
-    /// an id, error, stack and top of the stack pointer.
-    EnvFailed(EnvId, Error, Option<Vec<Vec<u8>>>, Option<usize>),
+    /// an id, error, stack, top of the stack pointer,
+    /// a [trace](trace/index.html) of the error and a
+    /// [profile](profiler/index.html) and a [recording](recording/index.html),
+    /// if they were requested.
+    EnvFailed(EnvId, Error, Option<Vec<Vec<u8>>>, Option<usize>, Option<trace::Trace>,
+              Option<profiler::Profile>, Option<recording::Recording>),
 }
 
 pub type TrySendError<T> = std::sync::mpsc::TrySendError<T>;
@@ -256,6 +341,28 @@ pub mod mod_msg;
 pub mod mod_uuid;
 #[cfg(feature="mod_string")]
 pub mod mod_string;
+#[cfg(feature="mod_regex")]
+pub mod mod_regex;
+#[cfg(feature="mod_encoding")]
+pub mod mod_encoding;
+#[cfg(feature="mod_random")]
+pub mod mod_random;
+#[cfg(feature="mod_error")]
+pub mod mod_error;
+
+pub mod verifier;
+pub mod trace;
+pub mod debugger;
+pub mod profiler;
+pub mod mailbox;
+pub mod park;
+pub mod spawn;
+pub mod timer;
+pub mod recording;
+pub mod session;
+pub mod capabilities;
+#[cfg(feature="mod_storage")]
+pub mod procedures;
 
 /// Scheduler is a PumpkinScript scheduler and interpreter. This is the
 /// most central part of this module.
@@ -271,12 +378,12 @@ pub mod mod_string;
 /// let (callback, receiver) = mpsc::channel::<ResponseMessage>();
 /// sender.schedule_env(EnvId::new(), script.clone(), callback);
 /// match receiver.recv() {
-///     Ok(ResponseMessage::EnvTerminated(_, stack, stack_size)) => {
+///     Ok(ResponseMessage::EnvTerminated(_, stack, stack_size, _, _)) => {
 ///         sender.shutdown();
 ///         // success
 ///         // ...
 ///     }
-///     Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size)) => {
+///     Ok(ResponseMessage::EnvFailed(_, err, stack, stack_size, trace, _, _)) => {
 ///         sender.shutdown();
 ///         // failure
 ///         // ...
@@ -288,12 +395,21 @@ pub mod mod_string;
 /// ```
 
 use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use std::marker::PhantomData;
 
 pub struct Scheduler<'a, T : Dispatcher<'a>> {
     inbox: Receiver<RequestMessage>,
+    // schedulers to spawn environments on
+    peers: Vec<Sender<RequestMessage>>,
+    timers: timer::Timers,
+    // heap chunks reused by environments
+    heap: envheap::Pool,
     dispatcher: T,
+    // instructions handled by the scheduler itself
+    table: DispatchTable<'a, Scheduler<'a, T>>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -313,22 +429,54 @@ pub const ERROR_UNKNOWN_KEY: &'static [u8] = b"\x01\x07";
 pub const ERROR_NO_TX: &'static [u8] = b"\x01\x08";
 pub const ERROR_DATABASE: &'static [u8] = b"\x01\x09";
 pub const ERROR_NO_VALUE: &'static [u8] = b"\x01\x0A";
+pub const ERROR_REPLAY_DIVERGED: &'static [u8] = b"\x01\x0B";
+pub const ERROR_PERMISSION_DENIED: &'static [u8] = b"\x01\x0C";
+pub const ERROR_DIVISION_BY_ZERO: &'static [u8] = b"\x01\x0D";
+pub const ERROR_OVERFLOW: &'static [u8] = b"\x01\x0E";
+pub const ERROR_UNDERFLOW: &'static [u8] = b"\x01\x0F";
 
 use std::sync::Arc;
 
 use pumpkinscript::{binparser};
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
 
 impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
     /// Creates an instance of Scheduler and a Sender
+    ///
+    /// Until `Peers` are set, spawned environments are scheduled
+    /// on the same scheduler.
     pub fn new(dispatcher: T) -> (Self, Sender<RequestMessage>) {
         let (tx, rx) = mpsc::channel::<RequestMessage>();
         (Scheduler::<'a, T> {
             inbox: rx,
+            peers: vec![tx.clone()],
+            timers: timer::Timers::new(),
+            heap: envheap::Pool::new(env::HEAP_SIZE, 0),
             dispatcher: dispatcher,
+            table: dispatch_table! {
+                TRY => Scheduler::handle_try,
+                TRY_END => Scheduler::handle_try_end,
+                SPAWN => Scheduler::handle_spawn,
+                SPAWN_DETACHED => Scheduler::handle_spawn,
+                JOIN => Scheduler::handle_join,
+                JOIN_TIMEOUT => Scheduler::handle_join,
+                AFTER => Scheduler::handle_timer,
+                EVERY => Scheduler::handle_timer,
+                EVERY_AS => Scheduler::handle_timer,
+                EVERY_AS_RESTRICTED => Scheduler::handle_timer,
+                CANCEL => Scheduler::handle_cancel,
+            },
             phantom: PhantomData,
         }, tx)
     }
 
+    /// Sets the default heap chunk size of environments and the number
+    /// of chunks kept to be reused by subsequent environments (none by default)
+    pub fn set_heap(&mut self, chunk_size: usize, pooled_chunks: usize) {
+        self.heap = envheap::Pool::new(chunk_size, pooled_chunks);
+    }
+
     /// Scheduler. It is supposed to be running in a separate thread
     ///
     /// The scheduler handles all incoming  messages. Once at least one
@@ -338,45 +486,86 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
     ///
     /// Once an environment execution has been terminated, a message will be sent,
     /// depending on the result (`EnvTerminated` or `EnvFailed`)
+    ///
+    /// Programs are [verified](verifier/index.html) before they are scheduled; if
+    /// a stack underflow is certain, `EnvFailed` is sent right away.
+    ///
+    /// Environments paused by a [debugger](debugger/index.html) or
+    /// [parked](park/index.html) are skipped until they are ready to proceed.
+    ///
+    /// Environments scheduled with `EnvOptions::profile` are
+    /// [profiled](profiler/index.html), and the profile is sent along with
+    /// the result. Similarly, environments scheduled with `EnvOptions::record`
+    /// send a [recording](recording/index.html) of non-deterministic
+    /// instructions' results, which can be replayed with `EnvOptions::replay`.
+    ///
+    /// Heaps of terminated environments are kept for reuse by the environments
+    /// scheduled later, if [enabled](#method.set_heap).
+    ///
+    /// When [timers](timer/index.html) are due, their closures are scheduled
+    /// as new environments.
     pub fn run(&mut self) {
         let mut rng = thread_rng();
-        let mut envs: VecDeque<(EnvId, Env<'a>, Sender<ResponseMessage>)> = VecDeque::new();
+        let mut envs: VecDeque<(EnvId, Env<'a>, Sender<ResponseMessage>, EnvOptions)> = VecDeque::new();
         // Flag that indicates that the Env being processed should be removed from
         // the queue
         let mut pop_front = false;
         // Queue length is dynamically updated to avoid scanning the queue
         // every time we need to know its size
         let mut len = 0;
+        // Number of consecutive passes skipped because of paused environments
+        let mut idle = 0;
         loop {
             // Borrow the front of the queue mutably
             match envs.front_mut() {
-                Some(&mut (pid, ref mut env, ref chan)) => {
-                    let program = env.program[env.program.len() - 1];
-                    match self.pass(env, pid) {
-                        Err(Error::Reschedule) => {
-                            env.program.push(program);
-                        }
-                        Err(err) => {
-                            self.dispatcher.done(env, pid);
-                            let stack_size = env.stack().len();
-                            let _ = chan.send(ResponseMessage::EnvFailed(pid,
-                                                                         err,
-                                                                         Some(env.stack_copy()),
-                                                                         Some(stack_size)));
-                            pop_front = true;
-                        }
-                        Ok(()) => {
-                            if env.program.is_empty() ||
-                                (env.program.len() == 1 && env.program[0].len() == 0) {
+                Some(&mut (pid, ref mut env, ref chan, ref options)) => {
+                    if debugger::ready(env) && park::ready(env) {
+                        idle = 0;
+                        match self.pass(env, pid) {
+                            Err(Error::Reschedule) => (),
+                            Err(err) => {
                                 self.dispatcher.done(env, pid);
+                                self.dispatcher.terminated(env, pid);
                                 let stack_size = env.stack().len();
-                                let _ = chan.send(ResponseMessage::EnvTerminated(pid,
-                                                                                 env.stack_copy(),
-                                                                                 stack_size));
+                                let trace = env.trace.take().map(|failure| failure.trace(env));
+                                let profile = Self::profile(env, options);
+                                let recording = env.recording.take();
+                                if let Some(ref session) = options.session {
+                                    session.save(env);
+                                }
+                                let _ = chan.send(ResponseMessage::EnvFailed(pid,
+                                                                             err,
+                                                                             Some(env.stack_copy()),
+                                                                             Some(stack_size),
+                                                                             trace,
+                                                                             profile,
+                                                                             recording));
                                 pop_front = true;
                             }
-                        }
-                    };
+                            Ok(()) => {
+                                if env.program.is_empty() ||
+                                    (env.program.len() == 1 && env.program[0].len() == 0) {
+                                    self.dispatcher.done(env, pid);
+                                    self.dispatcher.terminated(env, pid);
+                                    let stack_size = env.stack().len();
+                                    let profile = Self::profile(env, options);
+                                    let recording = env.recording.take();
+                                    if let Some(ref session) = options.session {
+                                        session.save(env);
+                                    }
+                                    let _ = chan.send(ResponseMessage::EnvTerminated(pid,
+                                                                                     env.stack_copy(),
+                                                                                     stack_size,
+                                                                                     profile,
+                                                                                     recording));
+                                    pop_front = true;
+                                }
+                            }
+                        };
+                    } else {
+                        // paused by the debugger or parked
+                        idle += 1;
+                    }
                 }
                 None => (),
             }
@@ -384,7 +573,9 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
             if pop_front {
                 pop_front = false;
                 len -= 1;
-                let _ = envs.pop_front();
+                if let Some((_, env, _, _)) = envs.pop_front() {
+                    self.heap.recycle(env.into_heap());
+                }
             } else {
                 // Otherwise, if there's more than one Env left,
                 // pick the next Env to schedule randomly
@@ -395,8 +586,24 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
                     envs.swap(0, index);
                 }
             }
+            // Don't spin while all environments are paused or parked
+            if idle > 0 && idle >= len {
+                idle = 0;
+                thread::sleep(Duration::from_millis(1));
+            }
+            self.fire_timers();
             let message = if envs.is_empty() {
-                self.inbox.recv()
+                match self.timers.next_due() {
+                    None => self.inbox.recv(),
+                    Some(due) => {
+                        let now = Instant::now();
+                        let timeout = if due > now { due.duration_since(now) } else { Duration::from_millis(0) };
+                        match self.inbox.recv_timeout(timeout) {
+                            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                            msg => msg.map_err(|_| mpsc::RecvError {}),
+                        }
+                    }
+                }
             } else {
                 let msg = self.inbox.try_recv();
                 if let Err(mpsc::TryRecvError::Empty) = msg {
@@ -407,26 +614,69 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
             match message {
                 Err(err) => panic!("error receiving: {:?}", err),
                 Ok(RequestMessage::Shutdown) => break,
-                Ok(RequestMessage::ScheduleEnv(pid, program, chan, cb)) => {
-                    match Env::new() {
+                Ok(RequestMessage::Peers(peers)) => {
+                    if !peers.is_empty() {
+                        self.peers = peers;
+                    }
+                }
+                Ok(RequestMessage::CancelTimer(handle)) => {
+                    self.timers.cancel(handle.as_slice());
+                }
+                Ok(RequestMessage::ScheduleEnv(pid, program, options, chan, cb)) => {
+                    if let Err(underflow) = verifier::verify(program.as_slice(),
+                                                             |instruction| self.dispatcher.signature(instruction)) {
+                        let trace = vec![trace::Frame {
+                            instruction: Vec::from(underflow.instruction),
+                            word: None,
+                            offset: Some(underflow.offset),
+                        }];
+                        let _ = chan.send(ResponseMessage::EnvFailed(pid, underflow.into(),
+                                                                     Some(vec![]), Some(0),
+                                                                     Some(trace), None, None));
+                        continue;
+                    }
+                    match Env::new_with_heap(self.heap.heap(options.heap_size)) {
                         Ok(mut env) => {
                             env.set_published_message_callback(cb);
                             match env.alloc(program.len()) {
                                 Ok(slice) => {
                                     slice.copy_from_slice(program.as_slice());
+                                    env.origin = slice;
                                     env.program.push(slice);
-                                    self.dispatcher.init(&mut env, pid);
-                                    envs.push_back((pid, env, chan));
+                                    if options.profile {
+                                        env.profiles.push(profiler::Profile::new(0));
+                                    }
+                                    if options.record {
+                                        env.recording = Some(recording::Recording::new());
+                                    }
+                                    env.replay = options.replay.clone();
+                                    env.capabilities = options.capabilities.clone();
+                                    if let Some(seed) = options.random_seed {
+                                        env.seed_rng(seed);
+                                    }
+                                    if let Some(ref session) = options.session {
+                                        if let Err(err) = session.load(&mut env) {
+                                            let _ = chan.send(ResponseMessage::EnvFailed(pid, err, None, None,
+                                                                                         None, None, None));
+                                            continue;
+                                        }
+                                    }
+                                    if let Err(err) = self.dispatcher.init(&mut env, pid) {
+                                        let _ = chan.send(ResponseMessage::EnvFailed(pid, err, None, None,
+                                                                                     None, None, None));
+                                        continue;
+                                    }
+                                    envs.push_back((pid, env, chan, options));
                                     len += 1;
                                 }
                                 Err(err) => {
                                     let _ =
-                                        chan.send(ResponseMessage::EnvFailed(pid, err, None, None));
+                                        chan.send(ResponseMessage::EnvFailed(pid, err, None, None, None, None, None));
                                 }
                             }
                         }
                         Err(err) => {
-                            let _ = chan.send(ResponseMessage::EnvFailed(pid, err, None, None));
+                            let _ = chan.send(ResponseMessage::EnvFailed(pid, err, None, None, None, None, None));
                         }
                     }
                 }
@@ -434,6 +684,52 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
         }
     }
 
+    /// Schedules environments for the timers that are due
+    fn fire_timers(&mut self) {
+        if self.timers.next_due().is_none() {
+            return;
+        }
+        for (program, capabilities, seed, callback) in self.timers.due(Instant::now()) {
+            let (sender, _) = mpsc::channel();
+            let options = EnvOptions {
+                capabilities: capabilities,
+                random_seed: seed,
+                ..Default::default()
+            };
+            self.peers.schedule_env_with_options(EnvId::new(), program, options, sender, callback);
+        }
+    }
+
+    /// Handles an instruction, recording or replaying its result
+    /// if it's non-deterministic
+    #[inline]
+    fn handle_recorded(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
+        if env.recording.is_none() && env.replay.is_none() {
+            return self.handle(env, instruction, pid);
+        }
+        match recording::arity(instruction) {
+            None => self.handle(env, instruction, pid),
+            Some(arity) => {
+                if env.replay.is_some() {
+                    return recording::replay(env, instruction, arity);
+                }
+                let depth = env.stack().len();
+                let result = self.handle(env, instruction, pid);
+                recording::record(env, instruction, arity, depth, &result);
+                result
+            }
+        }
+    }
+
+    /// Takes the profile requested when the environment was scheduled
+    fn profile(env: &mut Env<'a>, options: &EnvOptions) -> Option<profiler::Profile> {
+        if options.profile && !env.profiles.is_empty() {
+            Some(env.profiles.remove(0))
+        } else {
+            None
+        }
+    }
+
     #[allow(unused_mut)]
     fn pass(&mut self, env: &mut Env<'a>, pid: EnvId) -> PassResult<'a> {
         if env.program.len() == 0 {
@@ -460,11 +756,34 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
                 return Ok(());
             }
 
-            match self.handle(env, instruction, pid) {
+            let started = profiler::start(env);
+            let result = self.handle_recorded(env, instruction, pid);
+            if let Some(started) = started {
+                let rescheduled = match result {
+                    Err(Error::Reschedule) => true,
+                    _ => false,
+                };
+                profiler::record(env, instruction, started, rescheduled);
+            }
+
+            match result {
                 Ok(()) => Ok(()),
-                Err(Error::UnknownInstruction) => handle_error!(env, error_unknown_instruction!(instruction)),
-                Err(err @ Error::ProgramError(_)) => handle_error!(env, err),
-                Err(err) => Err(err),
+                Err(Error::Reschedule) => {
+                    // execute the instruction again
+                    if rest.len() > 0 {
+                        env.program.pop();
+                    }
+                    env.program.push(program);
+                    Err(Error::Reschedule)
+                }
+                Err(err) => {
+                    env.trace = Some(trace::Failure::new(env, instruction));
+                    match err {
+                        Error::UnknownInstruction => handle_error!(env, error_unknown_instruction!(instruction)),
+                        err @ Error::ProgramError(_) => handle_error!(env, err),
+                        err => Err(err),
+                    }
+                }
             }
 
         } else {
@@ -485,6 +804,14 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
                 let def = env.dictionary.get(instruction).unwrap();
                 env.program.push(def);
             }
+            profiler::word(env, instruction);
+            Ok(())
+        } else if env.procedures.contains_key(instruction) {
+            {
+                let def = env.procedures.get(instruction).unwrap();
+                env.program.push(def);
+            }
+            profiler::word(env, instruction);
             Ok(())
         } else {
             Err(Error::UnknownInstruction)
@@ -509,7 +836,15 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
             }
         }
 
+        if !found {
+            if let Some(def) = env.procedures.get(instruction) {
+                env.program.push(def);
+                found = true;
+            }
+        }
+
         if found {
+            profiler::word(env, instruction);
             Ok(())
         } else {
             Err(Error::UnknownInstruction)
@@ -535,6 +870,7 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
         return_unless_instructions_equal!(instruction, TRY_END);
         env.tracking_errors -= 1;
         if env.aborting_try.is_empty() {
+            env.trace = None;
             env.push(_EMPTY);
             Ok(())
         } else if let Some(Error::ProgramError(err)) = env.aborting_try.pop() {
@@ -547,15 +883,213 @@ impl<'a, T: Dispatcher<'a>> Scheduler<'a, T> {
             Ok(())
         }
     }
+
+    #[inline]
+    fn handle_spawn(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+        use serde_cbor;
+        let detached = instruction == SPAWN_DETACHED;
+        if instruction != SPAWN && !detached {
+            return Err(Error::UnknownInstruction);
+        }
+        let closure = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let id = EnvId::new();
+        let (sender, receiver) = mpsc::channel();
+        // messages the child has subscribed to are delivered to the same callback
+        let callback = match env.published_message_callback() {
+            Some(callback) => callback,
+            None => {
+                let (callback, _) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+                Box::new(callback)
+            }
+        };
+        // spawned environments can't do more than their parent,
+        // and are seeded if their parent was
+        let options = EnvOptions {
+            capabilities: env.capabilities.clone(),
+            random_seed: env.child_seed(),
+            ..Default::default()
+        };
+        self.peers.schedule_env_with_options(id, Vec::from(closure), options, sender, callback);
+        if !detached {
+            let bytes = serde_cbor::to_vec(&id).unwrap();
+            env.children.add(bytes.clone(), receiver);
+            let slice = alloc_and_write!(bytes.as_slice(), env);
+            env.push(slice);
+        }
+        Ok(())
+    }
+
+    /// Takes the result of a child, parks the environment
+    /// if the child hasn't finished yet
+    fn join(&mut self, env: &mut Env<'a>, id: &'a [u8], deadline: Option<Instant>)
+            -> Result<Option<ResponseMessage>, Error> {
+        if let Some(response) = env.children.take(id) {
+            env.parked = None;
+            return Ok(Some(response));
+        }
+        if !env.children.is_child(id) {
+            env.parked = None;
+            return Err(error_invalid_value!(id));
+        }
+        let park = park::Park {
+            topic: None,
+            child: Some(Vec::from(id)),
+            deadline: deadline,
+        };
+        if park.expired() {
+            env.parked = None;
+            Ok(None)
+        } else {
+            env.parked = Some(park);
+            Err(Error::Reschedule)
+        }
+    }
+
+    #[inline]
+    fn handle_join(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+        let timeout = if instruction == JOIN_TIMEOUT {
+            Some(env.pop().ok_or_else(|| error_empty_stack!())?)
+        } else if instruction == JOIN {
+            None
+        } else {
+            return Err(Error::UnknownInstruction);
+        };
+        let id = env.pop().ok_or_else(|| error_empty_stack!())?;
+        // the deadline is set on the first attempt
+        let deadline = match timeout {
+            None => None,
+            Some(timeout) => {
+                match env.parked {
+                    Some(park::Park { deadline: Some(deadline), .. }) => Some(deadline),
+                    _ => {
+                        let millis = BigUint::from_bytes_be(timeout).to_u64()
+                            .ok_or_else(|| error_invalid_value!(timeout))?;
+                        Some(Instant::now() + Duration::from_millis(millis))
+                    }
+                }
+            }
+        };
+        match self.join(env, id, deadline) {
+            Ok(Some(response)) => {
+                let (stack, error) = spawn::result(response);
+                if timeout.is_some() {
+                    let mut closure = Vec::new();
+                    write_size_header!(stack, closure);
+                    closure.extend_from_slice(&stack);
+                    write_size_header!(error, closure);
+                    closure.extend_from_slice(&error);
+                    let slice = alloc_and_write!(closure.as_slice(), env);
+                    env.push(slice);
+                } else {
+                    let slice = alloc_and_write!(stack.as_slice(), env);
+                    env.push(slice);
+                    let slice = alloc_and_write!(error.as_slice(), env);
+                    env.push(slice);
+                }
+                Ok(())
+            }
+            Ok(None) => {
+                env.push(_EMPTY);
+                Ok(())
+            }
+            Err(Error::Reschedule) => {
+                env.push(id);
+                if let Some(timeout) = timeout {
+                    env.push(timeout);
+                }
+                Err(Error::Reschedule)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    #[inline]
+    fn handle_timer(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+        use serde_cbor;
+        // timers can't do more than the environment that registered them
+        let mut allowed = env.capabilities.clone();
+        if instruction == EVERY_AS_RESTRICTED {
+            let encoded = env.pop().ok_or_else(|| error_empty_stack!())?;
+            let restrictions = capabilities::Capabilities::decode(encoded)
+                .ok_or_else(|| error_invalid_value!(encoded))?;
+            allowed.restrict(&restrictions);
+        }
+        let handle = if instruction == EVERY_AS || instruction == EVERY_AS_RESTRICTED {
+            Some(Vec::from(env.pop().ok_or_else(|| error_empty_stack!())?))
+        } else if instruction == EVERY {
+            Some(serde_cbor::to_vec(&EnvId::new()).unwrap())
+        } else if instruction == AFTER {
+            None
+        } else {
+            return Err(Error::UnknownInstruction);
+        };
+        let delay = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let closure = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let millis = BigUint::from_bytes_be(delay).to_u64()
+            .ok_or_else(|| error_invalid_value!(delay))?;
+        let interval = Duration::from_millis(millis);
+        let period = if handle.is_some() {
+            // a periodic timer without an interval would never let go
+            if millis == 0 {
+                return Err(error_invalid_value!(delay));
+            }
+            Some(interval)
+        } else {
+            None
+        };
+        // messages published by the timer are delivered to the same callback
+        let callback = match env.published_message_callback() {
+            Some(callback) => callback,
+            None => {
+                let (callback, _) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+                Box::new(callback)
+            }
+        };
+        if instruction == EVERY {
+            let bytes = handle.clone().unwrap();
+            let slice = alloc_and_write!(bytes.as_slice(), env);
+            env.push(slice);
+        }
+        self.timers.add(timer::Timer {
+            handle: handle,
+            program: Vec::from(closure),
+            due: Instant::now() + interval,
+            period: period,
+            capabilities: allowed,
+            seed: env.child_seed(),
+            callback: callback,
+        });
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_cancel(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, CANCEL);
+        let handle = env.pop().ok_or_else(|| error_empty_stack!())?;
+        // the timer might have been registered on any of the schedulers
+        if !self.timers.cancel(handle) {
+            for peer in self.peers.iter() {
+                let _ = peer.send(RequestMessage::CancelTimer(Vec::from(handle)));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a, T: Dispatcher<'a>> Dispatcher<'a> for Scheduler<'a, T> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_try(env, instruction, pid)
-            .if_unhandled_try(|| self.handle_try_end(env, instruction, pid))
-            .if_unhandled_try(|| self.dispatcher.handle(env, instruction, pid))
-            .if_unhandled_try(|| self.handle_dictionary(env, instruction, pid))
-            .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        match self.table.get(instruction) {
+            Some(handler) => {
+                if !env.capabilities.permits(instruction) {
+                    return Err(error_permission_denied!(instruction));
+                }
+                handler(self, env, instruction, pid)
+            }
+            None => {
+                self.dispatcher.handle(env, instruction, pid)
+                    .if_unhandled_try(|| self.handle_dictionary(env, instruction, pid))
+            }
+        }
     }
 }
 
@@ -598,6 +1132,16 @@ mod tests {
         });
     }
 
+    #[test]
+    fn stack_underflow() {
+        eval!("1 DUP DROP DROP DROP", env, result, {
+            assert_error!(result, "[\"Empty stack\" [] 4]");
+        });
+        eval!("1 [DROP DROP] EVAL", env, result, {
+            assert_error!(result, "[\"Empty stack\" [] 4]");
+        });
+    }
+
     #[test]
     fn nothing() {
         eval!("", env, {
@@ -665,6 +1209,12 @@ mod tests {
                     b);
     }
 
+    #[bench]
+    fn dispatch(b: &mut Bencher) {
+        // instructions of modules routed last and of the scheduler itself
+        bench_eval!("[\"Hello\" HASH/SHA256 DROP 0x00 HEX/ENCODE DROP [1] TRY 2DROP] 1000 TIMES", b);
+    }
+
     #[bench]
     fn ackermann_stack(b: &mut Bencher) {
         // HT @5HT
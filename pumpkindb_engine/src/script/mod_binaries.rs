@@ -5,9 +5,10 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, STACK_TRUE, STACK_FALSE, TryInstruction};
+            offset_by_size, STACK_TRUE, STACK_FALSE, DispatchTable};
 
 use std::marker::PhantomData;
+use std::ops::{BitAnd, BitOr, BitXor};
 
 use num_bigint::BigUint;
 use num_traits::ToPrimitive;
@@ -20,8 +21,31 @@ instruction!(CONCAT, (a, b => c), b"\x86CONCAT");
 instruction!(SLICE, (a, b, c => d), b"\x85SLICE");
 instruction!(PAD, (a, b, c => d), b"\x83PAD");
 
+// Bitwise operations
+instruction!(BINARY_AND, (a, b => c), b"\x8aBINARY/AND");
+instruction!(BINARY_OR, (a, b => c), b"\x89BINARY/OR");
+instruction!(BINARY_XOR, (a, b => c), b"\x8aBINARY/XOR");
+instruction!(BINARY_NOT, (a => b), b"\x8aBINARY/NOT");
+instruction!(BINARY_SHL, (a, b => c), b"\x8aBINARY/SHL");
+instruction!(BINARY_SHR, (a, b => c), b"\x8aBINARY/SHR");
+instruction!(BINARY_POPCOUNT, (a => b), b"\x8fBINARY/POPCOUNT");
+instruction!(BINARY_BITQ, (a, b => c), b"\x8bBINARY/BIT?");
+instruction!(BINARY_SET_BIT, (a, b => c), b"\x8eBINARY/SET-BIT");
+instruction!(BINARY_CLEAR_BIT, (a, b => c), b"\x90BINARY/CLEAR-BIT");
+
+/// Largest binary `BINARY/SET-BIT` can grow a binary to
+const MAX_BITMAP_SIZE: usize = 65536;
+
+// Decodes a bit index or a shift amount
+macro_rules! bit_index {
+    ($value: expr) => {
+        BigUint::from_bytes_be($value).to_u64()
+            .map(|i| i as usize).ok_or(error_invalid_value!($value))?
+    };
+}
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -29,21 +53,35 @@ builtins!("mod_binaries.psc");
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_builtins(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_ltp(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_gtp(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_equal(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_concat(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_slice(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_pad(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_length(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let mut table = dispatch_table! {
+            LTQ => Handler::handle_ltp,
+            GTQ => Handler::handle_gtp,
+            EQUALQ => Handler::handle_equal,
+            CONCAT => Handler::handle_concat,
+            SLICE => Handler::handle_slice,
+            PAD => Handler::handle_pad,
+            LENGTH => Handler::handle_length,
+            BINARY_AND => Handler::handle_bitwise,
+            BINARY_OR => Handler::handle_bitwise,
+            BINARY_XOR => Handler::handle_bitwise,
+            BINARY_NOT => Handler::handle_binary_not,
+            BINARY_SHL => Handler::handle_binary_shift,
+            BINARY_SHR => Handler::handle_binary_shift,
+            BINARY_POPCOUNT => Handler::handle_binary_popcount,
+            BINARY_BITQ => Handler::handle_binary_bitq,
+            BINARY_SET_BIT => Handler::handle_binary_set_bit,
+            BINARY_CLEAR_BIT => Handler::handle_binary_set_bit,
+        };
+        for instruction in BUILTINS.keys() {
+            table.insert(*instruction, Handler::handle_builtins);
+        }
+        Handler { phantom: PhantomData, table: table }
     }
 
     handle_builtins!();
@@ -200,4 +238,168 @@ impl<'a> Handler<'a> {
 
         Ok(())
     }
+
+    #[inline]
+    fn handle_bitwise(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        let op: fn(u8, u8) -> u8 = match instruction {
+            BINARY_AND => u8::bitand,
+            BINARY_OR => u8::bitor,
+            BINARY_XOR => u8::bitxor,
+            _ => return Err(Error::UnknownInstruction),
+        };
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        // the shorter binary is padded with zeros on the right
+        let len = ::std::cmp::max(a.len(), b.len());
+        let slice = alloc_slice!(len, env);
+        for i in 0..len {
+            slice[i] = op(a.get(i).cloned().unwrap_or(0), b.get(i).cloned().unwrap_or(0));
+        }
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_binary_not(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, BINARY_NOT);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let slice = alloc_slice!(a.len(), env);
+        for i in 0..a.len() {
+            slice[i] = !a[i];
+        }
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_binary_shift(&mut self,
+                           env: &mut Env<'a>,
+                           instruction: &'a [u8],
+                           _: EnvId)
+                           -> PassResult<'a> {
+        let left = match instruction {
+            BINARY_SHL => true,
+            BINARY_SHR => false,
+            _ => return Err(Error::UnknownInstruction),
+        };
+        let n = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        // shifting by the length or more leaves no bits set
+        let shift = ::std::cmp::min(bit_index!(n), a.len() * 8);
+        let (bytes, bits) = (shift / 8, (shift % 8) as u32);
+
+        // the length is retained, bits shifted out are dropped
+        let slice = alloc_slice!(a.len(), env);
+        for i in 0..a.len() {
+            let byte = |j: usize| if j < a.len() { a[j] } else { 0 };
+            slice[i] = if left {
+                let j = i + bytes;
+                if bits == 0 {
+                    byte(j)
+                } else {
+                    byte(j) << bits | byte(j + 1) >> (8 - bits)
+                }
+            } else if i < bytes {
+                0
+            } else if bits == 0 {
+                a[i - bytes]
+            } else {
+                let high = if i > bytes { a[i - bytes - 1] << (8 - bits) } else { 0 };
+                high | a[i - bytes] >> bits
+            };
+        }
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_binary_popcount(&mut self,
+                              env: &mut Env<'a>,
+                              instruction: &'a [u8],
+                              _: EnvId)
+                              -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, BINARY_POPCOUNT);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let count = a.iter().fold(0u64, |count, byte| count + byte.count_ones() as u64);
+        let count_bytes = BigUint::from(count).to_bytes_be();
+
+        let slice = alloc_and_write!(count_bytes.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_binary_bitq(&mut self,
+                          env: &mut Env<'a>,
+                          instruction: &'a [u8],
+                          _: EnvId)
+                          -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, BINARY_BITQ);
+        let index = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let i = bit_index!(index);
+        match a.get(i / 8) {
+            Some(byte) if byte & (0x80 >> (i % 8)) != 0 => env.push(STACK_TRUE),
+            _ => env.push(STACK_FALSE),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_binary_set_bit(&mut self,
+                             env: &mut Env<'a>,
+                             instruction: &'a [u8],
+                             _: EnvId)
+                             -> PassResult<'a> {
+        let set = match instruction {
+            BINARY_SET_BIT => true,
+            BINARY_CLEAR_BIT => false,
+            _ => return Err(Error::UnknownInstruction),
+        };
+        let index = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let i = bit_index!(index);
+        // setting a bit past the end grows the binary, clearing it is a no-op;
+        // only growth is limited, binaries that are already larger are fine
+        let grows = set && i / 8 + 1 > a.len();
+        if grows && i / 8 + 1 > MAX_BITMAP_SIZE {
+            return Err(error_invalid_value!(index));
+        }
+        let len = if grows { i / 8 + 1 } else { a.len() };
+
+        let slice = alloc_slice!(len, env);
+        slice[..a.len()].copy_from_slice(a);
+        for byte in slice[a.len()..].iter_mut() {
+            *byte = 0;
+        }
+        if i / 8 < len {
+            if set {
+                slice[i / 8] |= 0x80 >> (i % 8);
+            } else {
+                slice[i / 8] &= !(0x80 >> (i % 8));
+            }
+        }
+        env.push(slice);
+
+        Ok(())
+    }
 }
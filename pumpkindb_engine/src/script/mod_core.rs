@@ -7,10 +7,11 @@
 use pumpkinscript::{parse_bin, binparser};
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, STACK_TRUE, STACK_FALSE, TryInstruction};
+            offset_by_size, STACK_TRUE, STACK_FALSE, DispatchTable};
 
 use super::mod_stack::{PUSH, POP};
 use super::mod_queue::{TO_BQ, FROM_BQ};
+use super::profiler::Profile;
 use std::marker::PhantomData;
 
 use pumpkinscript;
@@ -25,12 +26,19 @@ instruction!(SCOPE_END, b"\x80\x8BEVAL/SCOPED"); // internal instruction
 instruction!(DOWHILE, b"\x87DOWHILE");
 instruction!(TIMES, b"\x85TIMES");
 instruction!(EVAL, b"\x84EVAL");
-instruction!(EVAL_VALIDP, b"\x8BEVAL/VALID?");
-instruction!(SET, b"\x83SET");
-instruction!(DEF, b"\x83DEF");
+instruction!(EVAL_VALIDP, (a => b), b"\x8BEVAL/VALID?");
+instruction!(SET, (a, b => ), b"\x83SET");
+instruction!(DEF, (a, b => ), b"\x83DEF");
 instruction!(IF, b"\x82IF"); // for reference, implemented in builtins
 instruction!(IFELSE, b"\x86IFELSE");
 
+// Category: Sessions
+instruction!(SESSION_RESET, b"\x8DSESSION/RESET");
+
+// Category: Profiling
+instruction!(PROFILE, b"\x87PROFILE");
+instruction!(PROFILE_END, b"\x80\x87PROFILE"); // internal instruction
+
 // Category: Logical operations
 instruction!(NOT, (a => c), b"\x83NOT");
 instruction!(AND, (a, b => c), b"\x83AND");
@@ -42,32 +50,49 @@ instruction!(FEATUREQ, (a => b), b"\x88FEATURE?");
 builtins!("mod_core.psc");
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
+    fn done(&mut self, env: &mut Env<'a>, _: EnvId) {
+        // drop profiles started within a TRY that has been left
+        let depth = env.tracking_errors;
+        env.profiles.retain(|profile| profile.depth <= depth);
+    }
+
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_builtins(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_dowhile(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_times(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_scope_end(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_eval(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_eval_validp(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_eval_scoped(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_set(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_def(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_not(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_and(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_or(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_ifelse(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_featurep(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
+        let mut table = dispatch_table! {
+            DOWHILE => Handler::handle_dowhile,
+            TIMES => Handler::handle_times,
+            #[cfg(feature = "scoped_dictionary")]
+            SCOPE_END => Handler::handle_scope_end,
+            EVAL => Handler::handle_eval,
+            EVAL_VALIDP => Handler::handle_eval_validp,
+            #[cfg(feature = "scoped_dictionary")]
+            EVAL_SCOPED => Handler::handle_eval_scoped,
+            SET => Handler::handle_set,
+            DEF => Handler::handle_def,
+            SESSION_RESET => Handler::handle_session_reset,
+            NOT => Handler::handle_not,
+            AND => Handler::handle_and,
+            OR => Handler::handle_or,
+            IFELSE => Handler::handle_ifelse,
+            FEATUREQ => Handler::handle_featurep,
+            PROFILE => Handler::handle_profile,
+            PROFILE_END => Handler::handle_profile_end,
+        };
+        for instruction in BUILTINS.keys() {
+            table.insert(*instruction, Handler::handle_builtins);
+        }
         Handler {
+            table: table,
             phantom: PhantomData,
         }
     }
@@ -171,12 +196,6 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
-    #[inline]
-    #[cfg(not(feature = "scoped_dictionary"))]
-    fn handle_eval_scoped(&mut self, _: &Env<'a>, _: &'a [u8], _: EnvId) -> PassResult<'a> {
-        Err(Error::UnknownInstruction)
-    }
-
 
     #[inline]
     #[cfg(feature = "scoped_dictionary")]
@@ -191,12 +210,6 @@ impl<'a> Handler<'a> {
     }
 
 
-    #[inline]
-    #[cfg(not(feature = "scoped_dictionary"))]
-    fn handle_scope_end(&mut self, _: &mut Env<'a>, _: &'a [u8], _: EnvId) -> PassResult<'a> {
-        Err(Error::UnknownInstruction)
-    }
-
     #[inline]
     fn handle_eval(&mut self,
                    env: &mut Env<'a>,
@@ -209,6 +222,37 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
+    #[inline]
+    fn handle_profile(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, PROFILE);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let profile = Profile::new(env.tracking_errors);
+        env.profiles.push(profile);
+        env.program.push(PROFILE_END);
+        env.program.push(a);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_profile_end(&mut self,
+                          env: &mut Env<'a>,
+                          instruction: &'a [u8],
+                          _: EnvId)
+                          -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, PROFILE_END);
+        let encoded = match env.profiles.pop() {
+            Some(profile) => profile.encode(),
+            None => vec![],
+        };
+        let slice = alloc_and_write!(encoded.as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
     #[inline]
     fn handle_eval_validp(&mut self,
                           env: &mut Env<'a>,
@@ -314,6 +358,16 @@ impl<'a> Handler<'a> {
         }
     }
 
+    #[inline]
+    fn handle_session_reset(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, SESSION_RESET);
+        #[cfg(feature = "scoped_dictionary")]
+        env.dictionary[0].clear();
+        #[cfg(not(feature = "scoped_dictionary"))]
+        env.dictionary.clear();
+        Ok(())
+    }
+
     #[inline]
     fn handle_def(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
         return_unless_instructions_equal!(instruction, DEF);
@@ -0,0 +1,251 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//!
+//! # Binary-to-text encodings
+//!
+//! This module encodes binaries as hexadecimal, base64 and base32
+//! text (RFC 4648) and decodes them back.
+//!
+
+instruction!(HEX_ENCODE, (a => b), b"\x8aHEX/ENCODE");
+instruction!(HEX_DECODE, (a => b), b"\x8aHEX/DECODE");
+instruction!(BASE64_ENCODE, (a => b), b"\x8dBASE64/ENCODE");
+instruction!(BASE64_DECODE, (a => b), b"\x8dBASE64/DECODE");
+instruction!(BASE64_URL_ENCODE, (a => b), b"\x91BASE64/URL/ENCODE");
+instruction!(BASE64_URL_DECODE, (a => b), b"\x91BASE64/URL/DECODE");
+instruction!(BASE32_ENCODE, (a => b), b"\x8dBASE32/ENCODE");
+instruction!(BASE32_DECODE, (a => b), b"\x8dBASE32/DECODE");
+
+use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
+            offset_by_size, DispatchTable};
+
+use std::marker::PhantomData;
+
+/// Encoding of a number of bits per character
+struct Encoding {
+    alphabet: &'static [u8],
+    bits: usize,
+    /// Length of a padded group of characters, `0` if the encoding isn't padded
+    group: usize,
+    /// Whether letters are decoded regardless of their case
+    case_insensitive: bool,
+}
+
+const HEX: Encoding = Encoding {
+    alphabet: b"0123456789abcdef",
+    bits: 4,
+    group: 0,
+    case_insensitive: true,
+};
+
+const BASE64: Encoding = Encoding {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+    bits: 6,
+    group: 4,
+    case_insensitive: false,
+};
+
+const BASE64_URL: Encoding = Encoding {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+    bits: 6,
+    group: 0,
+    case_insensitive: false,
+};
+
+const BASE32: Encoding = Encoding {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567",
+    bits: 5,
+    group: 8,
+    case_insensitive: true,
+};
+
+const PADDING: u8 = b'=';
+
+#[inline]
+fn lowercase(c: u8) -> u8 {
+    if c >= b'A' && c <= b'Z' { c + (b'a' - b'A') } else { c }
+}
+
+impl Encoding {
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity((data.len() * 8 + self.bits - 1) / self.bits);
+        let mask = (1 << self.bits) - 1;
+        let (mut buffer, mut buffered) = (0usize, 0);
+        for byte in data {
+            buffer = buffer << 8 | *byte as usize;
+            buffered += 8;
+            while buffered >= self.bits {
+                buffered -= self.bits;
+                text.push(self.alphabet[buffer >> buffered & mask]);
+            }
+            buffer &= (1 << buffered) - 1;
+        }
+        if buffered > 0 {
+            text.push(self.alphabet[buffer << (self.bits - buffered) & mask]);
+        }
+        if self.group > 0 {
+            while text.len() % self.group != 0 {
+                text.push(PADDING);
+            }
+        }
+        text
+    }
+
+    /// Decodes canonical text: unused bits of the last character must be zeros
+    /// and padding, if any, must be complete. Padding is optional.
+    fn decode(&self, text: &[u8]) -> Option<Vec<u8>> {
+        let len = text.iter().position(|c| *c == PADDING).unwrap_or(text.len());
+        if len < text.len() {
+            let padding = &text[len..];
+            if self.group == 0 || padding.iter().any(|c| *c != PADDING) ||
+               padding.len() != (self.group - len % self.group) % self.group {
+                return None;
+            }
+        }
+        let mut data = Vec::with_capacity(len * self.bits / 8);
+        let (mut buffer, mut buffered) = (0usize, 0);
+        for c in &text[..len] {
+            let value = if self.case_insensitive {
+                self.alphabet.iter().position(|a| lowercase(*a) == lowercase(*c))
+            } else {
+                self.alphabet.iter().position(|a| a == c)
+            };
+            let value = match value {
+                Some(value) => value,
+                None => return None,
+            };
+            buffer = buffer << self.bits | value;
+            buffered += self.bits;
+            if buffered >= 8 {
+                buffered -= 8;
+                data.push((buffer >> buffered) as u8);
+                buffer &= (1 << buffered) - 1;
+            }
+        }
+        if buffered >= self.bits || buffer != 0 {
+            return None;
+        }
+        Some(data)
+    }
+}
+
+fn encoding(instruction: &[u8]) -> Option<(&'static Encoding, bool)> {
+    match instruction {
+        HEX_ENCODE => Some((&HEX, true)),
+        HEX_DECODE => Some((&HEX, false)),
+        BASE64_ENCODE => Some((&BASE64, true)),
+        BASE64_DECODE => Some((&BASE64, false)),
+        BASE64_URL_ENCODE => Some((&BASE64_URL, true)),
+        BASE64_URL_DECODE => Some((&BASE64_URL, false)),
+        BASE32_ENCODE => Some((&BASE32, true)),
+        BASE32_DECODE => Some((&BASE32, false)),
+        _ => None,
+    }
+}
+
+pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Dispatcher<'a> for Handler<'a> {
+    fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
+        dispatch!(self, env, instruction, pid)
+    }
+}
+
+impl<'a> Handler<'a> {
+    pub fn new() -> Self {
+        let table = dispatch_table! {
+            HEX_ENCODE => Handler::handle_encoding,
+            HEX_DECODE => Handler::handle_encoding,
+            BASE64_ENCODE => Handler::handle_encoding,
+            BASE64_DECODE => Handler::handle_encoding,
+            BASE64_URL_ENCODE => Handler::handle_encoding,
+            BASE64_URL_DECODE => Handler::handle_encoding,
+            BASE32_ENCODE => Handler::handle_encoding,
+            BASE32_DECODE => Handler::handle_encoding,
+        };
+        Handler { phantom: PhantomData, table: table }
+    }
+
+    #[inline]
+    pub fn handle_encoding(&mut self,
+                           env: &mut Env<'a>,
+                           instruction: &'a [u8],
+                           _: EnvId)
+                           -> PassResult<'a> {
+        let (encoding, encode) = encoding(instruction).ok_or(Error::UnknownInstruction)?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let result = if encode {
+            encoding.encode(a)
+        } else {
+            encoding.decode(a).ok_or_else(|| error_invalid_value!(a))?
+        };
+        let slice = alloc_and_write!(result.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{HEX, BASE64, BASE64_URL, BASE32};
+
+    #[test]
+    fn rfc4648_vectors() {
+        let vectors: &[(&[u8], &[u8], &[u8], &[u8])] = &[
+            (b"", b"", b"", b""),
+            (b"f", b"66", b"Zg==", b"MY======"),
+            (b"fo", b"666f", b"Zm8=", b"MZXQ===="),
+            (b"foo", b"666f6f", b"Zm9v", b"MZXW6==="),
+            (b"foob", b"666f6f62", b"Zm9vYg==", b"MZXW6YQ="),
+            (b"fooba", b"666f6f6261", b"Zm9vYmE=", b"MZXW6YTB"),
+            (b"foobar", b"666f6f626172", b"Zm9vYmFy", b"MZXW6YTBOI======"),
+        ];
+        for &(data, hex, base64, base32) in vectors {
+            assert_eq!(HEX.encode(data), hex);
+            assert_eq!(HEX.decode(hex).unwrap(), data);
+            assert_eq!(BASE64.encode(data), base64);
+            assert_eq!(BASE64.decode(base64).unwrap(), data);
+            assert_eq!(BASE32.encode(data), base32);
+            assert_eq!(BASE32.decode(base32).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn decode_variants() {
+        assert_eq!(HEX.decode(b"DEADbeef").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(BASE64.decode(b"Zg").unwrap(), b"f");
+        assert_eq!(BASE64_URL.encode(&[0xfb, 0xff]), b"-_8");
+        assert_eq!(BASE64_URL.decode(b"-_8").unwrap(), vec![0xfb, 0xff]);
+        assert_eq!(BASE32.decode(b"mzxw6===").unwrap(), b"foo");
+        assert_eq!(BASE32.decode(b"MZXW6").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(HEX.decode(b"abc").is_none());
+        assert!(HEX.decode(b"zz").is_none());
+        // incomplete or excessive padding
+        assert!(BASE64.decode(b"Zg=").is_none());
+        assert!(BASE64.decode(b"Zm9v====").is_none());
+        assert!(BASE64.decode(b"Z=g=").is_none());
+        // unused bits are not zeros
+        assert!(BASE64.decode(b"Zh==").is_none());
+        assert!(BASE64.decode(b"Z").is_none());
+        assert!(BASE64_URL.decode(b"+/8").is_none());
+        assert!(BASE64_URL.decode(b"Zg==").is_none());
+        assert!(BASE32.decode(b"MZX").is_none());
+        assert!(BASE32.decode(b"MZ======").is_none());
+        assert!(BASE32.decode(b"MY=====").is_none());
+        assert!(BASE32.decode(b"MZXW6YTB========").is_none());
+    }
+
+}
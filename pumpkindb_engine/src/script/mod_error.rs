@@ -0,0 +1,179 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Errors
+//!
+//! Raising errors from PumpkinScript and inspecting errors caught by `TRY`.
+//!
+//! An error is a closure of three items: `[message details code]`. Built-in
+//! errors use single-byte codes (`0x02`, `0x03`, ...), user-raised errors
+//! must use codes that are at least two bytes long, so the two never clash.
+//!
+//! The [trace](../trace/index.html) of the error caught by the last `TRY`
+//! is available through `ERROR/TRACE`.
+
+instruction!(RAISE, (a, b, c => ), b"\x85RAISE");
+instruction!(RERAISE, (a => ), b"\x87RERAISE");
+instruction!(ERROR_CODE, (a => b), b"\x8aERROR/CODE");
+instruction!(ERROR_MESSAGE, (a => b), b"\x8dERROR/MESSAGE");
+instruction!(ERROR_DETAILS, (a => b), b"\x8dERROR/DETAILS");
+instruction!(ERROR_TRACE, ( => a), b"\x8bERROR/TRACE");
+
+use pumpkinscript::{binparser, ParseResult};
+use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
+            offset_by_size, DispatchTable};
+use super::trace;
+
+use std::marker::PhantomData;
+
+/// Minimum length of a user error code
+pub const USER_CODE_MIN_LENGTH: usize = 2;
+
+pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Dispatcher<'a> for Handler<'a> {
+    fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
+        dispatch!(self, env, instruction, pid)
+    }
+}
+
+/// Splits an error into its message, details and code
+fn parse_error(error: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    if let ParseResult::Done(rest, message) = binparser::data(error) {
+        if let ParseResult::Done(rest, details) = binparser::data(rest) {
+            if let ParseResult::Done(rest, code) = binparser::data(rest) {
+                if rest.len() == 0 {
+                    return Some((&message[offset_by_size(message.len())..],
+                                 &details[offset_by_size(details.len())..],
+                                 &code[offset_by_size(code.len())..]));
+                }
+            }
+        }
+    }
+    None
+}
+
+macro_rules! error_component {
+    ($env: expr, $index: tt) => {{
+        let error = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        match parse_error(error) {
+            Some(components) => {
+                $env.push(components.$index);
+                Ok(())
+            }
+            None => Err(error_invalid_value!(error)),
+        }
+    }};
+}
+
+impl<'a> Handler<'a> {
+    pub fn new() -> Self {
+        let table = dispatch_table! {
+            RAISE => Handler::handle_raise,
+            RERAISE => Handler::handle_reraise,
+            ERROR_CODE => Handler::handle_error_code,
+            ERROR_MESSAGE => Handler::handle_error_message,
+            ERROR_DETAILS => Handler::handle_error_details,
+            ERROR_TRACE => Handler::handle_error_trace,
+        };
+        Handler { phantom: PhantomData, table: table }
+    }
+
+    #[inline]
+    fn handle_raise(&mut self,
+                    env: &mut Env<'a>,
+                    instruction: &'a [u8],
+                    _: EnvId)
+                    -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RAISE);
+        let code = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let details = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let message = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        if code.len() < USER_CODE_MIN_LENGTH {
+            return Err(error_invalid_value!(code));
+        }
+
+        let mut error = Vec::new();
+        write_size_header!(message, error);
+        error.extend_from_slice(message);
+        write_size_header!(details, error);
+        error.extend_from_slice(details);
+        write_size_header!(code, error);
+        error.extend_from_slice(code);
+
+        Err(Error::ProgramError(error))
+    }
+
+    #[inline]
+    fn handle_reraise(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RERAISE);
+        let error = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        // `[]` is what `TRY` pushes when there was no error
+        if error.len() == 0 {
+            return Ok(());
+        }
+
+        match parse_error(error) {
+            Some(_) => Err(Error::ProgramError(Vec::from(error))),
+            None => Err(error_invalid_value!(error)),
+        }
+    }
+
+    #[inline]
+    fn handle_error_code(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, ERROR_CODE);
+        error_component!(env, 2)
+    }
+
+    #[inline]
+    fn handle_error_message(&mut self,
+                            env: &mut Env<'a>,
+                            instruction: &'a [u8],
+                            _: EnvId)
+                            -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, ERROR_MESSAGE);
+        error_component!(env, 0)
+    }
+
+    #[inline]
+    fn handle_error_details(&mut self,
+                            env: &mut Env<'a>,
+                            instruction: &'a [u8],
+                            _: EnvId)
+                            -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, ERROR_DETAILS);
+        error_component!(env, 1)
+    }
+
+    #[inline]
+    fn handle_error_trace(&mut self,
+                          env: &mut Env<'a>,
+                          instruction: &'a [u8],
+                          _: EnvId)
+                          -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, ERROR_TRACE);
+        let encoded = match env.trace {
+            Some(ref failure) => trace::encode(&failure.trace(env)),
+            None => vec![],
+        };
+        let slice = alloc_and_write!(encoded.as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+}
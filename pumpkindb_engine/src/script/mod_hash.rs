@@ -9,13 +9,13 @@
 //! This module handles hashing data
 //!
 
-instruction!(HASH_SHA1, b"\x89HASH/SHA1");
-instruction!(HASH_SHA224, b"\x8BHASH/SHA224");
-instruction!(HASH_SHA256, b"\x8BHASH/SHA256");
-instruction!(HASH_SHA384, b"\x8BHASH/SHA384");
-instruction!(HASH_SHA512, b"\x8BHASH/SHA512");
-instruction!(HASH_SHA512_224, b"\x8FHASH/SHA512-224");
-instruction!(HASH_SHA512_256, b"\x8FHASH/SHA512-256");
+instruction!(HASH_SHA1, (a => b), b"\x89HASH/SHA1");
+instruction!(HASH_SHA224, (a => b), b"\x8BHASH/SHA224");
+instruction!(HASH_SHA256, (a => b), b"\x8BHASH/SHA256");
+instruction!(HASH_SHA384, (a => b), b"\x8BHASH/SHA384");
+instruction!(HASH_SHA512, (a => b), b"\x8BHASH/SHA512");
+instruction!(HASH_SHA512_224, (a => b), b"\x8FHASH/SHA512-224");
+instruction!(HASH_SHA512_256, (a => b), b"\x8FHASH/SHA512-256");
 
 // `Sha224`, which is the 32-bit `Sha256` algorithm with the result truncated to 224 bits.
 // `Sha256`, which is the 32-bit `Sha256` algorithm.
@@ -26,7 +26,7 @@ instruction!(HASH_SHA512_256, b"\x8FHASH/SHA512-256");
 //
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, offset_by_size,
-            TryInstruction};
+            DispatchTable};
 use crypto::digest::Digest;
 use crypto::sha1::Sha1;
 use crypto::sha2::*;
@@ -34,6 +34,7 @@ use crypto::sha2::*;
 use std::marker::PhantomData;
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -55,20 +56,22 @@ macro_rules! hash_instruction {
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_hash_sha1(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_hash_sha224(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hash_sha256(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hash_sha384(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hash_sha512(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hash_sha512_224(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hash_sha512_256(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let table = dispatch_table! {
+            HASH_SHA1 => Handler::handle_hash_sha1,
+            HASH_SHA224 => Handler::handle_hash_sha224,
+            HASH_SHA256 => Handler::handle_hash_sha256,
+            HASH_SHA384 => Handler::handle_hash_sha384,
+            HASH_SHA512 => Handler::handle_hash_sha512,
+            HASH_SHA512_224 => Handler::handle_hash_sha512_224,
+            HASH_SHA512_256 => Handler::handle_hash_sha512_256,
+        };
+        Handler { phantom: PhantomData, table: table }
     }
 
     hash_instruction!(handle_hash_sha1, HASH_SHA1, Sha1, 20);
@@ -10,13 +10,14 @@
 //! Logical Clock timestamps (https://www.cse.buffalo.edu/tech-reports/2014-04.pdf)
 //!
 
-instruction!(HLC, b"\x83HLC");
-instruction!(HLC_LC, b"\x86HLC/LC");
-instruction!(HLC_TICK, b"\x88HLC/TICK");
-instruction!(HLC_OBSERVE, b"\x8BHLC/OBSERVE");
+instruction!(HLC, ( => a), b"\x83HLC");
+instruction!(HLC_LC, (a => b), b"\x86HLC/LC");
+instruction!(HLC_TICK, (a => b), b"\x88HLC/TICK");
+instruction!(HLC_OBSERVE, (a => b), b"\x8BHLC/OBSERVE");
+instruction!(HLC_TO_STRING, (a => b), b"\x8CHLC/->STRING");
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, TryInstruction};
+            offset_by_size, DispatchTable};
 use timestamp;
 
 use hlc;
@@ -25,31 +26,47 @@ use byteorder::{BigEndian, WriteBytesExt};
 use std::sync::Arc;
 use super::super::nvmem::NonVolatileMemory;
 
+/// Formats a timestamp as `epoch:time+count`, where `time` is
+/// the wall clock time in nanoseconds since the Unix epoch
+pub fn to_string(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != 16 {
+        return None;
+    }
+    let mut reader = bytes;
+    hlc::Timestamp::<hlc::WallT>::read_bytes(&mut reader).ok()
+        .map(|t| format!("{}:{}+{}", t.epoch, t.time.as_u64(), t.count))
+}
+
 pub struct Handler<'a, N> where N : NonVolatileMemory {
+    pub(crate) table: DispatchTable<'a, Handler<'a, N>>,
     phantom: PhantomData<&'a ()>,
     timestamp: Arc<timestamp::Timestamp<N>>,
 }
 
 impl<'a, N> Dispatcher<'a> for Handler<'a, N> where N : NonVolatileMemory {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_hlc(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_hlc_lc(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hlc_tick(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_hlc_observe(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a, N> Handler<'a, N> where N : NonVolatileMemory {
     pub fn new(timestamp_state: Arc<timestamp::Timestamp<N>>) -> Self {
+        let table = dispatch_table! {
+            HLC => Handler::handle_hlc,
+            HLC_LC => Handler::handle_hlc_lc,
+            HLC_TICK => Handler::handle_hlc_tick,
+            HLC_OBSERVE => Handler::handle_hlc_observe,
+            HLC_TO_STRING => Handler::handle_hlc_to_string,
+        };
         Handler {
+            table: table,
             phantom: PhantomData,
             timestamp: timestamp_state,
         }
     }
 
     #[inline]
-    pub fn handle_hlc(&self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
+    pub fn handle_hlc(&mut self, env: &mut Env<'a>, instruction: &'a [u8], _: EnvId) -> PassResult<'a> {
         return_unless_instructions_equal!(instruction, HLC);
         let now = self.timestamp.hlc();
         let slice = alloc_slice!(16, env);
@@ -147,4 +164,20 @@ impl<'a, N> Handler<'a, N> where N : NonVolatileMemory {
             Err(error_empty_stack!())
         }
     }
+
+    #[inline]
+    pub fn handle_hlc_to_string(&mut self,
+                                env: &mut Env<'a>,
+                                instruction: &'a [u8],
+                                _: EnvId)
+                                -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, HLC_TO_STRING);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let s = to_string(a).ok_or_else(|| error_invalid_value!(a))?;
+        let slice = alloc_and_write!(s.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
 }
@@ -11,26 +11,27 @@
 
 use core::convert::From;
 
-instruction!(JSONQ, b"\x85JSON?");
-instruction!(JSON_OBJECTQ, b"\x8CJSON/OBJECT?");
-instruction!(JSON_STRINGQ, b"\x8CJSON/STRING?");
-instruction!(JSON_NUMBERQ, b"\x8CJSON/NUMBER?");
-instruction!(JSON_BOOLEANQ, b"\x8DJSON/BOOLEAN?");
-instruction!(JSON_ARRAYQ, b"\x8BJSON/ARRAY?");
-instruction!(JSON_NULLQ, b"\x8AJSON/NULL?");
-instruction!(JSON_GET, b"\x88JSON/GET");
-instruction!(JSON_SET, b"\x88JSON/SET");
-instruction!(JSON_HASQ, b"\x89JSON/HAS?");
-instruction!(JSON_STRING_TO, b"\x8dJSON/STRING->");
-instruction!(JSON_TO_STRING, b"\x8dJSON/->STRING");
+instruction!(JSONQ, (a => b), b"\x85JSON?");
+instruction!(JSON_OBJECTQ, (a => b), b"\x8CJSON/OBJECT?");
+instruction!(JSON_STRINGQ, (a => b), b"\x8CJSON/STRING?");
+instruction!(JSON_NUMBERQ, (a => b), b"\x8CJSON/NUMBER?");
+instruction!(JSON_BOOLEANQ, (a => b), b"\x8DJSON/BOOLEAN?");
+instruction!(JSON_ARRAYQ, (a => b), b"\x8BJSON/ARRAY?");
+instruction!(JSON_NULLQ, (a => b), b"\x8AJSON/NULL?");
+instruction!(JSON_GET, (a, b => c), b"\x88JSON/GET");
+instruction!(JSON_SET, (a, b, c => d), b"\x88JSON/SET");
+instruction!(JSON_HASQ, (a, b => c), b"\x89JSON/HAS?");
+instruction!(JSON_STRING_TO, (a => b), b"\x8dJSON/STRING->");
+instruction!(JSON_TO_STRING, (a => b), b"\x8dJSON/->STRING");
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, STACK_TRUE, STACK_FALSE, TryInstruction};
+            offset_by_size, STACK_TRUE, STACK_FALSE, DispatchTable};
 use serde_json as json;
 
 use std::marker::PhantomData;
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -63,26 +64,30 @@ builtins!("mod_json.psc");
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_builtins(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_jsonq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_objectq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_stringq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_numberq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_booleanq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_arrayq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_nullq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_get(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_hasq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_set(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_string_to(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_json_to_string(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let mut table = dispatch_table! {
+            JSONQ => Handler::handle_jsonq,
+            JSON_OBJECTQ => Handler::handle_json_objectq,
+            JSON_STRINGQ => Handler::handle_json_stringq,
+            JSON_NUMBERQ => Handler::handle_json_numberq,
+            JSON_BOOLEANQ => Handler::handle_json_booleanq,
+            JSON_ARRAYQ => Handler::handle_json_arrayq,
+            JSON_NULLQ => Handler::handle_json_nullq,
+            JSON_GET => Handler::handle_json_get,
+            JSON_HASQ => Handler::handle_json_hasq,
+            JSON_SET => Handler::handle_json_set,
+            JSON_STRING_TO => Handler::handle_json_string_to,
+            JSON_TO_STRING => Handler::handle_json_to_string,
+        };
+        for instruction in BUILTINS.keys() {
+            table.insert(*instruction, Handler::handle_builtins);
+        }
+        Handler { phantom: PhantomData, table: table }
     }
 
     handle_builtins!();
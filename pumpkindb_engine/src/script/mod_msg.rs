@@ -4,34 +4,70 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, offset_by_size,
-            TryInstruction};
+use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
+            offset_by_size, DispatchTable, _EMPTY};
+use super::debugger::Debugger;
+use super::park::Park;
 use super::super::messaging;
 
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+
 use std::marker::PhantomData;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 instruction!(PUBLISH, (a, b => ), b"\x87PUBLISH");
-instruction!(SUBSCRIBE, (a => ), b"\x89SUBSCRIBE");
+instruction!(SUBSCRIBE, (a => b), b"\x89SUBSCRIBE");
 instruction!(UNSUBSCRIBE, (a => ), b"\x8bUNSUBSCRIBE");
+instruction!(RECEIVE, (a => b), b"\x87RECEIVE");
+instruction!(RECEIVE_TIMEOUT, (a, b => c), b"\x8fRECEIVE/TIMEOUT");
+instruction!(DEBUG, b"\x85DEBUG");
+instruction!(DEBUG_END, b"\x80\x85DEBUG"); // internal instruction
 
 pub struct Handler<'a, P: messaging::Publisher, S: messaging::Subscriber> {
+    pub(crate) table: DispatchTable<'a, Handler<'a, P, S>>,
     publisher: P,
     subscriber: S,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a, P: messaging::Publisher, S: messaging::Subscriber> Dispatcher<'a> for Handler<'a, P, S> {
+    fn done(&mut self, env: &mut Env<'a>, _: EnvId) {
+        // the environment has left the TRY it was in when the debugger was attached
+        let detach = match env.debugger {
+            Some(ref debugger) => env.tracking_errors < debugger.depth,
+            None => false,
+        };
+        if detach {
+            self.detach(env);
+        }
+    }
+
+    fn terminated(&mut self, env: &mut Env<'a>, _: EnvId) {
+        // the closure has failed with an uncaught error, or DEBUG_END
+        // hasn't been reached for some other reason
+        self.detach(env);
+    }
+
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_publish(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_subscribe(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_unsubscribe(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a, P: messaging::Publisher, S: messaging::Subscriber> Handler<'a, P, S> {
     pub fn new(publisher: P, subscriber: S) -> Self {
+        let table = dispatch_table! {
+            PUBLISH => Handler::handle_publish,
+            SUBSCRIBE => Handler::handle_subscribe,
+            UNSUBSCRIBE => Handler::handle_unsubscribe,
+            RECEIVE => Handler::handle_receive,
+            RECEIVE_TIMEOUT => Handler::handle_receive_timeout,
+            DEBUG => Handler::handle_debug,
+            DEBUG_END => Handler::handle_debug_end,
+        };
         Handler {
+            table: table,
             publisher: publisher,
             subscriber: subscriber,
             phantom: PhantomData,
@@ -63,14 +99,13 @@ impl<'a, P: messaging::Publisher, S: messaging::Subscriber> Handler<'a, P, S> {
 
         let topic = env.pop().ok_or_else(|| error_empty_stack!())?;
 
-        match env.published_message_callback() {
-            None => (),
-            Some(cb) => {
-                let ident = self.subscriber.subscribe(topic, cb);
-                let slice = alloc_and_write!(&ident, env);
-                env.push(slice);
-            }
-        }
+        // messages are delivered to the connection and to the mailbox
+        let callback = env.published_message_callback();
+        let cb = env.mailbox.callback(callback);
+        let ident = self.subscriber.subscribe(topic, cb);
+        env.mailbox.subscribed(&ident, topic);
+        let slice = alloc_and_write!(&ident, env);
+        env.push(slice);
 
         Ok(())
     }
@@ -86,10 +121,147 @@ impl<'a, P: messaging::Publisher, S: messaging::Subscriber> Handler<'a, P, S> {
         let identifier = env.pop().ok_or_else(|| error_empty_stack!())?;
 
         self.subscriber.unsubscribe(identifier);
+        env.mailbox.unsubscribed(identifier);
+
+        Ok(())
+    }
+
+    /// Takes a message on the topic from the mailbox, parks
+    /// the environment if there's none
+    fn receive(&mut self, env: &mut Env<'a>, topic: &'a [u8], deadline: Option<Instant>)
+               -> Result<Option<Vec<u8>>, Error> {
+        if let Some(message) = env.mailbox.take(topic) {
+            env.parked = None;
+            return Ok(Some(message));
+        }
+        if !env.mailbox.is_subscribed(topic) {
+            env.parked = None;
+            return Err(error_invalid_value!(topic));
+        }
+        let park = Park {
+            topic: Some(Vec::from(topic)),
+            child: None,
+            deadline: deadline,
+        };
+        if park.expired() {
+            env.parked = None;
+            Ok(None)
+        } else {
+            env.parked = Some(park);
+            Err(Error::Reschedule)
+        }
+    }
+
+    #[inline]
+    fn handle_receive(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RECEIVE);
+
+        let topic = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        match self.receive(env, topic, None) {
+            Ok(Some(message)) => {
+                let slice = alloc_and_write!(&message, env);
+                env.push(slice);
+                Ok(())
+            }
+            Ok(None) => unreachable!(),
+            Err(Error::Reschedule) => {
+                env.push(topic);
+                Err(Error::Reschedule)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    #[inline]
+    fn handle_receive_timeout(&mut self,
+                              env: &mut Env<'a>,
+                              instruction: &'a [u8],
+                              _: EnvId)
+                              -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RECEIVE_TIMEOUT);
+
+        let timeout = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let topic = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        // the deadline is set on the first attempt
+        let deadline = match env.parked {
+            Some(Park { deadline: Some(deadline), .. }) => deadline,
+            _ => {
+                let millis = BigUint::from_bytes_be(timeout).to_u64()
+                    .ok_or_else(|| error_invalid_value!(timeout))?;
+                Instant::now() + Duration::from_millis(millis)
+            }
+        };
+
+        match self.receive(env, topic, Some(deadline)) {
+            Ok(Some(message)) => {
+                let mut closure = Vec::new();
+                write_size_header!(message, closure);
+                closure.extend_from_slice(&message);
+                let slice = alloc_and_write!(&closure, env);
+                env.push(slice);
+                Ok(())
+            }
+            Ok(None) => {
+                env.push(_EMPTY);
+                Ok(())
+            }
+            Err(Error::Reschedule) => {
+                env.push(topic);
+                env.push(timeout);
+                Err(Error::Reschedule)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn detach(&mut self, env: &mut Env<'a>) {
+        if let Some(debugger) = env.debugger.take() {
+            self.subscriber.unsubscribe(&debugger.subscription);
+        }
+    }
+
+    #[inline]
+    fn handle_debug(&mut self,
+                    env: &mut Env<'a>,
+                    instruction: &'a [u8],
+                    _: EnvId)
+                    -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, DEBUG);
+
+        let topic = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let closure = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        if env.debugger.is_some() {
+            return Err(error_invalid_value!(topic));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let subscription = self.subscriber.subscribe(topic, Box::new(sender));
+        env.debugger = Some(Debugger::new(topic, subscription, env.tracking_errors, receiver));
+
+        env.program.push(DEBUG_END);
+        env.program.push(closure);
 
         Ok(())
     }
 
+    #[inline]
+    fn handle_debug_end(&mut self,
+                        env: &mut Env<'a>,
+                        instruction: &'a [u8],
+                        _: EnvId)
+                        -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, DEBUG_END);
+        self.detach(env);
+        Ok(())
+    }
+
 }
 
 #[cfg(test)]
@@ -152,5 +324,37 @@ mod tests {
 
     }
 
+    #[test]
+    fn receive() {
+        let (sender0, receiver0) = mpsc::channel();
+
+        eval!("\"Topic\" SUBSCRIBE DROP \"Hello\" \"Topic\" PUBLISH \"Topic\" RECEIVE",
+              env,
+              result,
+              sender0.clone(), receiver0,
+              {
+                  assert!(!result.is_err());
+                  assert_eq!(env.pop().unwrap(), b"Hello");
+                  // the message is delivered to the connection as well
+                  let result = receiver0.recv_timeout(Duration::from_secs(1)).unwrap();
+                  assert_eq!(result, (Vec::from("Topic"), Vec::from("Hello")));
+              });
+
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let (sender0, receiver0) = mpsc::channel();
+
+        eval!("\"Topic\" SUBSCRIBE DROP \"Topic\" 10 RECEIVE/TIMEOUT",
+              env,
+              result,
+              sender0.clone(), receiver0,
+              {
+                  assert!(!result.is_err());
+                  assert_eq!(env.pop().unwrap(), b"");
+              });
+
+    }
 
 }
@@ -5,16 +5,19 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, STACK_TRUE, STACK_FALSE, TryInstruction};
+            ERROR_DIVISION_BY_ZERO, ERROR_OVERFLOW, ERROR_UNDERFLOW,
+            offset_by_size, STACK_TRUE, STACK_FALSE, DispatchTable};
 
-use ::pumpkinscript::{Packable, Unpackable};
+use ::pumpkinscript::{Packable, Unpackable, Decimal};
+use ::pumpkinscript::decimal;
 
 use std::marker::PhantomData;
 
 use byteorder::{BigEndian, WriteBytesExt, ReadBytesExt};
 
-use num_bigint::{BigUint, BigInt};
-use core::ops::{Add, Sub};
+use num_bigint::{BigUint, BigInt, Sign};
+use num_traits::{Zero, FromPrimitive, ToPrimitive};
+use core::ops::{Add, Sub, Mul, Div, Rem, Neg};
 
 // Category: arithmetics
 instruction!(UINT_ADD, (a, b => c), b"\x88UINT/ADD");
@@ -42,15 +45,299 @@ instruction!(UINT64_SUB, (a, b => c), b"\x8aUINT64/SUB");
 instruction!(INT64_ADD, (a, b => c), b"\x89INT64/ADD");
 instruction!(INT64_SUB, (a, b => c), b"\x89INT64/SUB");
 
-instruction!(F32_ADD, b"\x87F32/ADD");
-instruction!(F32_SUB, b"\x87F32/SUB");
-instruction!(F64_ADD, b"\x87F64/ADD");
-instruction!(F64_SUB, b"\x87F64/SUB");
+instruction!(F32_ADD, (a, b => c), b"\x87F32/ADD");
+instruction!(F32_SUB, (a, b => c), b"\x87F32/SUB");
+instruction!(F64_ADD, (a, b => c), b"\x87F64/ADD");
+instruction!(F64_SUB, (a, b => c), b"\x87F64/SUB");
+
+instruction!(UINT_MUL, (a, b => c), b"\x88UINT/MUL");
+instruction!(UINT_DIV, (a, b => c), b"\x88UINT/DIV");
+instruction!(UINT_REM, (a, b => c), b"\x88UINT/REM");
+instruction!(INT_MUL, (a, b => c), b"\x87INT/MUL");
+instruction!(INT_DIV, (a, b => c), b"\x87INT/DIV");
+instruction!(INT_REM, (a, b => c), b"\x87INT/REM");
+instruction!(INT_NEG, (a => b), b"\x87INT/NEG");
+
+instruction!(UINT8_MUL, (a, b => c), b"\x89UINT8/MUL");
+instruction!(UINT8_DIV, (a, b => c), b"\x89UINT8/DIV");
+instruction!(UINT8_REM, (a, b => c), b"\x89UINT8/REM");
+instruction!(INT8_MUL, (a, b => c), b"\x88INT8/MUL");
+instruction!(INT8_DIV, (a, b => c), b"\x88INT8/DIV");
+instruction!(INT8_REM, (a, b => c), b"\x88INT8/REM");
+instruction!(INT8_NEG, (a => b), b"\x88INT8/NEG");
+
+instruction!(UINT16_MUL, (a, b => c), b"\x8aUINT16/MUL");
+instruction!(UINT16_DIV, (a, b => c), b"\x8aUINT16/DIV");
+instruction!(UINT16_REM, (a, b => c), b"\x8aUINT16/REM");
+instruction!(INT16_MUL, (a, b => c), b"\x89INT16/MUL");
+instruction!(INT16_DIV, (a, b => c), b"\x89INT16/DIV");
+instruction!(INT16_REM, (a, b => c), b"\x89INT16/REM");
+instruction!(INT16_NEG, (a => b), b"\x89INT16/NEG");
+
+instruction!(UINT32_MUL, (a, b => c), b"\x8aUINT32/MUL");
+instruction!(UINT32_DIV, (a, b => c), b"\x8aUINT32/DIV");
+instruction!(UINT32_REM, (a, b => c), b"\x8aUINT32/REM");
+instruction!(INT32_MUL, (a, b => c), b"\x89INT32/MUL");
+instruction!(INT32_DIV, (a, b => c), b"\x89INT32/DIV");
+instruction!(INT32_REM, (a, b => c), b"\x89INT32/REM");
+instruction!(INT32_NEG, (a => b), b"\x89INT32/NEG");
+
+instruction!(UINT64_MUL, (a, b => c), b"\x8aUINT64/MUL");
+instruction!(UINT64_DIV, (a, b => c), b"\x8aUINT64/DIV");
+instruction!(UINT64_REM, (a, b => c), b"\x8aUINT64/REM");
+instruction!(INT64_MUL, (a, b => c), b"\x89INT64/MUL");
+instruction!(INT64_DIV, (a, b => c), b"\x89INT64/DIV");
+instruction!(INT64_REM, (a, b => c), b"\x89INT64/REM");
+instruction!(INT64_NEG, (a => b), b"\x89INT64/NEG");
+
+instruction!(F32_MUL, (a, b => c), b"\x87F32/MUL");
+instruction!(F32_DIV, (a, b => c), b"\x87F32/DIV");
+instruction!(F32_REM, (a, b => c), b"\x87F32/REM");
+instruction!(F32_NEG, (a => b), b"\x87F32/NEG");
+instruction!(F64_MUL, (a, b => c), b"\x87F64/MUL");
+instruction!(F64_DIV, (a, b => c), b"\x87F64/DIV");
+instruction!(F64_REM, (a, b => c), b"\x87F64/REM");
+instruction!(F64_NEG, (a => b), b"\x87F64/NEG");
+
+instruction!(F32_FLOOR, (a => b), b"\x89F32/FLOOR");
+instruction!(F32_CEIL, (a => b), b"\x88F32/CEIL");
+instruction!(F32_ROUND, (a => b), b"\x89F32/ROUND");
+instruction!(F32_TRUNC, (a => b), b"\x89F32/TRUNC");
+instruction!(F32_ABS, (a => b), b"\x87F32/ABS");
+instruction!(F32_SQRT, (a => b), b"\x88F32/SQRT");
+instruction!(F32_MIN, (a, b => c), b"\x87F32/MIN");
+instruction!(F32_MAX, (a, b => c), b"\x87F32/MAX");
+instruction!(F32_POW, (a, b => c), b"\x87F32/POW");
+instruction!(F64_FLOOR, (a => b), b"\x89F64/FLOOR");
+instruction!(F64_CEIL, (a => b), b"\x88F64/CEIL");
+instruction!(F64_ROUND, (a => b), b"\x89F64/ROUND");
+instruction!(F64_TRUNC, (a => b), b"\x89F64/TRUNC");
+instruction!(F64_ABS, (a => b), b"\x87F64/ABS");
+instruction!(F64_SQRT, (a => b), b"\x88F64/SQRT");
+instruction!(F64_MIN, (a, b => c), b"\x87F64/MIN");
+instruction!(F64_MAX, (a, b => c), b"\x87F64/MAX");
+instruction!(F64_POW, (a, b => c), b"\x87F64/POW");
+
+instruction!(DECIMAL_ADD, (a, b => c), b"\x8bDECIMAL/ADD");
+instruction!(DECIMAL_SUB, (a, b => c), b"\x8bDECIMAL/SUB");
+instruction!(DECIMAL_MUL, (a, b => c), b"\x8bDECIMAL/MUL");
+instruction!(DECIMAL_DIV_FLOOR, (a, b => c), b"\x91DECIMAL/DIV/FLOOR");
+instruction!(DECIMAL_DIV_CEIL, (a, b => c), b"\x90DECIMAL/DIV/CEIL");
+instruction!(DECIMAL_DIV_ROUND, (a, b => c), b"\x91DECIMAL/DIV/ROUND");
+instruction!(DECIMAL_DIV_EVEN, (a, b => c), b"\x90DECIMAL/DIV/EVEN");
+instruction!(DECIMAL_DIV_TRUNC, (a, b => c), b"\x91DECIMAL/DIV/TRUNC");
+instruction!(DECIMAL_RESCALE_FLOOR, (a, b => c), b"\x95DECIMAL/RESCALE/FLOOR");
+instruction!(DECIMAL_RESCALE_CEIL, (a, b => c), b"\x94DECIMAL/RESCALE/CEIL");
+instruction!(DECIMAL_RESCALE_ROUND, (a, b => c), b"\x95DECIMAL/RESCALE/ROUND");
+instruction!(DECIMAL_RESCALE_EVEN, (a, b => c), b"\x94DECIMAL/RESCALE/EVEN");
+instruction!(DECIMAL_RESCALE_TRUNC, (a, b => c), b"\x95DECIMAL/RESCALE/TRUNC");
 
 // Casting
 instruction!(INT_TO_UINT, (a => b), b"\x89INT->UINT");
 instruction!(UINT_TO_INT, (a => b), b"\x89UINT->INT");
 
+instruction!(UINT_TO_UINT8, (a => b), b"\x8bUINT->UINT8");
+instruction!(UINT_TO_UINT16, (a => b), b"\x8cUINT->UINT16");
+instruction!(UINT_TO_UINT32, (a => b), b"\x8cUINT->UINT32");
+instruction!(UINT_TO_UINT64, (a => b), b"\x8cUINT->UINT64");
+instruction!(UINT_TO_INT8, (a => b), b"\x8aUINT->INT8");
+instruction!(UINT_TO_INT16, (a => b), b"\x8bUINT->INT16");
+instruction!(UINT_TO_INT32, (a => b), b"\x8bUINT->INT32");
+instruction!(UINT_TO_INT64, (a => b), b"\x8bUINT->INT64");
+instruction!(UINT_TO_F32, (a => b), b"\x89UINT->F32");
+instruction!(UINT_TO_F64, (a => b), b"\x89UINT->F64");
+
+instruction!(INT_TO_UINT8, (a => b), b"\x8aINT->UINT8");
+instruction!(INT_TO_UINT16, (a => b), b"\x8bINT->UINT16");
+instruction!(INT_TO_UINT32, (a => b), b"\x8bINT->UINT32");
+instruction!(INT_TO_UINT64, (a => b), b"\x8bINT->UINT64");
+instruction!(INT_TO_INT8, (a => b), b"\x89INT->INT8");
+instruction!(INT_TO_INT16, (a => b), b"\x8aINT->INT16");
+instruction!(INT_TO_INT32, (a => b), b"\x8aINT->INT32");
+instruction!(INT_TO_INT64, (a => b), b"\x8aINT->INT64");
+instruction!(INT_TO_F32, (a => b), b"\x88INT->F32");
+instruction!(INT_TO_F64, (a => b), b"\x88INT->F64");
+
+instruction!(UINT8_TO_UINT, (a => b), b"\x8bUINT8->UINT");
+instruction!(UINT8_TO_INT, (a => b), b"\x8aUINT8->INT");
+instruction!(UINT8_TO_UINT16, (a => b), b"\x8dUINT8->UINT16");
+instruction!(UINT8_TO_UINT32, (a => b), b"\x8dUINT8->UINT32");
+instruction!(UINT8_TO_UINT64, (a => b), b"\x8dUINT8->UINT64");
+instruction!(UINT8_TO_INT8, (a => b), b"\x8bUINT8->INT8");
+instruction!(UINT8_TO_INT16, (a => b), b"\x8cUINT8->INT16");
+instruction!(UINT8_TO_INT32, (a => b), b"\x8cUINT8->INT32");
+instruction!(UINT8_TO_INT64, (a => b), b"\x8cUINT8->INT64");
+instruction!(UINT8_TO_F32, (a => b), b"\x8aUINT8->F32");
+instruction!(UINT8_TO_F64, (a => b), b"\x8aUINT8->F64");
+
+instruction!(UINT16_TO_UINT, (a => b), b"\x8cUINT16->UINT");
+instruction!(UINT16_TO_INT, (a => b), b"\x8bUINT16->INT");
+instruction!(UINT16_TO_UINT8, (a => b), b"\x8dUINT16->UINT8");
+instruction!(UINT16_TO_UINT32, (a => b), b"\x8eUINT16->UINT32");
+instruction!(UINT16_TO_UINT64, (a => b), b"\x8eUINT16->UINT64");
+instruction!(UINT16_TO_INT8, (a => b), b"\x8cUINT16->INT8");
+instruction!(UINT16_TO_INT16, (a => b), b"\x8dUINT16->INT16");
+instruction!(UINT16_TO_INT32, (a => b), b"\x8dUINT16->INT32");
+instruction!(UINT16_TO_INT64, (a => b), b"\x8dUINT16->INT64");
+instruction!(UINT16_TO_F32, (a => b), b"\x8bUINT16->F32");
+instruction!(UINT16_TO_F64, (a => b), b"\x8bUINT16->F64");
+
+instruction!(UINT32_TO_UINT, (a => b), b"\x8cUINT32->UINT");
+instruction!(UINT32_TO_INT, (a => b), b"\x8bUINT32->INT");
+instruction!(UINT32_TO_UINT8, (a => b), b"\x8dUINT32->UINT8");
+instruction!(UINT32_TO_UINT16, (a => b), b"\x8eUINT32->UINT16");
+instruction!(UINT32_TO_UINT64, (a => b), b"\x8eUINT32->UINT64");
+instruction!(UINT32_TO_INT8, (a => b), b"\x8cUINT32->INT8");
+instruction!(UINT32_TO_INT16, (a => b), b"\x8dUINT32->INT16");
+instruction!(UINT32_TO_INT32, (a => b), b"\x8dUINT32->INT32");
+instruction!(UINT32_TO_INT64, (a => b), b"\x8dUINT32->INT64");
+instruction!(UINT32_TO_F32, (a => b), b"\x8bUINT32->F32");
+instruction!(UINT32_TO_F64, (a => b), b"\x8bUINT32->F64");
+
+instruction!(UINT64_TO_UINT, (a => b), b"\x8cUINT64->UINT");
+instruction!(UINT64_TO_INT, (a => b), b"\x8bUINT64->INT");
+instruction!(UINT64_TO_UINT8, (a => b), b"\x8dUINT64->UINT8");
+instruction!(UINT64_TO_UINT16, (a => b), b"\x8eUINT64->UINT16");
+instruction!(UINT64_TO_UINT32, (a => b), b"\x8eUINT64->UINT32");
+instruction!(UINT64_TO_INT8, (a => b), b"\x8cUINT64->INT8");
+instruction!(UINT64_TO_INT16, (a => b), b"\x8dUINT64->INT16");
+instruction!(UINT64_TO_INT32, (a => b), b"\x8dUINT64->INT32");
+instruction!(UINT64_TO_INT64, (a => b), b"\x8dUINT64->INT64");
+instruction!(UINT64_TO_F32, (a => b), b"\x8bUINT64->F32");
+instruction!(UINT64_TO_F64, (a => b), b"\x8bUINT64->F64");
+
+instruction!(INT8_TO_UINT, (a => b), b"\x8aINT8->UINT");
+instruction!(INT8_TO_INT, (a => b), b"\x89INT8->INT");
+instruction!(INT8_TO_UINT8, (a => b), b"\x8bINT8->UINT8");
+instruction!(INT8_TO_UINT16, (a => b), b"\x8cINT8->UINT16");
+instruction!(INT8_TO_UINT32, (a => b), b"\x8cINT8->UINT32");
+instruction!(INT8_TO_UINT64, (a => b), b"\x8cINT8->UINT64");
+instruction!(INT8_TO_INT16, (a => b), b"\x8bINT8->INT16");
+instruction!(INT8_TO_INT32, (a => b), b"\x8bINT8->INT32");
+instruction!(INT8_TO_INT64, (a => b), b"\x8bINT8->INT64");
+instruction!(INT8_TO_F32, (a => b), b"\x89INT8->F32");
+instruction!(INT8_TO_F64, (a => b), b"\x89INT8->F64");
+
+instruction!(INT16_TO_UINT, (a => b), b"\x8bINT16->UINT");
+instruction!(INT16_TO_INT, (a => b), b"\x8aINT16->INT");
+instruction!(INT16_TO_UINT8, (a => b), b"\x8cINT16->UINT8");
+instruction!(INT16_TO_UINT16, (a => b), b"\x8dINT16->UINT16");
+instruction!(INT16_TO_UINT32, (a => b), b"\x8dINT16->UINT32");
+instruction!(INT16_TO_UINT64, (a => b), b"\x8dINT16->UINT64");
+instruction!(INT16_TO_INT8, (a => b), b"\x8bINT16->INT8");
+instruction!(INT16_TO_INT32, (a => b), b"\x8cINT16->INT32");
+instruction!(INT16_TO_INT64, (a => b), b"\x8cINT16->INT64");
+instruction!(INT16_TO_F32, (a => b), b"\x8aINT16->F32");
+instruction!(INT16_TO_F64, (a => b), b"\x8aINT16->F64");
+
+instruction!(INT32_TO_UINT, (a => b), b"\x8bINT32->UINT");
+instruction!(INT32_TO_INT, (a => b), b"\x8aINT32->INT");
+instruction!(INT32_TO_UINT8, (a => b), b"\x8cINT32->UINT8");
+instruction!(INT32_TO_UINT16, (a => b), b"\x8dINT32->UINT16");
+instruction!(INT32_TO_UINT32, (a => b), b"\x8dINT32->UINT32");
+instruction!(INT32_TO_UINT64, (a => b), b"\x8dINT32->UINT64");
+instruction!(INT32_TO_INT8, (a => b), b"\x8bINT32->INT8");
+instruction!(INT32_TO_INT16, (a => b), b"\x8cINT32->INT16");
+instruction!(INT32_TO_INT64, (a => b), b"\x8cINT32->INT64");
+instruction!(INT32_TO_F32, (a => b), b"\x8aINT32->F32");
+instruction!(INT32_TO_F64, (a => b), b"\x8aINT32->F64");
+
+instruction!(INT64_TO_UINT, (a => b), b"\x8bINT64->UINT");
+instruction!(INT64_TO_INT, (a => b), b"\x8aINT64->INT");
+instruction!(INT64_TO_UINT8, (a => b), b"\x8cINT64->UINT8");
+instruction!(INT64_TO_UINT16, (a => b), b"\x8dINT64->UINT16");
+instruction!(INT64_TO_UINT32, (a => b), b"\x8dINT64->UINT32");
+instruction!(INT64_TO_UINT64, (a => b), b"\x8dINT64->UINT64");
+instruction!(INT64_TO_INT8, (a => b), b"\x8bINT64->INT8");
+instruction!(INT64_TO_INT16, (a => b), b"\x8cINT64->INT16");
+instruction!(INT64_TO_INT32, (a => b), b"\x8cINT64->INT32");
+instruction!(INT64_TO_F32, (a => b), b"\x8aINT64->F32");
+instruction!(INT64_TO_F64, (a => b), b"\x8aINT64->F64");
+
+instruction!(F32_TO_UINT_FLOOR, (a => b), b"\x8fF32->UINT/FLOOR");
+instruction!(F32_TO_UINT_CEIL, (a => b), b"\x8eF32->UINT/CEIL");
+instruction!(F32_TO_UINT_ROUND, (a => b), b"\x8fF32->UINT/ROUND");
+instruction!(F32_TO_UINT_TRUNC, (a => b), b"\x8fF32->UINT/TRUNC");
+instruction!(F32_TO_INT_FLOOR, (a => b), b"\x8eF32->INT/FLOOR");
+instruction!(F32_TO_INT_CEIL, (a => b), b"\x8dF32->INT/CEIL");
+instruction!(F32_TO_INT_ROUND, (a => b), b"\x8eF32->INT/ROUND");
+instruction!(F32_TO_INT_TRUNC, (a => b), b"\x8eF32->INT/TRUNC");
+instruction!(F32_TO_UINT8_FLOOR, (a => b), b"\x90F32->UINT8/FLOOR");
+instruction!(F32_TO_UINT8_CEIL, (a => b), b"\x8fF32->UINT8/CEIL");
+instruction!(F32_TO_UINT8_ROUND, (a => b), b"\x90F32->UINT8/ROUND");
+instruction!(F32_TO_UINT8_TRUNC, (a => b), b"\x90F32->UINT8/TRUNC");
+instruction!(F32_TO_UINT16_FLOOR, (a => b), b"\x91F32->UINT16/FLOOR");
+instruction!(F32_TO_UINT16_CEIL, (a => b), b"\x90F32->UINT16/CEIL");
+instruction!(F32_TO_UINT16_ROUND, (a => b), b"\x91F32->UINT16/ROUND");
+instruction!(F32_TO_UINT16_TRUNC, (a => b), b"\x91F32->UINT16/TRUNC");
+instruction!(F32_TO_UINT32_FLOOR, (a => b), b"\x91F32->UINT32/FLOOR");
+instruction!(F32_TO_UINT32_CEIL, (a => b), b"\x90F32->UINT32/CEIL");
+instruction!(F32_TO_UINT32_ROUND, (a => b), b"\x91F32->UINT32/ROUND");
+instruction!(F32_TO_UINT32_TRUNC, (a => b), b"\x91F32->UINT32/TRUNC");
+instruction!(F32_TO_UINT64_FLOOR, (a => b), b"\x91F32->UINT64/FLOOR");
+instruction!(F32_TO_UINT64_CEIL, (a => b), b"\x90F32->UINT64/CEIL");
+instruction!(F32_TO_UINT64_ROUND, (a => b), b"\x91F32->UINT64/ROUND");
+instruction!(F32_TO_UINT64_TRUNC, (a => b), b"\x91F32->UINT64/TRUNC");
+instruction!(F32_TO_INT8_FLOOR, (a => b), b"\x8fF32->INT8/FLOOR");
+instruction!(F32_TO_INT8_CEIL, (a => b), b"\x8eF32->INT8/CEIL");
+instruction!(F32_TO_INT8_ROUND, (a => b), b"\x8fF32->INT8/ROUND");
+instruction!(F32_TO_INT8_TRUNC, (a => b), b"\x8fF32->INT8/TRUNC");
+instruction!(F32_TO_INT16_FLOOR, (a => b), b"\x90F32->INT16/FLOOR");
+instruction!(F32_TO_INT16_CEIL, (a => b), b"\x8fF32->INT16/CEIL");
+instruction!(F32_TO_INT16_ROUND, (a => b), b"\x90F32->INT16/ROUND");
+instruction!(F32_TO_INT16_TRUNC, (a => b), b"\x90F32->INT16/TRUNC");
+instruction!(F32_TO_INT32_FLOOR, (a => b), b"\x90F32->INT32/FLOOR");
+instruction!(F32_TO_INT32_CEIL, (a => b), b"\x8fF32->INT32/CEIL");
+instruction!(F32_TO_INT32_ROUND, (a => b), b"\x90F32->INT32/ROUND");
+instruction!(F32_TO_INT32_TRUNC, (a => b), b"\x90F32->INT32/TRUNC");
+instruction!(F32_TO_INT64_FLOOR, (a => b), b"\x90F32->INT64/FLOOR");
+instruction!(F32_TO_INT64_CEIL, (a => b), b"\x8fF32->INT64/CEIL");
+instruction!(F32_TO_INT64_ROUND, (a => b), b"\x90F32->INT64/ROUND");
+instruction!(F32_TO_INT64_TRUNC, (a => b), b"\x90F32->INT64/TRUNC");
+instruction!(F32_TO_F64, (a => b), b"\x88F32->F64");
+
+instruction!(F64_TO_UINT_FLOOR, (a => b), b"\x8fF64->UINT/FLOOR");
+instruction!(F64_TO_UINT_CEIL, (a => b), b"\x8eF64->UINT/CEIL");
+instruction!(F64_TO_UINT_ROUND, (a => b), b"\x8fF64->UINT/ROUND");
+instruction!(F64_TO_UINT_TRUNC, (a => b), b"\x8fF64->UINT/TRUNC");
+instruction!(F64_TO_INT_FLOOR, (a => b), b"\x8eF64->INT/FLOOR");
+instruction!(F64_TO_INT_CEIL, (a => b), b"\x8dF64->INT/CEIL");
+instruction!(F64_TO_INT_ROUND, (a => b), b"\x8eF64->INT/ROUND");
+instruction!(F64_TO_INT_TRUNC, (a => b), b"\x8eF64->INT/TRUNC");
+instruction!(F64_TO_UINT8_FLOOR, (a => b), b"\x90F64->UINT8/FLOOR");
+instruction!(F64_TO_UINT8_CEIL, (a => b), b"\x8fF64->UINT8/CEIL");
+instruction!(F64_TO_UINT8_ROUND, (a => b), b"\x90F64->UINT8/ROUND");
+instruction!(F64_TO_UINT8_TRUNC, (a => b), b"\x90F64->UINT8/TRUNC");
+instruction!(F64_TO_UINT16_FLOOR, (a => b), b"\x91F64->UINT16/FLOOR");
+instruction!(F64_TO_UINT16_CEIL, (a => b), b"\x90F64->UINT16/CEIL");
+instruction!(F64_TO_UINT16_ROUND, (a => b), b"\x91F64->UINT16/ROUND");
+instruction!(F64_TO_UINT16_TRUNC, (a => b), b"\x91F64->UINT16/TRUNC");
+instruction!(F64_TO_UINT32_FLOOR, (a => b), b"\x91F64->UINT32/FLOOR");
+instruction!(F64_TO_UINT32_CEIL, (a => b), b"\x90F64->UINT32/CEIL");
+instruction!(F64_TO_UINT32_ROUND, (a => b), b"\x91F64->UINT32/ROUND");
+instruction!(F64_TO_UINT32_TRUNC, (a => b), b"\x91F64->UINT32/TRUNC");
+instruction!(F64_TO_UINT64_FLOOR, (a => b), b"\x91F64->UINT64/FLOOR");
+instruction!(F64_TO_UINT64_CEIL, (a => b), b"\x90F64->UINT64/CEIL");
+instruction!(F64_TO_UINT64_ROUND, (a => b), b"\x91F64->UINT64/ROUND");
+instruction!(F64_TO_UINT64_TRUNC, (a => b), b"\x91F64->UINT64/TRUNC");
+instruction!(F64_TO_INT8_FLOOR, (a => b), b"\x8fF64->INT8/FLOOR");
+instruction!(F64_TO_INT8_CEIL, (a => b), b"\x8eF64->INT8/CEIL");
+instruction!(F64_TO_INT8_ROUND, (a => b), b"\x8fF64->INT8/ROUND");
+instruction!(F64_TO_INT8_TRUNC, (a => b), b"\x8fF64->INT8/TRUNC");
+instruction!(F64_TO_INT16_FLOOR, (a => b), b"\x90F64->INT16/FLOOR");
+instruction!(F64_TO_INT16_CEIL, (a => b), b"\x8fF64->INT16/CEIL");
+instruction!(F64_TO_INT16_ROUND, (a => b), b"\x90F64->INT16/ROUND");
+instruction!(F64_TO_INT16_TRUNC, (a => b), b"\x90F64->INT16/TRUNC");
+instruction!(F64_TO_INT32_FLOOR, (a => b), b"\x90F64->INT32/FLOOR");
+instruction!(F64_TO_INT32_CEIL, (a => b), b"\x8fF64->INT32/CEIL");
+instruction!(F64_TO_INT32_ROUND, (a => b), b"\x90F64->INT32/ROUND");
+instruction!(F64_TO_INT32_TRUNC, (a => b), b"\x90F64->INT32/TRUNC");
+instruction!(F64_TO_INT64_FLOOR, (a => b), b"\x90F64->INT64/FLOOR");
+instruction!(F64_TO_INT64_CEIL, (a => b), b"\x8fF64->INT64/CEIL");
+instruction!(F64_TO_INT64_ROUND, (a => b), b"\x90F64->INT64/ROUND");
+instruction!(F64_TO_INT64_TRUNC, (a => b), b"\x90F64->INT64/TRUNC");
+instruction!(F64_TO_F32, (a => b), b"\x88F64->F32");
+
 // Comparison
 instruction!(UINT_EQUALQ, (a, b => c), b"\x8BUINT/EQUAL?");
 instruction!(UINT_GTQ, (a, b => c), b"\x88UINT/GT?");
@@ -58,23 +345,33 @@ instruction!(UINT_LTQ, (a, b => c), b"\x88UINT/LT?");
 instruction!(INT_EQUALQ, (a, b => c), b"\x8AINT/EQUAL?");
 instruction!(INT_GTQ, (a, b => c), b"\x87INT/GT?");
 instruction!(INT_LTQ, (a, b => c), b"\x87INT/LT?");
+instruction!(F32_EQUALQ, (a, b => c), b"\x8aF32/EQUAL?");
+instruction!(F32_LTQ, (a, b => c), b"\x87F32/LT?");
+instruction!(F32_GTQ, (a, b => c), b"\x87F32/GT?");
+instruction!(F64_EQUALQ, (a, b => c), b"\x8aF64/EQUAL?");
+instruction!(F64_LTQ, (a, b => c), b"\x87F64/LT?");
+instruction!(F64_GTQ, (a, b => c), b"\x87F64/GT?");
+instruction!(DECIMAL_EQUALQ, (a, b => c), b"\x8eDECIMAL/EQUAL?");
+instruction!(DECIMAL_LTQ, (a, b => c), b"\x8bDECIMAL/LT?");
+instruction!(DECIMAL_GTQ, (a, b => c), b"\x8bDECIMAL/GT?");
 
 // Stringify
-instruction!(UINT_TO_STRING, b"\x8dUINT/->STRING");
-instruction!(INT_TO_STRING, b"\x8cINT/->STRING");
+instruction!(UINT_TO_STRING, (a => b), b"\x8dUINT/->STRING");
+instruction!(INT_TO_STRING, (a => b), b"\x8cINT/->STRING");
 
-instruction!(UINT8_TO_STRING, b"\x8eUINT8/->STRING");
-instruction!(UINT16_TO_STRING, b"\x8fUINT16/->STRING");
-instruction!(UINT32_TO_STRING, b"\x8fUINT32/->STRING");
-instruction!(UINT64_TO_STRING, b"\x8fUINT64/->STRING");
+instruction!(UINT8_TO_STRING, (a => b), b"\x8eUINT8/->STRING");
+instruction!(UINT16_TO_STRING, (a => b), b"\x8fUINT16/->STRING");
+instruction!(UINT32_TO_STRING, (a => b), b"\x8fUINT32/->STRING");
+instruction!(UINT64_TO_STRING, (a => b), b"\x8fUINT64/->STRING");
 
-instruction!(INT8_TO_STRING, b"\x8dINT8/->STRING");
-instruction!(INT16_TO_STRING, b"\x8eINT16/->STRING");
-instruction!(INT32_TO_STRING, b"\x8eINT32/->STRING");
-instruction!(INT64_TO_STRING, b"\x8eINT64/->STRING");
+instruction!(INT8_TO_STRING, (a => b), b"\x8dINT8/->STRING");
+instruction!(INT16_TO_STRING, (a => b), b"\x8eINT16/->STRING");
+instruction!(INT32_TO_STRING, (a => b), b"\x8eINT32/->STRING");
+instruction!(INT64_TO_STRING, (a => b), b"\x8eINT64/->STRING");
 
-instruction!(F32_TO_STRING, b"\x8cF32/->STRING");
-instruction!(F64_TO_STRING, b"\x8cF64/->STRING");
+instruction!(F32_TO_STRING, (a => b), b"\x8cF32/->STRING");
+instruction!(F64_TO_STRING, (a => b), b"\x8cF64/->STRING");
+instruction!(DECIMAL_TO_STRING, (a => b), b"\x90DECIMAL/->STRING");
 
 macro_rules! uint_comparison {
     ($env: expr, $instruction: expr, $instruction_const: expr, $cmp: ident) => {{
@@ -112,6 +409,61 @@ macro_rules! int_comparison {
     }};
 }
 
+// Compares floats according to IEEE 754, any comparison with NaN is false
+macro_rules! float_comparison {
+    ($env: expr, $type: ident, $cmp: ident) => {{
+        let b_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a: $type = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+        let b: $type = b_bytes.unpack().ok_or(error_invalid_value!(b_bytes))?;
+
+        if a.$cmp(&b) {
+            $env.push(STACK_TRUE);
+        } else {
+            $env.push(STACK_FALSE);
+        }
+        Ok(())
+    }};
+}
+
+// Handles a failed checked operation on sized unsigned integers
+macro_rules! uint_op_failed {
+    (checked_add, $a: expr) => { return Err(error_invalid_value!($a)) };
+    (checked_sub, $a: expr) => { return Err(error_invalid_value!($a)) };
+    (checked_mul, $a: expr) => { return Err(error_overflow!()) };
+    (checked_div, $a: expr) => { return Err(error_division_by_zero!()) };
+    (checked_rem, $a: expr) => { return Err(error_division_by_zero!()) };
+}
+
+// Handles a failed checked operation on sized signed integers, yielding
+// the result if it is representable nevertheless
+macro_rules! int_op_failed {
+    (checked_add, $a: expr, $a_int: expr, $b_int: expr) => { return Err(error_invalid_value!($a)) };
+    (checked_sub, $a: expr, $a_int: expr, $b_int: expr) => { return Err(error_invalid_value!($a)) };
+    (checked_mul, $a: expr, $a_int: expr, $b_int: expr) => {
+        if ($a_int < 0) == ($b_int < 0) {
+            return Err(error_overflow!())
+        } else {
+            return Err(error_underflow!())
+        }
+    };
+    (checked_div, $a: expr, $a_int: expr, $b_int: expr) => {
+        if $b_int == 0 {
+            return Err(error_division_by_zero!())
+        } else {
+            return Err(error_overflow!())
+        }
+    };
+    // the smallest value divided by -1 overflows, but its remainder is zero
+    (checked_rem, $a: expr, $a_int: expr, $b_int: expr) => {
+        if $b_int == 0 {
+            return Err(error_division_by_zero!())
+        } else {
+            0
+        }
+    };
+}
 
 macro_rules! no_endianness_sized_uint_op {
     ($env: expr, $read_op: ident, $op: ident, $write_op: ident) => {{
@@ -130,7 +482,7 @@ macro_rules! no_endianness_sized_uint_op {
 
         let c_int = match a_int.$op(b_int) {
             Some(v) => v,
-            None => return Err(error_invalid_value!(a)),
+            None => uint_op_failed!($op, a),
         };
 
         let mut c_bytes = vec![];
@@ -168,7 +520,7 @@ macro_rules! no_endianness_sized_int_op {
 
         let c_int = match a_int.$op(b_int) {
             Some(v) => v,
-            None => return Err(error_invalid_value!(a)),
+            None => int_op_failed!($op, a, a_int, b_int),
         };
 
         let mut c_bytes = vec![];
@@ -202,7 +554,7 @@ macro_rules! sized_uint_op {
 
         let c_int = match a_int.$op(b_int) {
             Some(v) => v,
-            None => return Err(error_invalid_value!(a)),
+            None => uint_op_failed!($op, a),
         };
 
         let mut c_bytes = vec![];
@@ -240,7 +592,75 @@ macro_rules! sized_int_op {
 
         let c_int = match a_int.$op(b_int) {
             Some(v) => v,
-            None => return Err(error_invalid_value!(a)),
+            None => int_op_failed!($op, a, a_int, b_int),
+        };
+
+        let mut c_bytes = vec![];
+        match c_bytes.$write_op::<BigEndian>(c_int) {
+            Ok(_) => {},
+            Err(_) => return Err(error_invalid_value!(a)),
+        }
+
+        c_bytes[0] ^= 1u8 << 7;
+
+        let slice = alloc_and_write!(c_bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! no_endianness_sized_int_neg {
+    ($env: expr, $read_op: ident, $write_op: ident) => {{
+        let a = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        if a.is_empty() {
+            return Err(error_invalid_value!(a));
+        }
+
+        let mut a = Vec::from(a);
+        a[0] ^= 1u8 << 7;
+
+        let a_int = match a.as_slice().$read_op() {
+            Ok(v) => v,
+            Err(_) => return Err(error_invalid_value!(a)),
+        };
+
+        let c_int = match a_int.checked_neg() {
+            Some(v) => v,
+            None => return Err(error_overflow!()),
+        };
+
+        let mut c_bytes = vec![];
+        match c_bytes.$write_op(c_int) {
+            Ok(_) => {},
+            Err(_) => return Err(error_invalid_value!(a)),
+        }
+
+        c_bytes[0] ^= 1u8 << 7;
+
+        let slice = alloc_and_write!(c_bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! sized_int_neg {
+    ($env: expr, $read_op: ident, $write_op: ident) => {{
+        let a = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        if a.is_empty() {
+            return Err(error_invalid_value!(a));
+        }
+
+        let mut a = Vec::from(a);
+        a[0] ^= 1u8 << 7;
+
+        let a_int = match a.as_slice().$read_op::<BigEndian>() {
+            Ok(v) => v,
+            Err(_) => return Err(error_invalid_value!(a)),
+        };
+
+        let c_int = match a_int.checked_neg() {
+            Some(v) => v,
+            None => return Err(error_overflow!()),
         };
 
         let mut c_bytes = vec![];
@@ -257,6 +677,103 @@ macro_rules! sized_int_op {
     }};
 }
 
+macro_rules! float_op {
+    ($env: expr, $type: ident, $op: tt) => {{
+        let b_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let b: $type = b_bytes.unpack().ok_or(error_invalid_value!(b_bytes))?;
+
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a: $type = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+
+        let bytes = (a $op b).pack();
+        let slice = alloc_and_write!(bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! float_neg {
+    ($env: expr, $type: ident) => {{
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a: $type = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+
+        let bytes = (-a).pack();
+        let slice = alloc_and_write!(bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! float_unary {
+    ($env: expr, $type: ident, $method: ident) => {{
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a: $type = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+
+        let bytes = a.$method().pack();
+        let slice = alloc_and_write!(bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! float_binary {
+    ($env: expr, $type: ident, $method: ident) => {{
+        let b_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let b: $type = b_bytes.unpack().ok_or(error_invalid_value!(b_bytes))?;
+
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a: $type = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+
+        let bytes = a.$method(b).pack();
+        let slice = alloc_and_write!(bytes.as_slice(), $env);
+        $env.push(slice);
+        Ok(())
+    }};
+}
+
+macro_rules! decimal_operands {
+    ($env: expr) => {{
+        let b_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a: Decimal = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+        let b: Decimal = b_bytes.unpack().ok_or(error_invalid_value!(b_bytes))?;
+        (a, b)
+    }};
+}
+
+macro_rules! decimal_comparison {
+    ($env: expr, $cmp: ident) => {{
+        let (a, b) = decimal_operands!($env);
+        if a.$cmp(&b) {
+            $env.push(STACK_TRUE);
+        } else {
+            $env.push(STACK_FALSE);
+        }
+        Ok(())
+    }};
+}
+
+macro_rules! decimal_div {
+    ($env: expr, $rounding: expr) => {{
+        let (a, b) = decimal_operands!($env);
+        let scale = ::std::cmp::max(a.scale(), b.scale());
+        a.div(&b, scale, $rounding).ok_or_else(|| error_division_by_zero!())?
+    }};
+}
+
+macro_rules! decimal_rescale {
+    ($env: expr, $rounding: expr) => {{
+        let scale_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a: Decimal = a_bytes.unpack().ok_or(error_invalid_value!(a_bytes))?;
+        let scale: BigUint = scale_bytes.unpack().ok_or(error_invalid_value!(scale_bytes))?;
+        let scale = scale.to_u8().ok_or(error_invalid_value!(scale_bytes))?;
+        a.rescale(scale, $rounding)
+    }};
+}
+
 macro_rules! to_string {
     ($env: expr, $type: ident) => {{
         let a_bytes = $env.pop().ok_or_else(|| error_empty_stack!())?;
@@ -267,55 +784,582 @@ macro_rules! to_string {
 }
 
 
+/// Numeric types that can be converted between each other
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Numeric {
+    Uint,
+    Int,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    F32,
+    F64,
+}
+
+impl Numeric {
+    fn from_name(name: &[u8]) -> Option<Numeric> {
+        match name {
+            b"UINT" => Some(Numeric::Uint),
+            b"INT" => Some(Numeric::Int),
+            b"UINT8" => Some(Numeric::Uint8),
+            b"UINT16" => Some(Numeric::Uint16),
+            b"UINT32" => Some(Numeric::Uint32),
+            b"UINT64" => Some(Numeric::Uint64),
+            b"INT8" => Some(Numeric::Int8),
+            b"INT16" => Some(Numeric::Int16),
+            b"INT32" => Some(Numeric::Int32),
+            b"INT64" => Some(Numeric::Int64),
+            b"F32" => Some(Numeric::F32),
+            b"F64" => Some(Numeric::F64),
+            _ => None,
+        }
+    }
+
+    /// Size of the encoded value, `None` for arbitrary-precision types
+    fn size(&self) -> Option<usize> {
+        match *self {
+            Numeric::Uint | Numeric::Int => None,
+            Numeric::Uint8 | Numeric::Int8 => Some(1),
+            Numeric::Uint16 | Numeric::Int16 => Some(2),
+            Numeric::Uint32 | Numeric::Int32 | Numeric::F32 => Some(4),
+            Numeric::Uint64 | Numeric::Int64 | Numeric::F64 => Some(8),
+        }
+    }
+}
+
+/// Rounding applied when converting a float to an integer
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Rounding {
+    Floor,
+    Ceil,
+    /// Half away from zero
+    Round,
+    Trunc,
+}
+
+impl Rounding {
+    fn from_name(name: &[u8]) -> Option<Rounding> {
+        match name {
+            b"FLOOR" => Some(Rounding::Floor),
+            b"CEIL" => Some(Rounding::Ceil),
+            b"ROUND" => Some(Rounding::Round),
+            b"TRUNC" => Some(Rounding::Trunc),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, f: f64) -> f64 {
+        match *self {
+            Rounding::Floor => f.floor(),
+            Rounding::Ceil => f.ceil(),
+            Rounding::Round => f.round(),
+            Rounding::Trunc => f.trunc(),
+        }
+    }
+}
+
+/// Parses conversion instruction's name (`FROM->TO` or `FROM->TO/ROUNDING`)
+fn conversion(instruction: &[u8]) -> Option<(Numeric, Numeric, Option<Rounding>)> {
+    let name = &instruction[1..];
+    let arrow = match name.windows(2).position(|w| w == b"->") {
+        Some(arrow) => arrow,
+        None => return None,
+    };
+    let (from, rest) = (&name[..arrow], &name[arrow + 2..]);
+    let (to, rounding) = match rest.iter().position(|c| *c == b'/') {
+        Some(slash) => match Rounding::from_name(&rest[slash + 1..]) {
+            Some(rounding) => (&rest[..slash], Some(rounding)),
+            None => return None,
+        },
+        None => (rest, None),
+    };
+    match (Numeric::from_name(from), Numeric::from_name(to)) {
+        (Some(from), Some(to)) => Some((from, to, rounding)),
+        _ => None,
+    }
+}
+
+/// Decoded numeric value
+enum Number {
+    Integer(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    fn decode(numeric: Numeric, bytes: &[u8]) -> Option<Number> {
+        if let Some(size) = numeric.size() {
+            if bytes.len() != size {
+                return None;
+            }
+        }
+        match numeric {
+            Numeric::Uint => {
+                let a: Option<BigUint> = bytes.unpack();
+                a.map(|a| Number::Integer(BigInt::from_biguint(Sign::Plus, a)))
+            }
+            Numeric::Int => bytes.unpack().map(Number::Integer),
+            Numeric::Uint8 => bytes.unpack().and_then(BigInt::from_u8).map(Number::Integer),
+            Numeric::Uint16 => bytes.unpack().and_then(BigInt::from_u16).map(Number::Integer),
+            Numeric::Uint32 => bytes.unpack().and_then(BigInt::from_u32).map(Number::Integer),
+            Numeric::Uint64 => bytes.unpack().and_then(BigInt::from_u64).map(Number::Integer),
+            Numeric::Int8 => bytes.unpack().and_then(BigInt::from_i8).map(Number::Integer),
+            Numeric::Int16 => bytes.unpack().and_then(BigInt::from_i16).map(Number::Integer),
+            Numeric::Int32 => bytes.unpack().and_then(BigInt::from_i32).map(Number::Integer),
+            Numeric::Int64 => bytes.unpack().and_then(BigInt::from_i64).map(Number::Integer),
+            Numeric::F32 => {
+                let f: Option<f32> = bytes.unpack();
+                f.map(|f| Number::Float(f as f64))
+            }
+            Numeric::F64 => bytes.unpack().map(Number::Float),
+        }
+    }
+}
+
+/// Formats an encoded number of a named type (such as `UINT8` or `DECIMAL`)
+/// the way `[type]/->STRING` instructions do. Floats can be formatted
+/// with a fixed number of decimal places.
+pub fn to_string(name: &[u8], bytes: &[u8], precision: Option<usize>) -> Option<String> {
+    if name == b"DECIMAL" {
+        let decimal: Option<Decimal> = bytes.unpack();
+        return match precision {
+            None => decimal.map(|d| format!("{}", d)),
+            Some(_) => None,
+        };
+    }
+    let numeric = match Numeric::from_name(name) {
+        Some(numeric) => numeric,
+        None => return None,
+    };
+    if let Some(size) = numeric.size() {
+        if bytes.len() != size {
+            return None;
+        }
+    }
+    match (numeric, precision) {
+        (Numeric::F32, precision) => {
+            let f: Option<f32> = bytes.unpack();
+            f.map(|f| match precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => format!("{}", f),
+            })
+        }
+        (Numeric::F64, precision) => {
+            let f: Option<f64> = bytes.unpack();
+            f.map(|f| match precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => format!("{}", f),
+            })
+        }
+        (_, Some(_)) => None,
+        (numeric, None) => match Number::decode(numeric, bytes) {
+            Some(Number::Integer(i)) => Some(format!("{}", i)),
+            _ => None,
+        },
+    }
+}
+
+// Error raised when a value doesn't fit into the target type
+fn out_of_range(negative: bool) -> Error {
+    if negative { error_underflow!() } else { error_overflow!() }
+}
+
+// Packs an integer into a sized type
+macro_rules! pack_sized_int {
+    ($i: expr, $to_op: ident) => {
+        $i.$to_op().map(|v| v.pack()).ok_or_else(|| out_of_range($i.sign() == Sign::Minus))
+    };
+}
+
+// Packs an integer into a float, rounding its 64 most
+// significant bits to the nearest one
+macro_rules! pack_float_int {
+    ($i: expr, $to_op: ident) => {
+        match $i.$to_op() {
+            Some(f) if f.is_finite() => Ok(f.pack()),
+            _ => Err(out_of_range($i.sign() == Sign::Minus)),
+        }
+    };
+}
+
+fn encode_integer(numeric: Numeric, i: BigInt) -> Result<Vec<u8>, Error> {
+    match numeric {
+        Numeric::Uint => i.to_biguint().map(|u| u.pack()).ok_or_else(|| out_of_range(true)),
+        Numeric::Int => Ok(i.pack()),
+        Numeric::Uint8 => pack_sized_int!(i, to_u8),
+        Numeric::Uint16 => pack_sized_int!(i, to_u16),
+        Numeric::Uint32 => pack_sized_int!(i, to_u32),
+        Numeric::Uint64 => pack_sized_int!(i, to_u64),
+        Numeric::Int8 => pack_sized_int!(i, to_i8),
+        Numeric::Int16 => pack_sized_int!(i, to_i16),
+        Numeric::Int32 => pack_sized_int!(i, to_i32),
+        Numeric::Int64 => pack_sized_int!(i, to_i64),
+        Numeric::F32 => pack_float_int!(i, to_f32),
+        Numeric::F64 => pack_float_int!(i, to_f64),
+    }
+}
+
+fn encode_float(numeric: Numeric, f: f64, rounding: Option<Rounding>) -> Result<Vec<u8>, Error> {
+    match (numeric, rounding) {
+        (Numeric::F64, _) => Ok(f.pack()),
+        (Numeric::F32, _) => {
+            let narrowed = f as f32;
+            if narrowed.is_infinite() && f.is_finite() {
+                Err(out_of_range(f < 0.0))
+            } else {
+                Ok(narrowed.pack())
+            }
+        }
+        (_, Some(rounding)) => {
+            let rounded = rounding.apply(f);
+            if !rounded.is_finite() {
+                return Err(error_invalid_value!(f.pack()));
+            }
+            let i = BigInt::from_f64(rounded).ok_or(error_invalid_value!(f.pack()))?;
+            encode_integer(numeric, i)
+        }
+        (_, None) => Err(Error::UnknownInstruction),
+    }
+}
+
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_uint_add(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_uint_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_to_uint(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint_to_int(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint_equalq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint_gtq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint_ltq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_equalq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_gtq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_ltq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint8_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint8_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int8_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int8_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint16_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint16_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int16_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int16_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint32_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint32_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int32_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int32_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint64_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uint64_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int64_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int64_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_f32_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_f32_sub(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_f64_add(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_f64_sub(env, instruction, pid))
-
-        .if_unhandled_try(|| self.handle_uint_to_string(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_int_to_string(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_to_string(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let table = dispatch_table! {
+            UINT_ADD => Handler::handle_uint_add,
+            UINT_SUB => Handler::handle_uint_sub,
+            INT_ADD => Handler::handle_int_add,
+            INT_SUB => Handler::handle_int_sub,
+            INT_TO_UINT => Handler::handle_int_to_uint,
+            UINT_TO_INT => Handler::handle_uint_to_int,
+            UINT_TO_UINT8 => Handler::handle_conversion,
+            UINT_TO_UINT16 => Handler::handle_conversion,
+            UINT_TO_UINT32 => Handler::handle_conversion,
+            UINT_TO_UINT64 => Handler::handle_conversion,
+            UINT_TO_INT8 => Handler::handle_conversion,
+            UINT_TO_INT16 => Handler::handle_conversion,
+            UINT_TO_INT32 => Handler::handle_conversion,
+            UINT_TO_INT64 => Handler::handle_conversion,
+            UINT_TO_F32 => Handler::handle_conversion,
+            UINT_TO_F64 => Handler::handle_conversion,
+            INT_TO_UINT8 => Handler::handle_conversion,
+            INT_TO_UINT16 => Handler::handle_conversion,
+            INT_TO_UINT32 => Handler::handle_conversion,
+            INT_TO_UINT64 => Handler::handle_conversion,
+            INT_TO_INT8 => Handler::handle_conversion,
+            INT_TO_INT16 => Handler::handle_conversion,
+            INT_TO_INT32 => Handler::handle_conversion,
+            INT_TO_INT64 => Handler::handle_conversion,
+            INT_TO_F32 => Handler::handle_conversion,
+            INT_TO_F64 => Handler::handle_conversion,
+            UINT8_TO_UINT => Handler::handle_conversion,
+            UINT8_TO_INT => Handler::handle_conversion,
+            UINT8_TO_UINT16 => Handler::handle_conversion,
+            UINT8_TO_UINT32 => Handler::handle_conversion,
+            UINT8_TO_UINT64 => Handler::handle_conversion,
+            UINT8_TO_INT8 => Handler::handle_conversion,
+            UINT8_TO_INT16 => Handler::handle_conversion,
+            UINT8_TO_INT32 => Handler::handle_conversion,
+            UINT8_TO_INT64 => Handler::handle_conversion,
+            UINT8_TO_F32 => Handler::handle_conversion,
+            UINT8_TO_F64 => Handler::handle_conversion,
+            UINT16_TO_UINT => Handler::handle_conversion,
+            UINT16_TO_INT => Handler::handle_conversion,
+            UINT16_TO_UINT8 => Handler::handle_conversion,
+            UINT16_TO_UINT32 => Handler::handle_conversion,
+            UINT16_TO_UINT64 => Handler::handle_conversion,
+            UINT16_TO_INT8 => Handler::handle_conversion,
+            UINT16_TO_INT16 => Handler::handle_conversion,
+            UINT16_TO_INT32 => Handler::handle_conversion,
+            UINT16_TO_INT64 => Handler::handle_conversion,
+            UINT16_TO_F32 => Handler::handle_conversion,
+            UINT16_TO_F64 => Handler::handle_conversion,
+            UINT32_TO_UINT => Handler::handle_conversion,
+            UINT32_TO_INT => Handler::handle_conversion,
+            UINT32_TO_UINT8 => Handler::handle_conversion,
+            UINT32_TO_UINT16 => Handler::handle_conversion,
+            UINT32_TO_UINT64 => Handler::handle_conversion,
+            UINT32_TO_INT8 => Handler::handle_conversion,
+            UINT32_TO_INT16 => Handler::handle_conversion,
+            UINT32_TO_INT32 => Handler::handle_conversion,
+            UINT32_TO_INT64 => Handler::handle_conversion,
+            UINT32_TO_F32 => Handler::handle_conversion,
+            UINT32_TO_F64 => Handler::handle_conversion,
+            UINT64_TO_UINT => Handler::handle_conversion,
+            UINT64_TO_INT => Handler::handle_conversion,
+            UINT64_TO_UINT8 => Handler::handle_conversion,
+            UINT64_TO_UINT16 => Handler::handle_conversion,
+            UINT64_TO_UINT32 => Handler::handle_conversion,
+            UINT64_TO_INT8 => Handler::handle_conversion,
+            UINT64_TO_INT16 => Handler::handle_conversion,
+            UINT64_TO_INT32 => Handler::handle_conversion,
+            UINT64_TO_INT64 => Handler::handle_conversion,
+            UINT64_TO_F32 => Handler::handle_conversion,
+            UINT64_TO_F64 => Handler::handle_conversion,
+            INT8_TO_UINT => Handler::handle_conversion,
+            INT8_TO_INT => Handler::handle_conversion,
+            INT8_TO_UINT8 => Handler::handle_conversion,
+            INT8_TO_UINT16 => Handler::handle_conversion,
+            INT8_TO_UINT32 => Handler::handle_conversion,
+            INT8_TO_UINT64 => Handler::handle_conversion,
+            INT8_TO_INT16 => Handler::handle_conversion,
+            INT8_TO_INT32 => Handler::handle_conversion,
+            INT8_TO_INT64 => Handler::handle_conversion,
+            INT8_TO_F32 => Handler::handle_conversion,
+            INT8_TO_F64 => Handler::handle_conversion,
+            INT16_TO_UINT => Handler::handle_conversion,
+            INT16_TO_INT => Handler::handle_conversion,
+            INT16_TO_UINT8 => Handler::handle_conversion,
+            INT16_TO_UINT16 => Handler::handle_conversion,
+            INT16_TO_UINT32 => Handler::handle_conversion,
+            INT16_TO_UINT64 => Handler::handle_conversion,
+            INT16_TO_INT8 => Handler::handle_conversion,
+            INT16_TO_INT32 => Handler::handle_conversion,
+            INT16_TO_INT64 => Handler::handle_conversion,
+            INT16_TO_F32 => Handler::handle_conversion,
+            INT16_TO_F64 => Handler::handle_conversion,
+            INT32_TO_UINT => Handler::handle_conversion,
+            INT32_TO_INT => Handler::handle_conversion,
+            INT32_TO_UINT8 => Handler::handle_conversion,
+            INT32_TO_UINT16 => Handler::handle_conversion,
+            INT32_TO_UINT32 => Handler::handle_conversion,
+            INT32_TO_UINT64 => Handler::handle_conversion,
+            INT32_TO_INT8 => Handler::handle_conversion,
+            INT32_TO_INT16 => Handler::handle_conversion,
+            INT32_TO_INT64 => Handler::handle_conversion,
+            INT32_TO_F32 => Handler::handle_conversion,
+            INT32_TO_F64 => Handler::handle_conversion,
+            INT64_TO_UINT => Handler::handle_conversion,
+            INT64_TO_INT => Handler::handle_conversion,
+            INT64_TO_UINT8 => Handler::handle_conversion,
+            INT64_TO_UINT16 => Handler::handle_conversion,
+            INT64_TO_UINT32 => Handler::handle_conversion,
+            INT64_TO_UINT64 => Handler::handle_conversion,
+            INT64_TO_INT8 => Handler::handle_conversion,
+            INT64_TO_INT16 => Handler::handle_conversion,
+            INT64_TO_INT32 => Handler::handle_conversion,
+            INT64_TO_F32 => Handler::handle_conversion,
+            INT64_TO_F64 => Handler::handle_conversion,
+            F32_TO_UINT_FLOOR => Handler::handle_conversion,
+            F32_TO_UINT_CEIL => Handler::handle_conversion,
+            F32_TO_UINT_ROUND => Handler::handle_conversion,
+            F32_TO_UINT_TRUNC => Handler::handle_conversion,
+            F32_TO_INT_FLOOR => Handler::handle_conversion,
+            F32_TO_INT_CEIL => Handler::handle_conversion,
+            F32_TO_INT_ROUND => Handler::handle_conversion,
+            F32_TO_INT_TRUNC => Handler::handle_conversion,
+            F32_TO_UINT8_FLOOR => Handler::handle_conversion,
+            F32_TO_UINT8_CEIL => Handler::handle_conversion,
+            F32_TO_UINT8_ROUND => Handler::handle_conversion,
+            F32_TO_UINT8_TRUNC => Handler::handle_conversion,
+            F32_TO_UINT16_FLOOR => Handler::handle_conversion,
+            F32_TO_UINT16_CEIL => Handler::handle_conversion,
+            F32_TO_UINT16_ROUND => Handler::handle_conversion,
+            F32_TO_UINT16_TRUNC => Handler::handle_conversion,
+            F32_TO_UINT32_FLOOR => Handler::handle_conversion,
+            F32_TO_UINT32_CEIL => Handler::handle_conversion,
+            F32_TO_UINT32_ROUND => Handler::handle_conversion,
+            F32_TO_UINT32_TRUNC => Handler::handle_conversion,
+            F32_TO_UINT64_FLOOR => Handler::handle_conversion,
+            F32_TO_UINT64_CEIL => Handler::handle_conversion,
+            F32_TO_UINT64_ROUND => Handler::handle_conversion,
+            F32_TO_UINT64_TRUNC => Handler::handle_conversion,
+            F32_TO_INT8_FLOOR => Handler::handle_conversion,
+            F32_TO_INT8_CEIL => Handler::handle_conversion,
+            F32_TO_INT8_ROUND => Handler::handle_conversion,
+            F32_TO_INT8_TRUNC => Handler::handle_conversion,
+            F32_TO_INT16_FLOOR => Handler::handle_conversion,
+            F32_TO_INT16_CEIL => Handler::handle_conversion,
+            F32_TO_INT16_ROUND => Handler::handle_conversion,
+            F32_TO_INT16_TRUNC => Handler::handle_conversion,
+            F32_TO_INT32_FLOOR => Handler::handle_conversion,
+            F32_TO_INT32_CEIL => Handler::handle_conversion,
+            F32_TO_INT32_ROUND => Handler::handle_conversion,
+            F32_TO_INT32_TRUNC => Handler::handle_conversion,
+            F32_TO_INT64_FLOOR => Handler::handle_conversion,
+            F32_TO_INT64_CEIL => Handler::handle_conversion,
+            F32_TO_INT64_ROUND => Handler::handle_conversion,
+            F32_TO_INT64_TRUNC => Handler::handle_conversion,
+            F32_TO_F64 => Handler::handle_conversion,
+            F64_TO_UINT_FLOOR => Handler::handle_conversion,
+            F64_TO_UINT_CEIL => Handler::handle_conversion,
+            F64_TO_UINT_ROUND => Handler::handle_conversion,
+            F64_TO_UINT_TRUNC => Handler::handle_conversion,
+            F64_TO_INT_FLOOR => Handler::handle_conversion,
+            F64_TO_INT_CEIL => Handler::handle_conversion,
+            F64_TO_INT_ROUND => Handler::handle_conversion,
+            F64_TO_INT_TRUNC => Handler::handle_conversion,
+            F64_TO_UINT8_FLOOR => Handler::handle_conversion,
+            F64_TO_UINT8_CEIL => Handler::handle_conversion,
+            F64_TO_UINT8_ROUND => Handler::handle_conversion,
+            F64_TO_UINT8_TRUNC => Handler::handle_conversion,
+            F64_TO_UINT16_FLOOR => Handler::handle_conversion,
+            F64_TO_UINT16_CEIL => Handler::handle_conversion,
+            F64_TO_UINT16_ROUND => Handler::handle_conversion,
+            F64_TO_UINT16_TRUNC => Handler::handle_conversion,
+            F64_TO_UINT32_FLOOR => Handler::handle_conversion,
+            F64_TO_UINT32_CEIL => Handler::handle_conversion,
+            F64_TO_UINT32_ROUND => Handler::handle_conversion,
+            F64_TO_UINT32_TRUNC => Handler::handle_conversion,
+            F64_TO_UINT64_FLOOR => Handler::handle_conversion,
+            F64_TO_UINT64_CEIL => Handler::handle_conversion,
+            F64_TO_UINT64_ROUND => Handler::handle_conversion,
+            F64_TO_UINT64_TRUNC => Handler::handle_conversion,
+            F64_TO_INT8_FLOOR => Handler::handle_conversion,
+            F64_TO_INT8_CEIL => Handler::handle_conversion,
+            F64_TO_INT8_ROUND => Handler::handle_conversion,
+            F64_TO_INT8_TRUNC => Handler::handle_conversion,
+            F64_TO_INT16_FLOOR => Handler::handle_conversion,
+            F64_TO_INT16_CEIL => Handler::handle_conversion,
+            F64_TO_INT16_ROUND => Handler::handle_conversion,
+            F64_TO_INT16_TRUNC => Handler::handle_conversion,
+            F64_TO_INT32_FLOOR => Handler::handle_conversion,
+            F64_TO_INT32_CEIL => Handler::handle_conversion,
+            F64_TO_INT32_ROUND => Handler::handle_conversion,
+            F64_TO_INT32_TRUNC => Handler::handle_conversion,
+            F64_TO_INT64_FLOOR => Handler::handle_conversion,
+            F64_TO_INT64_CEIL => Handler::handle_conversion,
+            F64_TO_INT64_ROUND => Handler::handle_conversion,
+            F64_TO_INT64_TRUNC => Handler::handle_conversion,
+            F64_TO_F32 => Handler::handle_conversion,
+            UINT_EQUALQ => Handler::handle_uint_equalq,
+            UINT_GTQ => Handler::handle_uint_gtq,
+            UINT_LTQ => Handler::handle_uint_ltq,
+            INT_EQUALQ => Handler::handle_int_equalq,
+            INT_GTQ => Handler::handle_int_gtq,
+            INT_LTQ => Handler::handle_int_ltq,
+            F32_EQUALQ => Handler::handle_float_comparison,
+            F32_LTQ => Handler::handle_float_comparison,
+            F32_GTQ => Handler::handle_float_comparison,
+            F64_EQUALQ => Handler::handle_float_comparison,
+            F64_LTQ => Handler::handle_float_comparison,
+            F64_GTQ => Handler::handle_float_comparison,
+            UINT8_ADD => Handler::handle_uint8_add,
+            UINT8_SUB => Handler::handle_uint8_sub,
+            INT8_ADD => Handler::handle_int8_add,
+            INT8_SUB => Handler::handle_int8_sub,
+            UINT16_ADD => Handler::handle_uint16_add,
+            UINT16_SUB => Handler::handle_uint16_sub,
+            INT16_ADD => Handler::handle_int16_add,
+            INT16_SUB => Handler::handle_int16_sub,
+            UINT32_ADD => Handler::handle_uint32_add,
+            UINT32_SUB => Handler::handle_uint32_sub,
+            INT32_ADD => Handler::handle_int32_add,
+            INT32_SUB => Handler::handle_int32_sub,
+            UINT64_ADD => Handler::handle_uint64_add,
+            UINT64_SUB => Handler::handle_uint64_sub,
+            INT64_ADD => Handler::handle_int64_add,
+            INT64_SUB => Handler::handle_int64_sub,
+            F32_ADD => Handler::handle_f32_add,
+            F32_SUB => Handler::handle_f32_sub,
+            F64_ADD => Handler::handle_f64_add,
+            F64_SUB => Handler::handle_f64_sub,
+            UINT_MUL => Handler::handle_uint_mul,
+            UINT_DIV => Handler::handle_uint_div,
+            UINT_REM => Handler::handle_uint_rem,
+            INT_MUL => Handler::handle_int_mul,
+            INT_DIV => Handler::handle_int_div,
+            INT_REM => Handler::handle_int_rem,
+            INT_NEG => Handler::handle_int_neg,
+            UINT8_MUL => Handler::handle_sized_arithmetic,
+            UINT8_DIV => Handler::handle_sized_arithmetic,
+            UINT8_REM => Handler::handle_sized_arithmetic,
+            INT8_MUL => Handler::handle_sized_arithmetic,
+            INT8_DIV => Handler::handle_sized_arithmetic,
+            INT8_REM => Handler::handle_sized_arithmetic,
+            INT8_NEG => Handler::handle_sized_arithmetic,
+            UINT16_MUL => Handler::handle_sized_arithmetic,
+            UINT16_DIV => Handler::handle_sized_arithmetic,
+            UINT16_REM => Handler::handle_sized_arithmetic,
+            INT16_MUL => Handler::handle_sized_arithmetic,
+            INT16_DIV => Handler::handle_sized_arithmetic,
+            INT16_REM => Handler::handle_sized_arithmetic,
+            INT16_NEG => Handler::handle_sized_arithmetic,
+            UINT32_MUL => Handler::handle_sized_arithmetic,
+            UINT32_DIV => Handler::handle_sized_arithmetic,
+            UINT32_REM => Handler::handle_sized_arithmetic,
+            INT32_MUL => Handler::handle_sized_arithmetic,
+            INT32_DIV => Handler::handle_sized_arithmetic,
+            INT32_REM => Handler::handle_sized_arithmetic,
+            INT32_NEG => Handler::handle_sized_arithmetic,
+            UINT64_MUL => Handler::handle_sized_arithmetic,
+            UINT64_DIV => Handler::handle_sized_arithmetic,
+            UINT64_REM => Handler::handle_sized_arithmetic,
+            INT64_MUL => Handler::handle_sized_arithmetic,
+            INT64_DIV => Handler::handle_sized_arithmetic,
+            INT64_REM => Handler::handle_sized_arithmetic,
+            INT64_NEG => Handler::handle_sized_arithmetic,
+            F32_MUL => Handler::handle_float_arithmetic,
+            F32_DIV => Handler::handle_float_arithmetic,
+            F32_REM => Handler::handle_float_arithmetic,
+            F32_NEG => Handler::handle_float_arithmetic,
+            F64_MUL => Handler::handle_float_arithmetic,
+            F64_DIV => Handler::handle_float_arithmetic,
+            F64_REM => Handler::handle_float_arithmetic,
+            F64_NEG => Handler::handle_float_arithmetic,
+            F32_FLOOR => Handler::handle_float_math,
+            F32_CEIL => Handler::handle_float_math,
+            F32_ROUND => Handler::handle_float_math,
+            F32_TRUNC => Handler::handle_float_math,
+            F32_ABS => Handler::handle_float_math,
+            F32_SQRT => Handler::handle_float_math,
+            F32_MIN => Handler::handle_float_math,
+            F32_MAX => Handler::handle_float_math,
+            F32_POW => Handler::handle_float_math,
+            F64_FLOOR => Handler::handle_float_math,
+            F64_CEIL => Handler::handle_float_math,
+            F64_ROUND => Handler::handle_float_math,
+            F64_TRUNC => Handler::handle_float_math,
+            F64_ABS => Handler::handle_float_math,
+            F64_SQRT => Handler::handle_float_math,
+            F64_MIN => Handler::handle_float_math,
+            F64_MAX => Handler::handle_float_math,
+            F64_POW => Handler::handle_float_math,
+            UINT_TO_STRING => Handler::handle_uint_to_string,
+            INT_TO_STRING => Handler::handle_int_to_string,
+            UINT8_TO_STRING => Handler::handle_to_string,
+            INT8_TO_STRING => Handler::handle_to_string,
+            UINT16_TO_STRING => Handler::handle_to_string,
+            INT16_TO_STRING => Handler::handle_to_string,
+            UINT32_TO_STRING => Handler::handle_to_string,
+            INT32_TO_STRING => Handler::handle_to_string,
+            UINT64_TO_STRING => Handler::handle_to_string,
+            INT64_TO_STRING => Handler::handle_to_string,
+            F32_TO_STRING => Handler::handle_to_string,
+            F64_TO_STRING => Handler::handle_to_string,
+            DECIMAL_ADD => Handler::handle_decimal,
+            DECIMAL_SUB => Handler::handle_decimal,
+            DECIMAL_MUL => Handler::handle_decimal,
+            DECIMAL_DIV_FLOOR => Handler::handle_decimal,
+            DECIMAL_DIV_CEIL => Handler::handle_decimal,
+            DECIMAL_DIV_ROUND => Handler::handle_decimal,
+            DECIMAL_DIV_EVEN => Handler::handle_decimal,
+            DECIMAL_DIV_TRUNC => Handler::handle_decimal,
+            DECIMAL_RESCALE_FLOOR => Handler::handle_decimal,
+            DECIMAL_RESCALE_CEIL => Handler::handle_decimal,
+            DECIMAL_RESCALE_ROUND => Handler::handle_decimal,
+            DECIMAL_RESCALE_EVEN => Handler::handle_decimal,
+            DECIMAL_RESCALE_TRUNC => Handler::handle_decimal,
+            DECIMAL_EQUALQ => Handler::handle_decimal,
+            DECIMAL_LTQ => Handler::handle_decimal,
+            DECIMAL_GTQ => Handler::handle_decimal,
+            DECIMAL_TO_STRING => Handler::handle_to_string,
+        };
+        Handler { phantom: PhantomData, table: table }
     }
 
 
@@ -410,6 +1454,25 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
+    #[inline]
+    fn handle_conversion(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        let (from, to, rounding) = conversion(instruction).ok_or(Error::UnknownInstruction)?;
+
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let bytes = match Number::decode(from, a).ok_or(error_invalid_value!(a))? {
+            Number::Integer(i) => encode_integer(to, i)?,
+            Number::Float(f) => encode_float(to, f, rounding)?,
+        };
+
+        let slice = alloc_and_write!(bytes.as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
     #[inline]
     fn handle_uint_sub(&mut self,
                        env: &mut Env<'a>,
@@ -729,6 +1792,304 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
+    #[inline]
+    fn handle_uint_mul(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, UINT_MUL);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigUint = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigUint = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        let c_int = a_int.mul(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_uint_div(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, UINT_DIV);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigUint = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigUint = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        if b_int.is_zero() {
+            return Err(error_division_by_zero!());
+        }
+
+        let c_int = a_int.div(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_uint_rem(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, UINT_REM);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigUint = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigUint = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        if b_int.is_zero() {
+            return Err(error_division_by_zero!());
+        }
+
+        let c_int = a_int.rem(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_int_mul(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, INT_MUL);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigInt = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigInt = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        let c_int = a_int.mul(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_int_div(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, INT_DIV);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigInt = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigInt = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        if b_int.is_zero() {
+            return Err(error_division_by_zero!());
+        }
+
+        let c_int = a_int.div(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_int_rem(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, INT_REM);
+        let b = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigInt = a.unpack().ok_or(error_invalid_value!(a))?;
+        let b_int: BigInt = b.unpack().ok_or(error_invalid_value!(b))?;
+
+        if b_int.is_zero() {
+            return Err(error_division_by_zero!());
+        }
+
+        let c_int = a_int.rem(b_int);
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_int_neg(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, INT_NEG);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let a_int: BigInt = a.unpack().ok_or(error_invalid_value!(a))?;
+
+        let c_int = a_int.neg();
+
+        let slice = alloc_and_write!(c_int.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    fn handle_sized_arithmetic(&mut self,
+                               env: &mut Env<'a>,
+                               instruction: &'a [u8],
+                               _: EnvId)
+                               -> PassResult<'a> {
+        match instruction {
+            UINT8_MUL => no_endianness_sized_uint_op!(env, read_u8, checked_mul, write_u8),
+            UINT8_DIV => no_endianness_sized_uint_op!(env, read_u8, checked_div, write_u8),
+            UINT8_REM => no_endianness_sized_uint_op!(env, read_u8, checked_rem, write_u8),
+            INT8_MUL => no_endianness_sized_int_op!(env, read_i8, checked_mul, write_i8),
+            INT8_DIV => no_endianness_sized_int_op!(env, read_i8, checked_div, write_i8),
+            INT8_REM => no_endianness_sized_int_op!(env, read_i8, checked_rem, write_i8),
+            INT8_NEG => no_endianness_sized_int_neg!(env, read_i8, write_i8),
+            UINT16_MUL => sized_uint_op!(env, read_u16, checked_mul, write_u16),
+            UINT16_DIV => sized_uint_op!(env, read_u16, checked_div, write_u16),
+            UINT16_REM => sized_uint_op!(env, read_u16, checked_rem, write_u16),
+            INT16_MUL => sized_int_op!(env, read_i16, checked_mul, write_i16),
+            INT16_DIV => sized_int_op!(env, read_i16, checked_div, write_i16),
+            INT16_REM => sized_int_op!(env, read_i16, checked_rem, write_i16),
+            INT16_NEG => sized_int_neg!(env, read_i16, write_i16),
+            UINT32_MUL => sized_uint_op!(env, read_u32, checked_mul, write_u32),
+            UINT32_DIV => sized_uint_op!(env, read_u32, checked_div, write_u32),
+            UINT32_REM => sized_uint_op!(env, read_u32, checked_rem, write_u32),
+            INT32_MUL => sized_int_op!(env, read_i32, checked_mul, write_i32),
+            INT32_DIV => sized_int_op!(env, read_i32, checked_div, write_i32),
+            INT32_REM => sized_int_op!(env, read_i32, checked_rem, write_i32),
+            INT32_NEG => sized_int_neg!(env, read_i32, write_i32),
+            UINT64_MUL => sized_uint_op!(env, read_u64, checked_mul, write_u64),
+            UINT64_DIV => sized_uint_op!(env, read_u64, checked_div, write_u64),
+            UINT64_REM => sized_uint_op!(env, read_u64, checked_rem, write_u64),
+            INT64_MUL => sized_int_op!(env, read_i64, checked_mul, write_i64),
+            INT64_DIV => sized_int_op!(env, read_i64, checked_div, write_i64),
+            INT64_REM => sized_int_op!(env, read_i64, checked_rem, write_i64),
+            INT64_NEG => sized_int_neg!(env, read_i64, write_i64),
+            _ => Err(Error::UnknownInstruction),
+        }
+    }
+
+    #[inline]
+    fn handle_float_arithmetic(&mut self,
+                               env: &mut Env<'a>,
+                               instruction: &'a [u8],
+                               _: EnvId)
+                               -> PassResult<'a> {
+        match instruction {
+            F32_MUL => float_op!(env, f32, *),
+            F32_DIV => float_op!(env, f32, /),
+            F32_REM => float_op!(env, f32, %),
+            F32_NEG => float_neg!(env, f32),
+            F64_MUL => float_op!(env, f64, *),
+            F64_DIV => float_op!(env, f64, /),
+            F64_REM => float_op!(env, f64, %),
+            F64_NEG => float_neg!(env, f64),
+            _ => Err(Error::UnknownInstruction),
+        }
+    }
+
+    #[inline]
+    fn handle_float_comparison(&mut self,
+                               env: &mut Env<'a>,
+                               instruction: &'a [u8],
+                               _: EnvId)
+                               -> PassResult<'a> {
+        match instruction {
+            F32_EQUALQ => float_comparison!(env, f32, eq),
+            F32_LTQ => float_comparison!(env, f32, lt),
+            F32_GTQ => float_comparison!(env, f32, gt),
+            F64_EQUALQ => float_comparison!(env, f64, eq),
+            F64_LTQ => float_comparison!(env, f64, lt),
+            F64_GTQ => float_comparison!(env, f64, gt),
+            _ => Err(Error::UnknownInstruction),
+        }
+    }
+
+    #[inline]
+    fn handle_float_math(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        match instruction {
+            F32_FLOOR => float_unary!(env, f32, floor),
+            F32_CEIL => float_unary!(env, f32, ceil),
+            F32_ROUND => float_unary!(env, f32, round),
+            F32_TRUNC => float_unary!(env, f32, trunc),
+            F32_ABS => float_unary!(env, f32, abs),
+            F32_SQRT => float_unary!(env, f32, sqrt),
+            F32_MIN => float_binary!(env, f32, min),
+            F32_MAX => float_binary!(env, f32, max),
+            F32_POW => float_binary!(env, f32, powf),
+            F64_FLOOR => float_unary!(env, f64, floor),
+            F64_CEIL => float_unary!(env, f64, ceil),
+            F64_ROUND => float_unary!(env, f64, round),
+            F64_TRUNC => float_unary!(env, f64, trunc),
+            F64_ABS => float_unary!(env, f64, abs),
+            F64_SQRT => float_unary!(env, f64, sqrt),
+            F64_MIN => float_binary!(env, f64, min),
+            F64_MAX => float_binary!(env, f64, max),
+            F64_POW => float_binary!(env, f64, powf),
+            _ => Err(Error::UnknownInstruction),
+        }
+    }
+
+    #[inline]
+    fn handle_decimal(&mut self,
+                      env: &mut Env<'a>,
+                      instruction: &'a [u8],
+                      _: EnvId)
+                      -> PassResult<'a> {
+        let result = match instruction {
+            DECIMAL_ADD => {
+                let (a, b) = decimal_operands!(env);
+                a.add(&b)
+            },
+            DECIMAL_SUB => {
+                let (a, b) = decimal_operands!(env);
+                a.sub(&b)
+            },
+            DECIMAL_MUL => {
+                let (a, b) = decimal_operands!(env);
+                a.mul(&b).ok_or_else(|| error_overflow!())?
+            },
+            DECIMAL_DIV_FLOOR => decimal_div!(env, decimal::Rounding::Floor),
+            DECIMAL_DIV_CEIL => decimal_div!(env, decimal::Rounding::Ceil),
+            DECIMAL_DIV_ROUND => decimal_div!(env, decimal::Rounding::Round),
+            DECIMAL_DIV_EVEN => decimal_div!(env, decimal::Rounding::Even),
+            DECIMAL_DIV_TRUNC => decimal_div!(env, decimal::Rounding::Trunc),
+            DECIMAL_RESCALE_FLOOR => decimal_rescale!(env, decimal::Rounding::Floor),
+            DECIMAL_RESCALE_CEIL => decimal_rescale!(env, decimal::Rounding::Ceil),
+            DECIMAL_RESCALE_ROUND => decimal_rescale!(env, decimal::Rounding::Round),
+            DECIMAL_RESCALE_EVEN => decimal_rescale!(env, decimal::Rounding::Even),
+            DECIMAL_RESCALE_TRUNC => decimal_rescale!(env, decimal::Rounding::Trunc),
+            DECIMAL_EQUALQ => return decimal_comparison!(env, eq),
+            DECIMAL_LTQ => return decimal_comparison!(env, lt),
+            DECIMAL_GTQ => return decimal_comparison!(env, gt),
+            _ => return Err(Error::UnknownInstruction),
+        };
+
+        let slice = alloc_and_write!(result.pack().as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
     #[inline]
     fn handle_uint_to_string(&mut self,
                         env: &mut Env<'a>,
@@ -783,6 +2144,7 @@ impl<'a> Handler<'a> {
             INT64_TO_STRING        => to_string!(env, i64),
             F32_TO_STRING          => to_string!(env, f32),
             F64_TO_STRING          => to_string!(env, f64),
+            DECIMAL_TO_STRING      => to_string!(env, Decimal),
             
             _ => return Err(Error::UnknownInstruction),
         };
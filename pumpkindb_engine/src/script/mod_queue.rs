@@ -6,34 +6,37 @@
 
 use pumpkinscript::{offset_by_size};
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_NO_VALUE,
-            TryInstruction, STACK_TRUE, STACK_FALSE};
+            DispatchTable, STACK_TRUE, STACK_FALSE};
 
 use std::marker::PhantomData;
 
-instruction!(TO_BQ, b"\x82>Q");
-instruction!(FROM_BQ, b"\x82Q>");
-instruction!(TO_FQ, b"\x82<Q");
-instruction!(FROM_FQ, b"\x82Q<");
-instruction!(QQ, b"\x82Q?");
+instruction!(TO_BQ, (a => ), b"\x82>Q");
+instruction!(FROM_BQ, ( => a), b"\x82Q>");
+instruction!(TO_FQ, (a => ), b"\x82<Q");
+instruction!(FROM_FQ, ( => a), b"\x82Q<");
+instruction!(QQ, ( => a), b"\x82Q?");
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_to_bq(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_from_bq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_to_fq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_from_fq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_qq(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let table = dispatch_table! {
+            TO_BQ => Handler::handle_to_bq,
+            FROM_BQ => Handler::handle_from_bq,
+            TO_FQ => Handler::handle_to_fq,
+            FROM_FQ => Handler::handle_from_fq,
+            QQ => Handler::handle_qq,
+        };
+        Handler { phantom: PhantomData, table: table }
     }
 
     #[inline]
@@ -0,0 +1,167 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//!
+//! # Randomness
+//!
+//! This module draws random bytes and numbers from a cryptographically
+//! secure generator (ChaCha20). Every environment has its own generator,
+//! seeded by the operating system, unless a seed was given with
+//! [`EnvOptions::random_seed`](../struct.EnvOptions.html) or `RANDOM/SEED`.
+//! Environments spawned by a seeded environment, as well as closures of
+//! timers it registers, get seeds drawn from its generator.
+//!
+
+instruction!(RANDOM_BYTES, (a => b), b"\x8cRANDOM/BYTES");
+instruction!(RANDOM_UINT, (a, b => c), b"\x8bRANDOM/UINT");
+instruction!(RANDOM_F64, ( => a), b"\x8aRANDOM/F64");
+instruction!(RANDOM_SEED, (a => ), b"\x8bRANDOM/SEED");
+
+use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
+            offset_by_size, DispatchTable};
+
+use pumpkinscript::Packable;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use rand::Rng;
+
+use std::marker::PhantomData;
+
+/// Largest number of bytes `RANDOM/BYTES` can generate at once
+const MAX_RANDOM_BYTES: usize = 65536;
+
+/// Draws a number uniformly from `[0, range)` by rejecting candidates
+/// that don't fall into the range
+fn below<R: Rng>(rng: &mut R, range: &BigUint) -> BigUint {
+    let bytes = range.to_bytes_be();
+    // only keep as many bits of the leading byte as the range has
+    let mask = 0xffu8 >> bytes[0].leading_zeros();
+    let mut candidate = vec![0; bytes.len()];
+    loop {
+        rng.fill_bytes(&mut candidate);
+        candidate[0] &= mask;
+        let n = BigUint::from_bytes_be(&candidate);
+        if &n < range {
+            return n;
+        }
+    }
+}
+
+pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Dispatcher<'a> for Handler<'a> {
+    fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
+        dispatch!(self, env, instruction, pid)
+    }
+}
+
+impl<'a> Handler<'a> {
+    pub fn new() -> Self {
+        let table = dispatch_table! {
+            RANDOM_BYTES => Handler::handle_random_bytes,
+            RANDOM_UINT => Handler::handle_random_uint,
+            RANDOM_F64 => Handler::handle_random_f64,
+            RANDOM_SEED => Handler::handle_random_seed,
+        };
+        Handler { phantom: PhantomData, table: table }
+    }
+
+    #[inline]
+    pub fn handle_random_bytes(&mut self,
+                               env: &mut Env<'a>,
+                               instruction: &'a [u8],
+                               _: EnvId)
+                               -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RANDOM_BYTES);
+        let n = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let len = match BigUint::from_bytes_be(n).to_usize() {
+            Some(len) if len <= MAX_RANDOM_BYTES => len,
+            _ => return Err(error_invalid_value!(n)),
+        };
+        let mut bytes = vec![0; len];
+        env.rng().fill_bytes(&mut bytes);
+        let slice = alloc_and_write!(bytes.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_random_uint(&mut self,
+                              env: &mut Env<'a>,
+                              instruction: &'a [u8],
+                              _: EnvId)
+                              -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RANDOM_UINT);
+        let high = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let low = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let (lbound, ubound) = (BigUint::from_bytes_be(low), BigUint::from_bytes_be(high));
+        if lbound >= ubound {
+            return Err(error_invalid_value!(high));
+        }
+        let n = (below(env.rng(), &(&ubound - &lbound)) + lbound).to_bytes_be();
+        let slice = alloc_and_write!(n.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_random_f64(&mut self,
+                             env: &mut Env<'a>,
+                             instruction: &'a [u8],
+                             _: EnvId)
+                             -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RANDOM_F64);
+
+        let f: f64 = env.rng().gen();
+        let bytes = f.pack();
+        let slice = alloc_and_write!(bytes.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_random_seed(&mut self,
+                              env: &mut Env<'a>,
+                              instruction: &'a [u8],
+                              _: EnvId)
+                              -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, RANDOM_SEED);
+        let seed = env.pop().ok_or_else(|| error_empty_stack!())?;
+
+        let seed = BigUint::from_bytes_be(seed).to_u64().ok_or_else(|| error_invalid_value!(seed))?;
+        env.seed_rng(seed);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::below;
+    use num_bigint::BigUint;
+    use rand::SeedableRng;
+    use rand::chacha::ChaChaRng;
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = ChaChaRng::from_seed(&[1, 2]);
+        for range in &[1u64, 2, 255, 256, 257, 1 << 40] {
+            let range = BigUint::from(*range);
+            for _ in 0..100 {
+                assert!(below(&mut rng, &range) < range);
+            }
+        }
+    }
+
+}
@@ -0,0 +1,169 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//!
+//! # Regular expressions
+//!
+//! This module matches UTF-8 strings against regular expressions.
+//! Compiled patterns are cached by the handler, so every scheduler
+//! keeps its own cache.
+//!
+
+instruction!(REGEX_MATCHQ, (a, b => c), b"\x8cREGEX/MATCH?");
+instruction!(REGEX_CAPTURES, (a, b => c), b"\x8eREGEX/CAPTURES");
+instruction!(REGEX_REPLACE, (a, b, c => d), b"\x8dREGEX/REPLACE");
+
+use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
+            STACK_TRUE, STACK_FALSE, offset_by_size, DispatchTable, _EMPTY};
+use regex::Regex;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::str;
+
+/// Number of compiled patterns kept by a handler, the cache
+/// is emptied once it is reached
+const MAX_CACHED_PATTERNS: usize = 1024;
+
+// Decodes a UTF-8 string
+macro_rules! utf8 {
+    ($bytes: expr) => {
+        str::from_utf8($bytes).or(Err(error_invalid_value!($bytes)))?
+    };
+}
+
+pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
+    patterns: HashMap<Vec<u8>, Regex>,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> Dispatcher<'a> for Handler<'a> {
+    fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
+        dispatch!(self, env, instruction, pid)
+    }
+}
+
+impl<'a> Handler<'a> {
+    pub fn new() -> Self {
+        let table = dispatch_table! {
+            REGEX_MATCHQ => Handler::handle_matchq,
+            REGEX_CAPTURES => Handler::handle_captures,
+            REGEX_REPLACE => Handler::handle_replace,
+        };
+        Handler { phantom: PhantomData, table: table, patterns: HashMap::new() }
+    }
+
+    /// Returns a compiled pattern, compiling it if it's not cached yet
+    fn compile(&mut self, pattern: &[u8]) -> Result<&Regex, Error> {
+        if !self.patterns.contains_key(pattern) {
+            let regex = Regex::new(utf8!(pattern)).or(Err(error_invalid_value!(pattern)))?;
+            if self.patterns.len() >= MAX_CACHED_PATTERNS {
+                self.patterns.clear();
+            }
+            self.patterns.insert(Vec::from(pattern), regex);
+        }
+        Ok(&self.patterns[pattern])
+    }
+
+    #[inline]
+    pub fn handle_matchq(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, REGEX_MATCHQ);
+        let pattern = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        if self.compile(pattern)?.is_match(s) {
+            env.push(STACK_TRUE);
+        } else {
+            env.push(STACK_FALSE);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_captures(&mut self,
+                           env: &mut Env<'a>,
+                           instruction: &'a [u8],
+                           _: EnvId)
+                           -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, REGEX_CAPTURES);
+        let pattern = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        let closure = match self.compile(pattern)?.captures(s) {
+            Some(captures) => {
+                let mut closure = Vec::new();
+                // groups that didn't participate in the match are empty
+                for group in captures.iter() {
+                    let group = group.map(|m| m.as_str()).unwrap_or("");
+                    write_size_header!(group, closure);
+                    closure.extend_from_slice(group.as_bytes());
+                }
+                closure
+            }
+            None => {
+                env.push(_EMPTY);
+                return Ok(());
+            }
+        };
+        let slice = alloc_and_write!(closure.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_replace(&mut self,
+                          env: &mut Env<'a>,
+                          instruction: &'a [u8],
+                          _: EnvId)
+                          -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, REGEX_REPLACE);
+        let replacement = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let pattern = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+        let rep = utf8!(replacement);
+
+        let result = self.compile(pattern)?.replace_all(s, rep).into_owned();
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Handler, MAX_CACHED_PATTERNS};
+
+    #[test]
+    fn compile_caches_patterns() {
+        let mut handler = Handler::new();
+        assert!(handler.compile(b"^a+$").unwrap().is_match("aaa"));
+        assert!(handler.compile(b"^a+$").unwrap().is_match("a"));
+        assert_eq!(handler.patterns.len(), 1);
+        assert!(handler.compile(b"(").is_err());
+        assert_eq!(handler.patterns.len(), 1);
+    }
+
+    #[test]
+    fn compile_cache_is_bounded() {
+        let mut handler = Handler::new();
+        for i in 0..MAX_CACHED_PATTERNS + 1 {
+            handler.compile(format!("^{}$", i).as_bytes()).unwrap();
+        }
+        assert_eq!(handler.patterns.len(), 1);
+    }
+
+}
@@ -6,7 +6,7 @@
 
 use pumpkinscript::{offset_by_size, binparser};
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK,
-            ERROR_INVALID_VALUE, TryInstruction};
+            ERROR_INVALID_VALUE, DispatchTable};
 
 use std::marker::PhantomData;
 
@@ -15,7 +15,7 @@ use num_bigint::BigUint;
 use num_traits::ToPrimitive;
 
 instruction!(THREEDROP, (a, b, c => ), b"\x853DROP");
-instruction!(THREEDUP, (a, b, c => a, b, c), b"\x843DUP");
+instruction!(THREEDUP, (a, b, c => a, b, c, a, b, c), b"\x843DUP");
 instruction!(DROP, (a => ), b"\x84DROP");
 instruction!(DUP, (a => a, a), b"\x83DUP");
 instruction!(SWAP, (a, b => b, a), b"\x84SWAP");
@@ -24,13 +24,14 @@ instruction!(ROT, (a, b, c  => b, c, a), b"\x83ROT");
 instruction!(TWOROT, (a, b, c, d, e, f  => c, d, e, f, a, b), b"\x842ROT");
 instruction!(OVER, (a, b => a, b, a), b"\x84OVER");
 instruction!(TWOOVER, (a, b, c, d => a, b, c, d, a, b), b"\x852OVER");
-instruction!(DEPTH, b"\x85DEPTH");
+instruction!(DEPTH, ( => a), b"\x85DEPTH");
 instruction!(UNWRAP, b"\x86UNWRAP");
 instruction!(WRAP, b"\x84WRAP");
 instruction!(PUSH, b"\x81<");
 instruction!(POP, b"\x81>");
 
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -38,29 +39,33 @@ builtins!("mod_stack.psc");
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_builtins(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_drop(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_dup(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_3drop(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_3dup(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_swap(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_2swap(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_rot(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_2rot(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_over(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_2over(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_depth(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_wrap(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_unwrap(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_push(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_pop(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let mut table = dispatch_table! {
+            DROP => Handler::handle_drop,
+            DUP => Handler::handle_dup,
+            THREEDROP => Handler::handle_3drop,
+            THREEDUP => Handler::handle_3dup,
+            SWAP => Handler::handle_swap,
+            TWOSWAP => Handler::handle_2swap,
+            ROT => Handler::handle_rot,
+            TWOROT => Handler::handle_2rot,
+            OVER => Handler::handle_over,
+            TWOOVER => Handler::handle_2over,
+            DEPTH => Handler::handle_depth,
+            WRAP => Handler::handle_wrap,
+            UNWRAP => Handler::handle_unwrap,
+            PUSH => Handler::handle_push,
+            POP => Handler::handle_pop,
+        };
+        for instruction in BUILTINS.keys() {
+            table.insert(*instruction, Handler::handle_builtins);
+        }
+        Handler { phantom: PhantomData, table: table }
     }
 
     handle_builtins!();
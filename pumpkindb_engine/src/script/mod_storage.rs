@@ -9,48 +9,71 @@
 //! This module handles all instructions and state related to handling storage
 //! capabilities
 //!
+//! It also loads [stored procedures](../procedures/index.html) into every
+//! new environment, reloading them only after they have been changed.
+//! Keys of stored procedures (`$PROCEDURE/`) and persistent timers
+//! (`$TIMER/`) can't be written with `ASSOC`, only with `PROCEDURE/INSTALL`,
+//! `PROCEDURE/DROP`, `TIMER/RECORD` and `TIMER/DROP`.
+//!
+//! Keys and values retrieved within a read transaction (`READ`) are not
+//! copied, they are borrowed from LMDB's memory map instead. Once the
+//! transaction ends, those still referenced by the environment are copied
+//! onto its heap. Within write transactions, everything retrieved is copied
+//! right away, as the transaction's own changes may move it.
+//!
 
 use lmdb;
 use lmdb::traits::{LmdbResultExt, AsLmdbBytes, FromLmdbBytes};
 use storage;
 use std::mem;
 use std::error::Error as StdError;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
 use super::{Env, EnvId, Dispatcher, PassResult, Error, STACK_TRUE, STACK_FALSE, offset_by_size,
             ERROR_EMPTY_STACK, ERROR_INVALID_VALUE, ERROR_DUPLICATE_KEY, ERROR_NO_TX,
-            ERROR_UNKNOWN_KEY, ERROR_DATABASE, ERROR_NO_VALUE, TryInstruction};
+            ERROR_UNKNOWN_KEY, ERROR_DATABASE, ERROR_NO_VALUE, ERROR_PERMISSION_DENIED,
+            DispatchTable, _EMPTY};
+use pumpkinscript::{self, binparser};
 use snowflake::ProcessUniqueId;
 use std::collections::BTreeMap;
 use storage::WriteTransactionContainer;
 use num_bigint::BigUint;
 use num_traits::FromPrimitive;
+use super::procedures;
 
 pub type CursorId = ProcessUniqueId;
 
-instruction!(TXID, b"\x84TXID");
+instruction!(TXID, ( => a), b"\x84TXID");
 instruction!(WRITE, b"\x85WRITE");
 instruction!(WRITE_END, b"\x80\x85WRITE"); // internal instruction
 
 instruction!(READ, b"\x84READ");
 instruction!(READ_END, b"\x80\x84READ"); // internal instruction
 
-instruction!(ASSOC, b"\x85ASSOC");
-instruction!(ASSOCQ, b"\x86ASSOC?");
-instruction!(RETR, b"\x84RETR");
+instruction!(ASSOC, (a, b => ), b"\x85ASSOC");
+instruction!(ASSOCQ, (a => b), b"\x86ASSOC?");
+instruction!(RETR, (a => b), b"\x84RETR");
+
+instruction!(CURSOR, ( => a), b"\x86CURSOR");
+instruction!(CURSOR_FIRST, (a => b), b"\x8CCURSOR/FIRST");
+instruction!(CURSOR_LAST, (a => b), b"\x8BCURSOR/LAST");
+instruction!(CURSOR_NEXT, (a => b), b"\x8BCURSOR/NEXT");
+instruction!(CURSOR_PREV, (a => b), b"\x8BCURSOR/PREV");
+instruction!(CURSOR_SEEK, (a, b => c), b"\x8BCURSOR/SEEK");
+instruction!(CURSOR_POSITIONEDQ, (a => b), b"\x92CURSOR/POSITIONED?");
+instruction!(CURSOR_KEY, (a => b), b"\x8ACURSOR/KEY");
+instruction!(CURSOR_VAL, (a => b), b"\x8ACURSOR/VAL");
 
-instruction!(CURSOR, b"\x86CURSOR");
-instruction!(CURSOR_FIRST, b"\x8CCURSOR/FIRST");
-instruction!(CURSOR_LAST, b"\x8BCURSOR/LAST");
-instruction!(CURSOR_NEXT, b"\x8BCURSOR/NEXT");
-instruction!(CURSOR_PREV, b"\x8BCURSOR/PREV");
-instruction!(CURSOR_SEEK, b"\x8BCURSOR/SEEK");
-instruction!(CURSOR_POSITIONEDQ, b"\x92CURSOR/POSITIONED?");
-instruction!(CURSOR_KEY, b"\x8ACURSOR/KEY");
-instruction!(CURSOR_VAL, b"\x8ACURSOR/VAL");
+instruction!(COMMIT, ( => ), b"\x86COMMIT");
 
-instruction!(COMMIT, b"\x86COMMIT");
+instruction!(MAXKEYSIZE, ( => a), b"\x92$SYSTEM/MAXKEYSIZE");
 
-instruction!(MAXKEYSIZE, b"\x92$SYSTEM/MAXKEYSIZE");
+instruction!(PROCEDURE_INSTALL, (a, b => c), b"\x91PROCEDURE/INSTALL");
+instruction!(PROCEDURE_DROP, (a => b), b"\x8ePROCEDURE/DROP");
+instruction!(PROCEDURES, ( => a), b"\x8aPROCEDURES");
+
+instruction!(TIMER_RECORD, (a, b, c => c), b"\x8cTIMER/RECORD");
+instruction!(TIMER_DROP, (a => ), b"\x8aTIMER/DROP");
 
 #[derive(PartialEq, Debug)]
 enum TxType {
@@ -119,11 +142,18 @@ use super::super::nvmem::NonVolatileMemory;
 pub struct Handler<'a, T, N>
     where T : AsRef<storage::Storage<'a>> + 'a,
           N : NonVolatileMemory {
+    pub(crate) table: DispatchTable<'a, Handler<'a, T, N>>,
     db: T,
     txns: HashMap<EnvId, Vec<Txn<'a>>>,
     cursors: BTreeMap<(EnvId, Vec<u8>), (TxType, lmdb::Cursor<'a, 'a>)>,
     maxkeysize: Vec<u8>,
     timestamp: Arc<timestamp::Timestamp<N>>,
+    // stored procedures as of the generation they were loaded at
+    // (see `Storage::procedures_generation`)
+    procedures: BTreeMap<Vec<u8>, procedures::Procedure>,
+    procedures_generation: Option<usize>,
+    // environments whose write transaction has changed stored procedures
+    procedures_changed: HashSet<EnvId>,
 }
 
 macro_rules! read_or_write_transaction {
@@ -190,12 +220,56 @@ macro_rules! cursor_map_op {
     }};
 }
 
+/// Returns a value retrieved from the database, borrowing it from
+/// the memory map if `$borrow` is `true` (within a read transaction)
+///
+/// Borrowed values are only valid until the end of the transaction,
+/// so every holder of `'a` slices in `Env` (program, stack, queue,
+/// dictionary, error trace, etc.) must be covered by `Env::relocate`,
+/// which `retain_borrowed` uses to copy them onto the heap.
+macro_rules! stored_value {
+    ($borrow: expr, $val: expr, $env: expr) => {
+        if $borrow {
+            // valid until the end of the read transaction
+            unsafe { mem::transmute::<&[u8], &'a [u8]>($val) }
+        } else {
+            alloc_and_write!($val, $env)
+        }
+    };
+}
+
 builtins!("mod_storage.psc");
 
+/// Key prefix of persistent timers
+const TIMER_PREFIX: &'static [u8] = b"$TIMER/";
+
+/// Key prefixes reserved for stored procedures and persistent timers.
+/// `ASSOC` can't write to them, so that versioning of procedures and
+/// capabilities of timers can't be bypassed.
+const RESERVED_PREFIXES: &'static [&'static [u8]] = &[procedures::PREFIX, TIMER_PREFIX];
+
 impl<'a, T, N> Dispatcher<'a> for Handler<'a, T, N>
     where T : AsRef<storage::Storage<'a>> + 'a,
           N : NonVolatileMemory {
-    fn done(&mut self, _: &mut Env, pid: EnvId) {
+    fn init(&mut self, env: &mut Env<'a>, _: EnvId) -> Result<(), Error> {
+        self.load_procedures();
+        for (name, procedure) in self.procedures.iter() {
+            let name_slice = env.alloc(name.len())?;
+            name_slice.copy_from_slice(name);
+            let definition_slice = env.alloc(procedure.definition.len())?;
+            definition_slice.copy_from_slice(&procedure.definition);
+            env.procedures.insert(name_slice, definition_slice);
+        }
+        Ok(())
+    }
+
+    fn done(&mut self, env: &mut Env<'a>, pid: EnvId) {
+        self.procedures_changed.remove(&pid);
+        let reading = self.txns.get(&pid)
+            .map_or(false, |vec| vec.iter().any(|txn| txn.tx_type() == TxType::Read));
+        if reading {
+            self.retain_borrowed(env);
+        }
         self.txns.get_mut(&pid)
             .and_then(|vec| {
                 while vec.len() > 0 {
@@ -207,25 +281,7 @@ impl<'a, T, N> Dispatcher<'a> for Handler<'a, T, N>
     }
 
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_builtins(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_write(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_read(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_txid(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_assoc(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_assocq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_retr(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_commit(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_first(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_next(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_prev(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_last(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_seek(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_positionedq(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_key(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_cursor_val(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_maxkeysize(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
@@ -233,16 +289,104 @@ impl<'a, T, N> Handler<'a, T, N>
     where T : AsRef<storage::Storage<'a>> + 'a,
           N : NonVolatileMemory {
     pub fn new(db: T, timestamp: Arc<timestamp::Timestamp<N>>) -> Self {
+        let mut table = dispatch_table! {
+            WRITE => Handler::handle_write,
+            WRITE_END => Handler::handle_write,
+            READ => Handler::handle_read,
+            READ_END => Handler::handle_read,
+            TXID => Handler::handle_txid,
+            ASSOC => Handler::handle_assoc,
+            ASSOCQ => Handler::handle_assocq,
+            RETR => Handler::handle_retr,
+            COMMIT => Handler::handle_commit,
+            CURSOR => Handler::handle_cursor,
+            CURSOR_FIRST => Handler::handle_cursor_first,
+            CURSOR_NEXT => Handler::handle_cursor_next,
+            CURSOR_PREV => Handler::handle_cursor_prev,
+            CURSOR_LAST => Handler::handle_cursor_last,
+            CURSOR_SEEK => Handler::handle_cursor_seek,
+            CURSOR_POSITIONEDQ => Handler::handle_cursor_positionedq,
+            CURSOR_KEY => Handler::handle_cursor_key,
+            CURSOR_VAL => Handler::handle_cursor_val,
+            MAXKEYSIZE => Handler::handle_maxkeysize,
+            PROCEDURE_INSTALL => Handler::handle_procedure_install,
+            PROCEDURE_DROP => Handler::handle_procedure_drop,
+            PROCEDURES => Handler::handle_procedures,
+            TIMER_RECORD => Handler::handle_timer_record,
+            TIMER_DROP => Handler::handle_timer_drop,
+        };
+        for instruction in BUILTINS.keys() {
+            table.insert(*instruction, Handler::handle_builtins);
+        }
         let maxkeysize = BigUint::from_u32(db.as_ref().env.maxkeysize()).unwrap().to_bytes_be();
         Handler {
+            table: table,
             db: db,
             txns: HashMap::new(),
             cursors: BTreeMap::new(),
             maxkeysize: maxkeysize,
             timestamp,
+            procedures: BTreeMap::new(),
+            procedures_generation: None,
+            procedures_changed: HashSet::new(),
         }
     }
 
+    /// Loads stored procedures, unless they haven't been
+    /// changed since they were loaded last time
+    fn load_procedures(&mut self) {
+        let db = self.db.as_ref();
+        // read before scanning, so that a change committed
+        // in the meantime causes another reload
+        let generation = db.procedures_generation.load(Ordering::SeqCst);
+        if self.procedures_generation == Some(generation) {
+            return;
+        }
+        // if no read transaction is available, previously
+        // loaded procedures will be used
+        let txn = match db.read() {
+            Some(Ok(txn)) => txn,
+            _ => return,
+        };
+        let result = txn.cursor(&db.db)
+            .and_then(|mut cursor| procedures::scan(&txn.access(), &mut cursor));
+        match result {
+            Ok(procedures) => {
+                self.procedures = procedures;
+                self.procedures_generation = Some(generation);
+            }
+            Err(err) => warn!("Can't load stored procedures: {}", err.description()),
+        }
+    }
+
+    /// Returns procedures installed as of the current transaction
+    fn installed_procedures(&self, pid: EnvId) -> Result<BTreeMap<Vec<u8>, procedures::Procedure>, Error> {
+        let db = self.db.as_ref();
+        let txn = read_or_write_transaction!(self, pid);
+        let result = match txn.cursor(&db.db) {
+            Ok(mut cursor) => {
+                match txn.access() {
+                    Accessor::Const(acc) => procedures::scan(&acc, &mut cursor),
+                    Accessor::Write(acc) => procedures::scan(&acc, &mut cursor),
+                }
+            }
+            Err(err) => Err(err),
+        };
+        result.map_err(|err| error_database!(err))
+    }
+
+    /// Copies values borrowed from the memory map that are still referenced
+    /// by the environment onto its heap
+    fn retain_borrowed(&self, env: &mut Env<'a>) {
+        let info = self.db.as_ref().env.info().expect("can't get environment info");
+        let start = info.mapaddr as usize;
+        let end = start + info.mapsize;
+        env.relocate(|slice| {
+            let ptr = slice.as_ptr() as usize;
+            ptr >= start && ptr < end
+        });
+    }
+
     fn new_txid(&self, env: &mut Env<'a>) -> Result<TxnId<'a>, super::Error> {
         let now = self.timestamp.hlc();
         let slice = env.alloc(16);
@@ -272,7 +416,11 @@ impl<'a, T, N> Handler<'a, T, N>
                                ERROR_DATABASE));
                 }
                 match self.db.as_ref().write() {
-                    None => Err(Error::Reschedule),
+                    None => {
+                        // leave the stack intact for the next attempt
+                        env.push(v);
+                        Err(Error::Reschedule)
+                    }
                     Some(result) =>
                         match result {
                             Err(e) => Err(error_database!(e)),
@@ -290,6 +438,7 @@ impl<'a, T, N> Handler<'a, T, N>
                 }
             }
             WRITE_END => {
+                self.procedures_changed.remove(&pid);
                 match self.txns.get_mut(&pid).unwrap().pop() {
                     Some(_) => {
                         self.cursors = mem::replace(&mut self.cursors,
@@ -314,7 +463,11 @@ impl<'a, T, N> Handler<'a, T, N>
             READ => {
                 let v = env.pop().ok_or_else(|| error_empty_stack!())?;
                 match self.db.as_ref().read() {
-                    None => Err(Error::Reschedule),
+                    None => {
+                        // leave the stack intact for the next attempt
+                        env.push(v);
+                        Err(Error::Reschedule)
+                    }
                     Some(result) =>
                         match result {
                             Err(e) => Err(error_database!(e)),
@@ -332,6 +485,7 @@ impl<'a, T, N> Handler<'a, T, N>
                 }
             }
             READ_END => {
+                self.retain_borrowed(env);
                 match self.txns.get_mut(&pid).unwrap().pop() {
                     Some(_) => {
                         self.cursors = mem::replace(&mut self.cursors,
@@ -348,7 +502,7 @@ impl<'a, T, N> Handler<'a, T, N>
 
 
     #[inline]
-    pub fn handle_txid(&self,
+    pub fn handle_txid(&mut self,
                        env: &mut Env<'a>,
                        instruction: &'a [u8],
                        pid: EnvId)
@@ -364,7 +518,7 @@ impl<'a, T, N> Handler<'a, T, N>
     }
 
     #[inline]
-    pub fn handle_assoc(&self,
+    pub fn handle_assoc(&mut self,
 						env: &mut Env<'a>,
 						instruction: &'a [u8],
 						pid: EnvId)
@@ -380,6 +534,10 @@ impl<'a, T, N> Handler<'a, T, N>
                 let value = env.pop().ok_or_else(|| error_empty_stack!())?;
                 let key = env.pop().ok_or_else(|| error_empty_stack!())?;
 
+                if RESERVED_PREFIXES.iter().any(|prefix| key.starts_with(prefix)) {
+                    return Err(error_permission_denied!(instruction));
+                }
+
                 let mut access = txn.access();
 
                 match access.put(&self.db.as_ref().db, key, value, lmdb::put::NOOVERWRITE) {
@@ -394,7 +552,7 @@ impl<'a, T, N> Handler<'a, T, N>
 
     #[inline]
     pub fn handle_commit(&mut self,
-						 _: &Env<'a>,
+						 _: &mut Env<'a>,
 						 instruction: &'a [u8],
 						 pid: EnvId)
 						 -> PassResult<'a> {
@@ -403,7 +561,13 @@ impl<'a, T, N> Handler<'a, T, N>
             .and_then(|vec| vec.pop()) {
             Some(Txn::Write(txn, _)) => {
                 match txn.commit() {
-                    Ok(_) => Ok(()),
+                    Ok(_) => {
+                        // let every scheduler reload stored procedures
+                        if self.procedures_changed.remove(&pid) {
+                            self.db.as_ref().procedures_generation.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Ok(())
+                    }
                     Err(reason) => Err(error_database!(reason))
                 }
             },
@@ -424,13 +588,14 @@ impl<'a, T, N> Handler<'a, T, N>
                        -> PassResult<'a> {
         return_unless_instructions_equal!(instruction, RETR);
         let key = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let borrow = tx_type!(self, pid) == TxType::Read;
         self.txns.get(&pid)
             .and_then(|v| Some(&v[v.len() - 1]))
             .and_then(|txn| Some(txn.access()))
             .map_or_else(|| Err(error_no_transaction!()), |acc| {
                 match acc.get::<[u8], [u8]>(&self.db.as_ref().db, key) {
                     Ok(Some(val)) => {
-                        let slice = alloc_and_write!(val, env);
+                        let slice = stored_value!(borrow, val, env);
                         env.push(slice);
                         Ok(())
                     },
@@ -582,10 +747,10 @@ impl<'a, T, N> Handler<'a, T, N>
                              pid: EnvId)
                              -> PassResult<'a> {
         return_unless_instructions_equal!(instruction, CURSOR_KEY);
+        let borrow = tx_type!(self, pid) == TxType::Read;
         cursor_map_op!(self, env, pid, get_current, (),
            |(key, _) | {
-              let slice = alloc_slice!(key.len(), env);
-              slice.copy_from_slice(key);
+              let slice = stored_value!(borrow, key, env);
               env.push(slice);
               Ok(())
         }, |_| error_no_value!())
@@ -598,15 +763,150 @@ impl<'a, T, N> Handler<'a, T, N>
                              pid: EnvId)
                              -> PassResult<'a> {
         return_unless_instructions_equal!(instruction, CURSOR_VAL);
+        let borrow = tx_type!(self, pid) == TxType::Read;
         cursor_map_op!(self, env, pid, get_current, (),
            |(_, val) | {
-              let slice = alloc_slice!(val.len(), env);
-              slice.copy_from_slice(val);
+              let slice = stored_value!(borrow, val, env);
               env.push(slice);
               Ok(())
         }, |_| error_no_value!())
     }
 
+    #[inline]
+    pub fn handle_procedure_install(&mut self,
+                                    env: &mut Env<'a>,
+                                    instruction: &'a [u8],
+                                    pid: EnvId)
+                                    -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, PROCEDURE_INSTALL);
+        let name = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let definition = env.pop().ok_or_else(|| error_empty_stack!())?;
+        match binparser::instruction(name) {
+            pumpkinscript::ParseResult::Done(rest, _) if rest.len() == 0 => (),
+            _ => return Err(error_invalid_value!(name)),
+        }
+        // an empty definition marks a dropped procedure
+        if definition.len() == 0 {
+            return Err(error_invalid_value!(definition));
+        }
+        self.put_procedure(env, pid, name, definition)
+    }
+
+    #[inline]
+    pub fn handle_procedure_drop(&mut self,
+                                 env: &mut Env<'a>,
+                                 instruction: &'a [u8],
+                                 pid: EnvId)
+                                 -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, PROCEDURE_DROP);
+        let name = env.pop().ok_or_else(|| error_empty_stack!())?;
+        if !self.installed_procedures(pid)?.contains_key(name) {
+            return Err(error_unknown_key!(name));
+        }
+        self.put_procedure(env, pid, name, _EMPTY)
+    }
+
+    /// Records a new version of a procedure and pushes the version
+    fn put_procedure(&mut self, env: &mut Env<'a>, pid: EnvId, name: &[u8], definition: &[u8]) -> PassResult<'a> {
+        let version = self.new_txid(env)?;
+        match self.txns.get(&pid)
+            .and_then(|v| Some(&v[v.len() - 1])) {
+            Some(&Txn::Write(ref txn, _)) => {
+                let mut access = txn.access();
+                let key = procedures::key(name, version);
+                match access.put(&self.db.as_ref().db, key.as_slice(), definition, lmdb::put::NOOVERWRITE) {
+                    Ok(_) => {
+                        self.procedures_changed.insert(pid);
+                        env.push(version);
+                        Ok(())
+                    }
+                    Err(lmdb::Error::Code(code)) if lmdb::error::KEYEXIST == code => {
+                        Err(error_duplicate_key!(key.as_slice()))
+                    }
+                    Err(err) => Err(error_database!(err)),
+                }
+            }
+            _ => Err(error_no_transaction!()),
+        }
+    }
+
+    #[inline]
+    pub fn handle_procedures(&mut self,
+                             env: &mut Env<'a>,
+                             instruction: &'a [u8],
+                             pid: EnvId)
+                             -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, PROCEDURES);
+        let installed = self.installed_procedures(pid)?;
+        let encoded = procedures::encode(&installed);
+        let slice = alloc_and_write!(encoded.as_slice(), env);
+        env.push(slice);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_timer_record(&mut self,
+                               env: &mut Env<'a>,
+                               instruction: &'a [u8],
+                               pid: EnvId)
+                               -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, TIMER_RECORD);
+        let handle = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let interval = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let closure = env.pop().ok_or_else(|| error_empty_stack!())?;
+        match self.txns.get(&pid)
+            .and_then(|v| Some(&v[v.len() - 1])) {
+            Some(&Txn::Write(ref txn, _)) => {
+                let mut key = Vec::from(TIMER_PREFIX);
+                key.extend_from_slice(handle);
+                // the timer is restored with the capabilities
+                // of the environment that registered it
+                let capabilities = env.capabilities.encode();
+                let mut value = Vec::new();
+                for item in [closure, interval, handle, capabilities.as_slice()].iter() {
+                    write_size_header!(item, value);
+                    value.extend_from_slice(item);
+                }
+                let mut access = txn.access();
+                match access.put(&self.db.as_ref().db, key.as_slice(), value.as_slice(), lmdb::put::NOOVERWRITE) {
+                    Ok(_) => {
+                        env.push(handle);
+                        Ok(())
+                    }
+                    Err(lmdb::Error::Code(code)) if lmdb::error::KEYEXIST == code => {
+                        Err(error_duplicate_key!(key.as_slice()))
+                    }
+                    Err(err) => Err(error_database!(err)),
+                }
+            }
+            _ => Err(error_no_transaction!()),
+        }
+    }
+
+    #[inline]
+    pub fn handle_timer_drop(&mut self,
+                             env: &mut Env<'a>,
+                             instruction: &'a [u8],
+                             pid: EnvId)
+                             -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, TIMER_DROP);
+        let handle = env.pop().ok_or_else(|| error_empty_stack!())?;
+        match self.txns.get(&pid)
+            .and_then(|v| Some(&v[v.len() - 1])) {
+            Some(&Txn::Write(ref txn, _)) => {
+                let mut key = Vec::from(TIMER_PREFIX);
+                key.extend_from_slice(handle);
+                let mut access = txn.access();
+                // dropping a timer that isn't recorded is fine
+                match access.del_key(&self.db.as_ref().db, key.as_slice()).to_opt() {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(error_database!(err)),
+                }
+            }
+            _ => Err(error_no_transaction!()),
+        }
+    }
+
     #[inline]
     pub fn handle_maxkeysize(&mut self,
                              env: &mut Env<'a>,
@@ -626,7 +926,7 @@ mod tests {
     use pumpkinscript::{parse, offset_by_size};
     use messaging;
     use nvmem::{MmapedFile, MmapedRegion, NonVolatileMemory};
-    use script::{Env, Scheduler, Error, RequestMessage, ResponseMessage, EnvId, dispatcher};
+    use script::{Env, Scheduler, Error, RequestMessage, ResponseMessage, EnvId, dispatcher, trace};
 
     use byteorder::WriteBytesExt;
     use std::sync::mpsc;
@@ -668,6 +968,101 @@ mod tests {
         });
     }
 
+    #[test]
+    fn values_outliving_read_txn() {
+        eval!("[\"key\" \"value\" ASSOC \"code\" [0x01] ASSOC COMMIT] WRITE \
+               [\"code\" RETR 'c DEF \"key\" RETR 1 3 SLICE \"key\" RETR] READ c",
+              env,
+              result,
+              {
+                  assert_eq!(Vec::from(env.pop().unwrap()), parsed_data!("0x01"));
+                  assert_eq!(Vec::from(env.pop().unwrap()), parsed_data!("\"value\""));
+                  assert_eq!(Vec::from(env.pop().unwrap()), parsed_data!("\"al\""));
+                  assert_eq!(env.pop(), None);
+              });
+    }
+
+    #[test]
+    fn trace_outliving_read_txn() {
+        eval!("[\"code\" [[DROP] 'FOO DEF] ASSOC COMMIT] WRITE \
+               [\"code\" RETR EVAL [FOO] TRY DROP] READ ERROR/TRACE",
+              env,
+              result,
+              {
+                  // the definition of `FOO` has been moved off the memory map
+                  // along with the trace
+                  let trace = trace::decode(env.pop().unwrap()).unwrap();
+                  assert_eq!(trace[0].instruction, Vec::from(&b"\x84DROP"[..]));
+                  assert_eq!(trace[0].word, Some(Vec::from(&b"\x83FOO"[..])));
+              });
+    }
+
+    #[test]
+    #[cfg(feature = "mod_hlc")]
+    fn restored_timers_stay_restricted() {
+        use messaging::Subscriber;
+        use script::{SchedulerHandle, EnvOptions, capabilities};
+        use std::time::Duration;
+
+        let dir = TempDir::new("pumpkindb").unwrap();
+        let path = dir.path().to_str().unwrap();
+        fs::create_dir_all(path).expect("can't create directory");
+        let env = unsafe {
+            lmdb::EnvBuilder::new()
+                .expect("can't create env builder")
+                .open(path, lmdb::open::NOTLS, 0o600)
+                .expect("can't open env")
+        };
+        let db = Arc::new(storage::Storage::new(&env));
+        crossbeam::scope(|scope| {
+            let mut nvmem = MmapedFile::new_anonymous(20).unwrap();
+            let region = nvmem.claim(20).unwrap();
+            let timestamp = Arc::new(timestamp::Timestamp::new(region));
+            let mut simple = messaging::Simple::new();
+            let messaging_accessor = simple.accessor();
+            let publisher_thread = scope.spawn(move || simple.run());
+            let (mut scheduler, sender) = Scheduler::new(
+                dispatcher::StandardDispatcher::new(db.clone(), messaging_accessor.clone(),
+                                                    messaging_accessor.clone(), timestamp.clone()));
+            let handle = scope.spawn(move || scheduler.run());
+
+            // register a persistent timer from a restricted environment
+            // and stop it, leaving only its record behind
+            let (callback, receiver) = mpsc::channel::<ResponseMessage>();
+            let (published, _) = mpsc::channel();
+            let options = EnvOptions {
+                capabilities: capabilities::Capabilities::profile("no-clock-observe").unwrap(),
+                ..Default::default()
+            };
+            sender.schedule_env_with_options(EnvId::new(),
+                                             parse("[[HLC HLC/OBSERVE] TRY UNWRAP \"restored\" PUBLISH] \
+                                                    10 EVERY/PERSISTENT CANCEL").unwrap(),
+                                             options, callback.clone(), Box::new(published.clone()));
+            match receiver.recv() {
+                Ok(ResponseMessage::EnvTerminated(..)) => (),
+                _ => panic!("timer was not registered"),
+            }
+
+            // restore it from an unrestricted environment
+            let (subscription, restored) = mpsc::channel();
+            messaging_accessor.subscribe(b"restored", Box::new(subscription));
+            sender.schedule_env(EnvId::new(), parse("TIMERS/RESTORE").unwrap(),
+                                callback, Box::new(published));
+            match receiver.recv() {
+                Ok(ResponseMessage::EnvTerminated(..)) => (),
+                _ => panic!("timers were not restored"),
+            }
+
+            let (_, message) = restored.recv_timeout(Duration::from_secs(1)).unwrap();
+            sender.shutdown();
+            messaging_accessor.shutdown();
+            let _ = handle.join();
+            let _ = publisher_thread.join();
+            // HLC/OBSERVE is still denied
+            assert_eq!(message, parsed_data!("0x0C"));
+        });
+    }
+
     use test::Bencher;
 
     #[bench]
@@ -5,25 +5,57 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, TryInstruction};
+            STACK_TRUE, STACK_FALSE, offset_by_size, DispatchTable, _EMPTY};
 
-use ::pumpkinscript::Packable;
+use ::pumpkinscript::{self, Packable, Decimal, binparser};
 use core::str::FromStr;
 use std::marker::PhantomData;
+use std::str;
 use num_bigint::{BigUint, BigInt};
+use num_traits::ToPrimitive;
+use unicode_normalization::UnicodeNormalization;
 
-instruction!(STRING_TO_UINT, b"\x8dSTRING/->UINT");
-instruction!(STRING_TO_INT, b"\x8cSTRING/->INT");
-instruction!(STRING_TO_UINT8, b"\x8eSTRING/->UINT8");
-instruction!(STRING_TO_INT8 ,b"\x8dSTRING/->INT8");
-instruction!(STRING_TO_UINT16, b"\x8fSTRING/->UINT16");
-instruction!(STRING_TO_INT16, b"\x8eSTRING/->INT16");
-instruction!(STRING_TO_UINT32, b"\x8fSTRING/->UINT32");
-instruction!(STRING_TO_INT32, b"\x8eSTRING/->INT32");
-instruction!(STRING_TO_UINT64, b"\x8fSTRING/->UINT64");
-instruction!(STRING_TO_INT64, b"\x8eSTRING/->INT64");
-instruction!(STRING_TO_F32, b"\x8cSTRING/->F32");
-instruction!(STRING_TO_F64, b"\x8cSTRING/->F64");
+#[cfg(feature = "mod_numbers")]
+use super::mod_numbers;
+#[cfg(feature = "mod_uuid")]
+use super::mod_uuid;
+#[cfg(feature = "mod_hlc")]
+use super::mod_hlc;
+
+instruction!(STRING_TO_UINT, (a => b), b"\x8dSTRING/->UINT");
+instruction!(STRING_TO_INT, (a => b), b"\x8cSTRING/->INT");
+instruction!(STRING_TO_UINT8, (a => b), b"\x8eSTRING/->UINT8");
+instruction!(STRING_TO_INT8, (a => b), b"\x8dSTRING/->INT8");
+instruction!(STRING_TO_UINT16, (a => b), b"\x8fSTRING/->UINT16");
+instruction!(STRING_TO_INT16, (a => b), b"\x8eSTRING/->INT16");
+instruction!(STRING_TO_UINT32, (a => b), b"\x8fSTRING/->UINT32");
+instruction!(STRING_TO_INT32, (a => b), b"\x8eSTRING/->INT32");
+instruction!(STRING_TO_UINT64, (a => b), b"\x8fSTRING/->UINT64");
+instruction!(STRING_TO_INT64, (a => b), b"\x8eSTRING/->INT64");
+instruction!(STRING_TO_F32, (a => b), b"\x8cSTRING/->F32");
+instruction!(STRING_TO_F64, (a => b), b"\x8cSTRING/->F64");
+instruction!(STRING_TO_DECIMAL, (a => b), b"\x90STRING/->DECIMAL");
+
+// Text
+instruction!(STRING_LENGTH, (a => b), b"\x8dSTRING/LENGTH");
+instruction!(STRING_SLICE, (a, b, c => d), b"\x8cSTRING/SLICE");
+instruction!(STRING_UPPERCASE, (a => b), b"\x90STRING/UPPERCASE");
+instruction!(STRING_LOWERCASE, (a => b), b"\x90STRING/LOWERCASE");
+instruction!(STRING_TRIM, (a => b), b"\x8bSTRING/TRIM");
+instruction!(STRING_SPLIT, (a, b => c), b"\x8cSTRING/SPLIT");
+instruction!(STRING_JOIN, (a, b => c), b"\x8bSTRING/JOIN");
+instruction!(STRING_FIND, (a, b => c), b"\x8bSTRING/FIND");
+instruction!(STRING_CONTAINSQ, (a, b => c), b"\x90STRING/CONTAINS?");
+instruction!(STRING_REPLACE, (a, b, c => d), b"\x8eSTRING/REPLACE");
+
+// Formatting
+instruction!(FORMAT, b"\x86FORMAT");
+
+// Unicode normalization
+instruction!(STRING_NFC, (a => b), b"\x8aSTRING/NFC");
+instruction!(STRING_NFD, (a => b), b"\x8aSTRING/NFD");
+instruction!(STRING_NFKC, (a => b), b"\x8bSTRING/NFKC");
+instruction!(STRING_NFKD, (a => b), b"\x8bSTRING/NFKD");
 
 macro_rules! to_sized {
     ($env: expr, $type: ident) => {{
@@ -34,22 +66,164 @@ macro_rules! to_sized {
     }}
 }
 
+// Decodes a UTF-8 string
+macro_rules! utf8 {
+    ($bytes: expr) => {
+        str::from_utf8($bytes).or(Err(error_invalid_value!($bytes)))?
+    };
+}
+
+// Decodes a character index
+macro_rules! char_index {
+    ($value: expr) => {
+        BigUint::from_bytes_be($value).to_u64()
+            .map(|i| i as usize).ok_or(error_invalid_value!($value))?
+    };
+}
+
+/// Returns the byte offset of the character at `index`, where the index
+/// equal to the number of characters points at the end of the string
+fn byte_offset(s: &str, index: usize) -> Option<usize> {
+    s.char_indices().map(|(offset, _)| offset).chain(Some(s.len())).nth(index)
+}
+
+/// Types of `FORMAT` placeholders
+const PLACEHOLDERS: &'static [&'static str] = &["str", "hex", "uuid", "hlc", "decimal",
+                                                "uint", "u8", "u16", "u32", "u64",
+                                                "int", "i8", "i16", "i32", "i64",
+                                                "f32", "f64"];
+
+/// Largest number of decimal places a `FORMAT` placeholder can request
+const MAX_PRECISION: usize = 64;
+
+/// Part of a `FORMAT` template
+enum Segment<'t> {
+    Text(&'t str),
+    /// Placeholder's type and precision
+    Placeholder(&'t str, Option<usize>),
+}
+
+/// Splits a `FORMAT` template into text and placeholders
+fn parse_template(template: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(|c: char| c == '{' || c == '}') {
+        if i > 0 {
+            segments.push(Segment::Text(&rest[..i]));
+        }
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            segments.push(Segment::Text(&tail[..1]));
+            rest = &tail[2..];
+            continue;
+        }
+        let end = match tail.find('}') {
+            Some(end) if tail.starts_with('{') => end,
+            _ => return None,
+        };
+        let spec = &tail[1..end];
+        let placeholder = match spec.find(':') {
+            None if PLACEHOLDERS.iter().any(|name| *name == spec) =>
+                Segment::Placeholder(spec, None),
+            None => return None,
+            Some(colon) if spec[colon + 1..].starts_with('.') => {
+                let (name, precision) = (&spec[..colon], &spec[colon + 2..]);
+                match precision.parse::<usize>() {
+                    Ok(precision) if precision <= MAX_PRECISION &&
+                                     (name == "f32" || name == "f64") =>
+                        Segment::Placeholder(name, Some(precision)),
+                    _ => return None,
+                }
+            }
+            Some(_) => return None,
+        };
+        segments.push(placeholder);
+        rest = &tail[end + 1..];
+    }
+    if rest.len() > 0 {
+        segments.push(Segment::Text(rest));
+    }
+    Some(segments)
+}
+
+/// Formats a value for a `FORMAT` placeholder
+fn format_value(name: &str, precision: Option<usize>, value: &[u8]) -> Option<String> {
+    match name {
+        "str" => str::from_utf8(value).ok().map(String::from),
+        "hex" => Some(value.iter().map(|b| format!("{:02x}", b)).collect()),
+        #[cfg(feature = "mod_uuid")]
+        "uuid" => mod_uuid::to_string(value),
+        #[cfg(feature = "mod_hlc")]
+        "hlc" => mod_hlc::to_string(value),
+        #[cfg(feature = "mod_numbers")]
+        _ => {
+            let numeric: &[u8] = match name {
+                "uint" => b"UINT",
+                "int" => b"INT",
+                "u8" => b"UINT8",
+                "u16" => b"UINT16",
+                "u32" => b"UINT32",
+                "u64" => b"UINT64",
+                "i8" => b"INT8",
+                "i16" => b"INT16",
+                "i32" => b"INT32",
+                "i64" => b"INT64",
+                "f32" => b"F32",
+                "f64" => b"F64",
+                "decimal" => b"DECIMAL",
+                _ => return None,
+            };
+            mod_numbers::to_string(numeric, value, precision)
+        }
+        #[cfg(not(feature = "mod_numbers"))]
+        _ => None,
+    }
+}
+
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_to_uint(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_to_int(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_to_sized_num(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let table = dispatch_table! {
+            STRING_TO_UINT => Handler::handle_to_uint,
+            STRING_TO_INT => Handler::handle_to_int,
+            STRING_TO_UINT8 => Handler::handle_to_sized_num,
+            STRING_TO_INT8 => Handler::handle_to_sized_num,
+            STRING_TO_UINT16 => Handler::handle_to_sized_num,
+            STRING_TO_INT16 => Handler::handle_to_sized_num,
+            STRING_TO_UINT32 => Handler::handle_to_sized_num,
+            STRING_TO_INT32 => Handler::handle_to_sized_num,
+            STRING_TO_UINT64 => Handler::handle_to_sized_num,
+            STRING_TO_INT64 => Handler::handle_to_sized_num,
+            STRING_TO_F32 => Handler::handle_to_sized_num,
+            STRING_TO_F64 => Handler::handle_to_sized_num,
+            STRING_TO_DECIMAL => Handler::handle_to_sized_num,
+            STRING_LENGTH => Handler::handle_length,
+            STRING_SLICE => Handler::handle_slice,
+            STRING_UPPERCASE => Handler::handle_case,
+            STRING_LOWERCASE => Handler::handle_case,
+            STRING_TRIM => Handler::handle_trim,
+            STRING_SPLIT => Handler::handle_split,
+            STRING_JOIN => Handler::handle_join,
+            STRING_FIND => Handler::handle_find,
+            STRING_CONTAINSQ => Handler::handle_containsq,
+            STRING_REPLACE => Handler::handle_replace,
+            FORMAT => Handler::handle_format,
+            STRING_NFC => Handler::handle_normalization,
+            STRING_NFD => Handler::handle_normalization,
+            STRING_NFKC => Handler::handle_normalization,
+            STRING_NFKD => Handler::handle_normalization,
+        };
+        Handler { phantom: PhantomData, table: table }
     }
     
     #[inline]
@@ -105,6 +279,7 @@ impl<'a> Handler<'a> {
             STRING_TO_INT64   => to_sized!(env, i64),
             STRING_TO_F32     => to_sized!(env, f32),
             STRING_TO_F64     => to_sized!(env, f64),
+            STRING_TO_DECIMAL => to_sized!(env, Decimal),
             
             _ => return Err(Error::UnknownInstruction),
         };        
@@ -115,4 +290,270 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
+    #[inline]
+    pub fn handle_length(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_LENGTH);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        let len = BigUint::from(s.chars().count()).to_bytes_be();
+        let slice = alloc_and_write!(len.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_slice(&mut self,
+                        env: &mut Env<'a>,
+                        instruction: &'a [u8],
+                        _: EnvId)
+                        -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_SLICE);
+        let end = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let start = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        let start_index = char_index!(start);
+        let end_index = char_index!(end);
+        if start_index > end_index {
+            return Err(error_invalid_value!(start));
+        }
+        let from = byte_offset(s, start_index).ok_or(error_invalid_value!(start))?;
+        let to = byte_offset(s, end_index).ok_or(error_invalid_value!(end))?;
+
+        env.push(&a[from..to]);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_case(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        let upper = match instruction {
+            STRING_UPPERCASE => true,
+            STRING_LOWERCASE => false,
+            _ => return Err(Error::UnknownInstruction),
+        };
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        let result = if upper { s.to_uppercase() } else { s.to_lowercase() };
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_trim(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_TRIM);
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        env.push(s.trim().as_bytes());
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_split(&mut self,
+                        env: &mut Env<'a>,
+                        instruction: &'a [u8],
+                        _: EnvId)
+                        -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_SPLIT);
+        let separator = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+        let sep = utf8!(separator);
+        if sep.is_empty() {
+            return Err(error_invalid_value!(separator));
+        }
+
+        let mut closure = Vec::new();
+        for part in s.split(sep) {
+            write_size_header!(part, closure);
+            closure.extend_from_slice(part.as_bytes());
+        }
+        let slice = alloc_and_write!(closure.as_slice(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_join(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_JOIN);
+        let separator = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let list = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let sep = utf8!(separator);
+
+        let mut parts = Vec::new();
+        let mut current = list;
+        while current.len() > 0 {
+            match binparser::data(current) {
+                pumpkinscript::ParseResult::Done(rest, val) => {
+                    parts.push(utf8!(&val[offset_by_size(val.len())..]));
+                    current = rest
+                }
+                _ => return Err(error_invalid_value!(list)),
+            }
+        }
+
+        let result = parts.join(sep);
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_find(&mut self,
+                       env: &mut Env<'a>,
+                       instruction: &'a [u8],
+                       _: EnvId)
+                       -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_FIND);
+        let needle = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        match s.find(utf8!(needle)) {
+            Some(offset) => {
+                let index = BigUint::from(s[..offset].chars().count()).to_bytes_be();
+                let mut closure = Vec::new();
+                write_size_header!(index, closure);
+                closure.extend_from_slice(&index);
+                let slice = alloc_and_write!(closure.as_slice(), env);
+                env.push(slice);
+            }
+            None => env.push(_EMPTY),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_containsq(&mut self,
+                            env: &mut Env<'a>,
+                            instruction: &'a [u8],
+                            _: EnvId)
+                            -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_CONTAINSQ);
+        let needle = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        if s.contains(utf8!(needle)) {
+            env.push(STACK_TRUE);
+        } else {
+            env.push(STACK_FALSE);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_replace(&mut self,
+                          env: &mut Env<'a>,
+                          instruction: &'a [u8],
+                          _: EnvId)
+                          -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, STRING_REPLACE);
+        let to = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let from = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+        let pattern = utf8!(from);
+        if pattern.is_empty() {
+            return Err(error_invalid_value!(from));
+        }
+
+        let result = s.replace(pattern, utf8!(to));
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_format(&mut self,
+                         env: &mut Env<'a>,
+                         instruction: &'a [u8],
+                         _: EnvId)
+                         -> PassResult<'a> {
+        return_unless_instructions_equal!(instruction, FORMAT);
+        let template = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let segments = parse_template(utf8!(template)).ok_or(error_invalid_value!(template))?;
+
+        // arguments are taken in the order they were pushed
+        let count = segments.iter().filter(|s| match **s {
+            Segment::Placeholder(..) => true,
+            _ => false,
+        }).count();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(env.pop().ok_or_else(|| error_empty_stack!())?);
+        }
+
+        let mut result = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => result.push_str(text),
+                Segment::Placeholder(name, precision) => {
+                    let value = args.pop().unwrap();
+                    let formatted = format_value(name, precision, value)
+                        .ok_or(error_invalid_value!(value))?;
+                    result.push_str(&formatted);
+                }
+            }
+        }
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn handle_normalization(&mut self,
+                                env: &mut Env<'a>,
+                                instruction: &'a [u8],
+                                _: EnvId)
+                                -> PassResult<'a> {
+        match instruction {
+            STRING_NFC | STRING_NFD | STRING_NFKC | STRING_NFKD => (),
+            _ => return Err(Error::UnknownInstruction),
+        }
+        let a = env.pop().ok_or_else(|| error_empty_stack!())?;
+        let s = utf8!(a);
+
+        let result: String = match instruction {
+            STRING_NFC => s.nfc().collect(),
+            STRING_NFD => s.nfd().collect(),
+            STRING_NFKC => s.nfkc().collect(),
+            _ => s.nfkd().collect(),
+        };
+        let slice = alloc_and_write!(result.as_bytes(), env);
+        env.push(slice);
+
+        Ok(())
+    }
+
 }
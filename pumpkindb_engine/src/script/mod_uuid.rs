@@ -4,36 +4,44 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-instruction!(UUID_V4, b"\x87UUID/V4");
-instruction!(UUID_V5, b"\x87UUID/V5");
-instruction!(UUID_TO_STRING, b"\x8dUUID/->STRING");
-instruction!(UUID_STRING_TO, b"\x8dUUID/STRING->");
+instruction!(UUID_V4, ( => a), b"\x87UUID/V4");
+instruction!(UUID_V5, (a, b => c), b"\x87UUID/V5");
+instruction!(UUID_TO_STRING, (a => b), b"\x8dUUID/->STRING");
+instruction!(UUID_STRING_TO, (a => b), b"\x8dUUID/STRING->");
 
 use super::{Env, EnvId, Dispatcher, PassResult, Error, ERROR_EMPTY_STACK, ERROR_INVALID_VALUE,
-            offset_by_size, TryInstruction};
+            offset_by_size, DispatchTable};
 
 use core::str::FromStr;
 use uuid::Uuid;
 use std::marker::PhantomData;
 use std::str;
 
+/// Formats UUID bytes as a hyphenated string
+pub fn to_string(bytes: &[u8]) -> Option<String> {
+    Uuid::from_bytes(bytes).ok().map(|uuid| uuid.hyphenated().to_string())
+}
+
 pub struct Handler<'a> {
+    pub(crate) table: DispatchTable<'a, Handler<'a>>,
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Dispatcher<'a> for Handler<'a> {
     fn handle(&mut self, env: &mut Env<'a>, instruction: &'a [u8], pid: EnvId) -> PassResult<'a> {
-        self.handle_uuid_v4(env, instruction, pid)
-        .if_unhandled_try(|| self.handle_uuid_v5(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uuid_to_string(env, instruction, pid))
-        .if_unhandled_try(|| self.handle_uuid_string_to(env, instruction, pid))
-        .if_unhandled_try(|| Err(Error::UnknownInstruction))
+        dispatch!(self, env, instruction, pid)
     }
 }
 
 impl<'a> Handler<'a> {
     pub fn new() -> Self {
-        Handler { phantom: PhantomData }
+        let table = dispatch_table! {
+            UUID_V4 => Handler::handle_uuid_v4,
+            UUID_V5 => Handler::handle_uuid_v5,
+            UUID_TO_STRING => Handler::handle_uuid_to_string,
+            UUID_STRING_TO => Handler::handle_uuid_string_to,
+        };
+        Handler { phantom: PhantomData, table: table }
     }
 
     #[inline]
@@ -85,8 +93,7 @@ impl<'a> Handler<'a> {
 
         let top = env.pop().ok_or_else(|| error_empty_stack!())?;
 
-        if let Ok(uuid) = Uuid::from_bytes(top) {
-            let str = uuid.hyphenated().to_string();
+        if let Some(str) = to_string(top) {
             let val = alloc_and_write!(str.as_bytes(), env);
             env.push(val);
 
@@ -0,0 +1,61 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Parked environments
+//!
+//! An instruction that can't complete yet (for example, `RECEIVE` waiting
+//! for a message) parks its environment and returns `Error::Reschedule`,
+//! leaving the stack as it was. The scheduler keeps the instruction to be
+//! executed again, but skips the environment until it is ready: a message
+//! has arrived on the topic it waits for, a [child](../spawn/index.html) it
+//! waits for has finished or its deadline has passed. Other environments
+//! keep running in the meantime.
+//!
+//! Once the instruction completes, it is responsible for unparking the
+//! environment.
+
+use std::time::Instant;
+
+use super::Env;
+
+/// Condition a parked environment waits for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Park {
+    /// Topic a message is expected on
+    pub topic: Option<Vec<u8>>,
+    /// Child environment expected to finish
+    pub child: Option<Vec<u8>>,
+    /// Time to wait until
+    pub deadline: Option<Instant>,
+}
+
+impl Park {
+    /// Returns `true` if the deadline has passed
+    pub fn expired(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => false,
+        }
+    }
+}
+
+/// Returns `true` if the environment isn't parked or is ready to be woken up
+pub fn ready(env: &mut Env) -> bool {
+    match env.parked {
+        None => true,
+        Some(ref park) => {
+            park.expired() ||
+            match park.topic {
+                Some(ref topic) => env.mailbox.has_message(topic),
+                None => false,
+            } ||
+            match park.child {
+                Some(ref child) => env.children.finished(child),
+                None => false,
+            }
+        }
+    }
+}
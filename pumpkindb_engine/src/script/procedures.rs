@@ -0,0 +1,121 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Stored procedures
+//!
+//! Stored procedures are definitions kept in the database, under the
+//! `$PROCEDURE/` prefix, which are loaded into every new environment (see
+//! [mod_storage](../mod_storage/index.html)). They are looked up after
+//! built-in instructions and the environment's own dictionary.
+//!
+//! Every installation of a procedure is recorded as a new version under
+//! the `"$PROCEDURE/" name version` key, `version` being the HLC timestamp
+//! of the installation. The latest version of a procedure is the one in
+//! effect; dropping a procedure records a version with an empty definition.
+
+use lmdb;
+use pumpkinscript::{binparser, ParseResult};
+
+use std::collections::BTreeMap;
+
+use super::offset_by_size;
+
+/// Key prefix of stored procedures
+pub const PREFIX: &'static [u8] = b"$PROCEDURE/";
+
+/// Version of a stored procedure
+#[derive(Debug, Clone, PartialEq)]
+pub struct Procedure {
+    /// Timestamp of the installation
+    pub version: Vec<u8>,
+    /// Definition, empty if the procedure was dropped
+    pub definition: Vec<u8>,
+}
+
+/// Returns the key a procedure's version is stored under
+pub fn key(name: &[u8], version: &[u8]) -> Vec<u8> {
+    let mut key = Vec::from(PREFIX);
+    key.extend_from_slice(name);
+    key.extend_from_slice(version);
+    key
+}
+
+/// Splits a key into procedure's name and version
+pub fn parse_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    if !key.starts_with(PREFIX) {
+        return None;
+    }
+    let rest = &key[PREFIX.len()..];
+    match binparser::instruction(rest) {
+        ParseResult::Done(version, _) if version.len() > 0 => {
+            Some((&rest[0..rest.len() - version.len()], version))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the latest versions of installed procedures
+pub fn scan(access: &lmdb::ConstAccessor, cursor: &mut lmdb::Cursor)
+            -> Result<BTreeMap<Vec<u8>, Procedure>, lmdb::Error> {
+    let mut procedures = BTreeMap::new();
+    let mut item = cursor.seek_range_k::<[u8], [u8]>(access, PREFIX);
+    loop {
+        match item {
+            Ok((key, definition)) => {
+                if !key.starts_with(PREFIX) {
+                    break;
+                }
+                // versions of a procedure are ordered, the latest one comes last
+                if let Some((name, version)) = parse_key(key) {
+                    if definition.len() == 0 {
+                        procedures.remove(name);
+                    } else {
+                        procedures.insert(Vec::from(name),
+                                          Procedure {
+                                              version: Vec::from(version),
+                                              definition: Vec::from(definition),
+                                          });
+                    }
+                }
+            }
+            Err(lmdb::Error::Code(code)) if code == lmdb::error::NOTFOUND => break,
+            Err(err) => return Err(err),
+        }
+        item = cursor.next::<[u8], [u8]>(access);
+    }
+    Ok(procedures)
+}
+
+/// Encodes procedures as a sequence of `[name version definition]` closures
+pub fn encode(procedures: &BTreeMap<Vec<u8>, Procedure>) -> Vec<u8> {
+    let mut result = Vec::new();
+    for (name, procedure) in procedures.iter() {
+        let mut item = Vec::new();
+        for field in [name, &procedure.version, &procedure.definition].iter() {
+            write_size_header!(field, item);
+            item.extend_from_slice(field);
+        }
+        write_size_header!(item, result);
+        result.extend_from_slice(&item);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{key, parse_key};
+
+    #[test]
+    fn keys() {
+        let key = key(b"\x83FOO", b"\x01\x02");
+        assert_eq!(key, Vec::from(&b"$PROCEDURE/\x83FOO\x01\x02"[..]));
+        assert_eq!(parse_key(&key), Some((&b"\x83FOO"[..], &b"\x01\x02"[..])));
+        assert_eq!(parse_key(b"$PROCEDURE/\x83FOO"), None);
+        assert_eq!(parse_key(b"$TIMER/\x83FOO\x01"), None);
+    }
+
+}
@@ -0,0 +1,249 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Profiler
+//!
+//! An environment can be profiled either by scheduling it with
+//! [`EnvOptions::profile`](../struct.EnvOptions.html) set, in which case
+//! the profile is returned alongside the result stack, or by evaluating a
+//! closure with `PROFILE` (see [mod_core](../mod_core/index.html)).
+//!
+//! While profiling, the scheduler counts and times every instruction and
+//! dictionary word it executes. Time spent in a dictionary word's definition
+//! is attributed to the instructions it consists of, the word itself is only
+//! charged for its lookup. When an instruction has to be rescheduled (for
+//! example, `WRITE` waiting for another write transaction to finish), the
+//! time until it is finally executed is recorded separately.
+
+use pumpkinscript::{binparser, ParseResult};
+use num_bigint::BigUint;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::{Env, offset_by_size, STACK_TRUE, STACK_FALSE};
+
+/// Aggregated statistics of an instruction
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Entry {
+    /// Number of times the instruction was executed
+    pub count: u64,
+    /// Total execution time
+    pub time: Duration,
+    /// Total time spent rescheduled
+    pub rescheduled: Duration,
+    /// `true` if the instruction is a dictionary word
+    pub word: bool,
+}
+
+/// Execution profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Statistics per instruction
+    pub entries: BTreeMap<Vec<u8>, Entry>,
+    /// Number of `TRY`s the environment was in when profiling started
+    pub depth: usize,
+    /// Time of the first attempt to execute a rescheduled instruction
+    waiting: Option<Instant>,
+}
+
+macro_rules! write_item {
+    ($item: expr, $vec: expr) => {{
+        write_size_header!($item, $vec);
+        $vec.extend_from_slice($item);
+    }};
+}
+
+fn nanoseconds(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+fn decode_item(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    match binparser::data(input) {
+        ParseResult::Done(rest, data) => Some((rest, &data[offset_by_size(data.len())..])),
+        _ => None,
+    }
+}
+
+fn decode_u64(input: &[u8]) -> Option<u64> {
+    BigUint::from_bytes_be(input).to_u64()
+}
+
+fn decode_entry(item: &[u8]) -> Option<(Vec<u8>, Entry)> {
+    let mut fields = Vec::new();
+    let mut input = item;
+    while input.len() > 0 {
+        match decode_item(input) {
+            Some((rest, field)) => {
+                fields.push(field);
+                input = rest;
+            }
+            None => return None,
+        }
+    }
+    if fields.len() != 5 {
+        return None;
+    }
+    match (decode_u64(fields[1]), decode_u64(fields[2]), decode_u64(fields[3])) {
+        (Some(count), Some(time), Some(rescheduled)) => {
+            Some((Vec::from(fields[0]),
+                  Entry {
+                      count: count,
+                      time: Duration::new(time / 1_000_000_000, (time % 1_000_000_000) as u32),
+                      rescheduled: Duration::new(rescheduled / 1_000_000_000,
+                                                 (rescheduled % 1_000_000_000) as u32),
+                      word: fields[4] == STACK_TRUE,
+                  }))
+        }
+        _ => None,
+    }
+}
+
+impl Profile {
+    /// Creates an empty profile
+    pub fn new(depth: usize) -> Self {
+        Profile {
+            entries: BTreeMap::new(),
+            depth: depth,
+            waiting: None,
+        }
+    }
+
+    fn entry(&mut self, instruction: &[u8]) -> &mut Entry {
+        self.entries.entry(Vec::from(instruction)).or_insert_with(Entry::default)
+    }
+
+    /// Records an attempt to execute an instruction
+    pub fn record(&mut self, instruction: &[u8], started: Instant, finished: Instant, rescheduled: bool) {
+        if rescheduled {
+            if self.waiting.is_none() {
+                self.waiting = Some(started);
+            }
+            return;
+        }
+        let waiting = self.waiting.take();
+        let entry = self.entry(instruction);
+        entry.count += 1;
+        entry.time += finished.duration_since(started);
+        if let Some(waiting) = waiting {
+            entry.rescheduled += started.duration_since(waiting);
+        }
+    }
+
+    /// Marks an instruction as a dictionary word
+    pub fn word(&mut self, instruction: &[u8]) {
+        self.entry(instruction).word = true;
+    }
+
+    /// Encodes the profile as a sequence of
+    /// `[instruction count time rescheduled word?]` closures,
+    /// times being in nanoseconds
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        for (instruction, entry) in self.entries.iter() {
+            let count = BigUint::from_u64(entry.count).unwrap().to_bytes_be();
+            let time = BigUint::from_u64(nanoseconds(entry.time)).unwrap().to_bytes_be();
+            let rescheduled = BigUint::from_u64(nanoseconds(entry.rescheduled)).unwrap().to_bytes_be();
+            let word = if entry.word { STACK_TRUE } else { STACK_FALSE };
+            let mut item = Vec::new();
+            write_item!(instruction.as_slice(), item);
+            write_item!(&count, item);
+            write_item!(&time, item);
+            write_item!(&rescheduled, item);
+            write_item!(word, item);
+            write_item!(&item, result);
+        }
+        result
+    }
+
+    /// Decodes a profile encoded with [`encode`](#method.encode)
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut profile = Profile::new(0);
+        let mut input = data;
+        while input.len() > 0 {
+            match decode_item(input) {
+                Some((rest, item)) => {
+                    match decode_entry(item) {
+                        Some((instruction, entry)) => {
+                            profile.entries.insert(instruction, entry);
+                        }
+                        None => return None,
+                    }
+                    input = rest;
+                }
+                None => return None,
+            }
+        }
+        Some(profile)
+    }
+}
+
+/// Returns the time an instruction is started at, if the environment is being profiled
+#[inline]
+pub fn start(env: &Env) -> Option<Instant> {
+    if env.profiles.is_empty() {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/// Records an attempt to execute an instruction in all active profiles
+#[inline]
+pub fn record(env: &mut Env, instruction: &[u8], started: Instant, rescheduled: bool) {
+    let finished = Instant::now();
+    for profile in env.profiles.iter_mut() {
+        profile.record(instruction, started, finished, rescheduled);
+    }
+}
+
+/// Marks an instruction as a dictionary word in all active profiles
+#[inline]
+pub fn word(env: &mut Env, instruction: &[u8]) {
+    for profile in env.profiles.iter_mut() {
+        profile.word(instruction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+    use super::Profile;
+
+    #[test]
+    fn rescheduled() {
+        let mut profile = Profile::new(0);
+        let started = Instant::now();
+        profile.record(b"\x85WRITE", started, started, true);
+        profile.record(b"\x85WRITE", started + Duration::from_millis(5),
+                       started + Duration::from_millis(6), true);
+        profile.record(b"\x85WRITE", started + Duration::from_millis(10),
+                       started + Duration::from_millis(12), false);
+        profile.record(b"\x84DROP", started + Duration::from_millis(12),
+                       started + Duration::from_millis(13), false);
+
+        let write = profile.entries.get(&b"\x85WRITE"[..]).unwrap();
+        assert_eq!(write.count, 1);
+        assert_eq!(write.time, Duration::from_millis(2));
+        assert_eq!(write.rescheduled, Duration::from_millis(10));
+        let drop = profile.entries.get(&b"\x84DROP"[..]).unwrap();
+        assert_eq!(drop.count, 1);
+        assert_eq!(drop.rescheduled, Duration::from_millis(0));
+    }
+
+    #[test]
+    fn encoding() {
+        let mut profile = Profile::new(0);
+        let started = Instant::now();
+        profile.record(b"\x84DROP", started, started + Duration::new(2, 5), false);
+        profile.record(b"\x83FOO", started, started, false);
+        profile.word(b"\x83FOO");
+        assert_eq!(Profile::decode(&profile.encode()), Some(profile));
+    }
+
+}
@@ -0,0 +1,329 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Recording and replay
+//!
+//! Some instructions are non-deterministic: their results depend on the
+//! clock (`HLC`, `PROCEDURE/INSTALL`), randomness (`UUID/V4`, `RANDOM/*`),
+//! the data in the database at the time of a read (including `PROCEDURES`),
+//! messages arriving (`RECEIVE`) or other environments (`JOIN`). An
+//! environment scheduled with [`EnvOptions::record`](../struct.EnvOptions.html)
+//! set records the results of every such instruction it executes, and the
+//! recording is returned alongside the result stack.
+//!
+//! An environment scheduled with `EnvOptions::replay` set to a recording
+//! doesn't execute these instructions, it consumes their operands and
+//! pushes the recorded results instead (or fails with the recorded error).
+//! Instructions with effects outside of the environment (`PUBLISH`,
+//! `SPAWN/DETACHED`, `AFTER`, etc.) are recorded as well, so that
+//! they aren't repeated during replay. Everything else, including writes,
+//! is executed as usual, so a replay is supposed to run against a scratch
+//! copy of the database (see
+//! [`storage::copy_environment`](../../storage/fn.copy_environment.html)).
+//!
+//! If the program executes a different non-deterministic instruction than
+//! the one recorded, the replay fails with a `ReplayDiverged` error.
+//!
+//! Recording and replay are only available to programs embedding the
+//! engine, neither the server nor the terminal expose them.
+
+use pumpkinscript::{binparser, ParseResult};
+
+use std::collections::VecDeque;
+
+use super::{Env, Error, offset_by_size, ERROR_EMPTY_STACK, ERROR_REPLAY_DIVERGED};
+
+#[cfg(feature = "mod_hlc")]
+use super::mod_hlc::{HLC, HLC_TICK, HLC_OBSERVE};
+#[cfg(feature = "mod_uuid")]
+use super::mod_uuid::UUID_V4;
+#[cfg(feature = "mod_random")]
+use super::mod_random::{RANDOM_BYTES, RANDOM_UINT, RANDOM_F64};
+#[cfg(feature = "mod_storage")]
+use super::mod_storage::{TXID, ASSOCQ, RETR, CURSOR, CURSOR_FIRST, CURSOR_LAST, CURSOR_NEXT,
+                         CURSOR_PREV, CURSOR_SEEK, CURSOR_POSITIONEDQ, CURSOR_KEY, CURSOR_VAL,
+                         PROCEDURE_INSTALL, PROCEDURE_DROP, PROCEDURES};
+#[cfg(feature = "mod_msg")]
+use super::mod_msg::{PUBLISH, SUBSCRIBE, UNSUBSCRIBE, RECEIVE, RECEIVE_TIMEOUT};
+use super::{SPAWN, SPAWN_DETACHED, JOIN, JOIN_TIMEOUT, AFTER, EVERY, EVERY_AS, EVERY_AS_RESTRICTED,
+            CANCEL};
+
+/// Returns the number of items a non-deterministic instruction consumes
+pub fn arity(instruction: &[u8]) -> Option<usize> {
+    match instruction {
+        #[cfg(feature = "mod_hlc")]
+        HLC => Some(0),
+        #[cfg(feature = "mod_hlc")]
+        HLC_TICK => Some(1),
+        #[cfg(feature = "mod_hlc")]
+        HLC_OBSERVE => Some(1),
+        #[cfg(feature = "mod_uuid")]
+        UUID_V4 => Some(0),
+        #[cfg(feature = "mod_random")]
+        RANDOM_BYTES => Some(1),
+        #[cfg(feature = "mod_random")]
+        RANDOM_UINT => Some(2),
+        #[cfg(feature = "mod_random")]
+        RANDOM_F64 => Some(0),
+        #[cfg(feature = "mod_storage")]
+        TXID => Some(0),
+        #[cfg(feature = "mod_storage")]
+        ASSOCQ => Some(1),
+        #[cfg(feature = "mod_storage")]
+        RETR => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR => Some(0),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_FIRST => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_LAST => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_NEXT => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_PREV => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_SEEK => Some(2),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_POSITIONEDQ => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_KEY => Some(1),
+        #[cfg(feature = "mod_storage")]
+        CURSOR_VAL => Some(1),
+        // new procedure versions are timestamped
+        #[cfg(feature = "mod_storage")]
+        PROCEDURE_INSTALL => Some(2),
+        #[cfg(feature = "mod_storage")]
+        PROCEDURE_DROP => Some(1),
+        #[cfg(feature = "mod_storage")]
+        PROCEDURES => Some(0),
+        #[cfg(feature = "mod_msg")]
+        PUBLISH => Some(2),
+        #[cfg(feature = "mod_msg")]
+        SUBSCRIBE => Some(1),
+        #[cfg(feature = "mod_msg")]
+        UNSUBSCRIBE => Some(1),
+        #[cfg(feature = "mod_msg")]
+        RECEIVE => Some(1),
+        #[cfg(feature = "mod_msg")]
+        RECEIVE_TIMEOUT => Some(2),
+        SPAWN => Some(1),
+        SPAWN_DETACHED => Some(1),
+        JOIN => Some(1),
+        JOIN_TIMEOUT => Some(2),
+        AFTER => Some(2),
+        EVERY => Some(2),
+        EVERY_AS => Some(3),
+        EVERY_AS_RESTRICTED => Some(4),
+        CANCEL => Some(1),
+        _ => None,
+    }
+}
+
+/// Recorded result of a non-deterministic instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    /// Instruction executed
+    pub instruction: Vec<u8>,
+    /// Items pushed by the instruction
+    pub outputs: Vec<Vec<u8>>,
+    /// Error raised by the instruction, if any
+    pub error: Option<Vec<u8>>,
+}
+
+/// Results of non-deterministic instructions, in order of execution
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    pub inputs: VecDeque<Input>,
+}
+
+macro_rules! write_item {
+    ($item: expr, $vec: expr) => {{
+        write_size_header!($item, $vec);
+        $vec.extend_from_slice($item);
+    }};
+}
+
+fn decode_items(input: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut items = Vec::new();
+    let mut input = input;
+    while input.len() > 0 {
+        match binparser::data(input) {
+            ParseResult::Done(rest, data) => {
+                items.push(&data[offset_by_size(data.len())..]);
+                input = rest;
+            }
+            _ => return None,
+        }
+    }
+    Some(items)
+}
+
+fn decode_input(item: &[u8]) -> Option<Input> {
+    let fields = match decode_items(item) {
+        Some(fields) => fields,
+        None => return None,
+    };
+    if fields.len() != 3 {
+        return None;
+    }
+    let outputs: Vec<Vec<u8>> = match decode_items(fields[1]) {
+        Some(outputs) => outputs.into_iter().map(Vec::from).collect(),
+        None => return None,
+    };
+    Some(Input {
+        instruction: Vec::from(fields[0]),
+        outputs: outputs,
+        error: if fields[2].len() == 0 {
+            None
+        } else {
+            Some(Vec::from(fields[2]))
+        },
+    })
+}
+
+impl Recording {
+    /// Creates an empty recording
+    pub fn new() -> Self {
+        Recording::default()
+    }
+
+    /// Encodes the recording as a sequence of
+    /// `[instruction [outputs] error]` closures,
+    /// `error` being empty if there was none
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        for input in self.inputs.iter() {
+            let mut outputs = Vec::new();
+            for output in input.outputs.iter() {
+                write_item!(output.as_slice(), outputs);
+            }
+            let error = match input.error {
+                Some(ref error) => error.as_slice(),
+                None => &b""[..],
+            };
+            let mut item = Vec::new();
+            write_item!(input.instruction.as_slice(), item);
+            write_item!(&outputs, item);
+            write_item!(error, item);
+            write_item!(&item, result);
+        }
+        result
+    }
+
+    /// Decodes a recording encoded with [`encode`](#method.encode)
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let items = match decode_items(data) {
+            Some(items) => items,
+            None => return None,
+        };
+        let mut recording = Recording::new();
+        for item in items {
+            match decode_input(item) {
+                Some(input) => recording.inputs.push_back(input),
+                None => return None,
+            }
+        }
+        Some(recording)
+    }
+}
+
+/// Records the result of a non-deterministic instruction that consumed
+/// `arity` items off the stack of `depth` items
+pub fn record(env: &mut Env, instruction: &[u8], arity: usize, depth: usize, result: &Result<(), Error>) {
+    let input = match *result {
+        Ok(()) => {
+            let stack = env.stack();
+            let pushed = (stack.len() + arity).saturating_sub(depth);
+            Input {
+                instruction: Vec::from(instruction),
+                outputs: stack[stack.len() - pushed..].iter().map(|v| Vec::from(*v)).collect(),
+                error: None,
+            }
+        }
+        Err(Error::ProgramError(ref error)) => {
+            Input {
+                instruction: Vec::from(instruction),
+                outputs: vec![],
+                error: Some(error.clone()),
+            }
+        }
+        // rescheduled instructions are recorded once they complete,
+        // other errors terminate the environment
+        Err(_) => return,
+    };
+    if let Some(ref mut recording) = env.recording {
+        recording.inputs.push_back(input);
+    }
+}
+
+/// Replays the recorded result of a non-deterministic instruction
+/// that consumes `arity` items
+pub fn replay<'a>(env: &mut Env<'a>, instruction: &'a [u8], arity: usize) -> Result<(), Error> {
+    let input = match env.replay {
+        Some(ref mut recording) => {
+            let matches = match recording.inputs.front() {
+                Some(input) => input.instruction.as_slice() == instruction,
+                None => false,
+            };
+            if !matches {
+                return Err(error_replay_diverged!(instruction));
+            }
+            recording.inputs.pop_front().unwrap()
+        }
+        None => return Ok(()),
+    };
+    for _ in 0..arity {
+        env.pop().ok_or_else(|| error_empty_stack!())?;
+    }
+    if let Some(error) = input.error {
+        return Err(Error::ProgramError(error));
+    }
+    for output in input.outputs {
+        let slice = alloc_and_write!(output.as_slice(), env);
+        env.push(slice);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::VecDeque;
+    use super::{Recording, Input, arity};
+
+    #[test]
+    fn nondeterministic() {
+        assert_eq!(arity(b"\x83HLC"), Some(0));
+        assert_eq!(arity(b"\x8BCURSOR/SEEK"), Some(2));
+        assert_eq!(arity(b"\x91PROCEDURE/INSTALL"), Some(2));
+        assert_eq!(arity(b"\x8aPROCEDURES"), Some(0));
+        assert_eq!(arity(b"\x84DROP"), None);
+    }
+
+    #[test]
+    fn encoding() {
+        let mut inputs = VecDeque::new();
+        inputs.push_back(Input {
+            instruction: Vec::from(&b"\x83HLC"[..]),
+            outputs: vec![vec![1, 2, 3]],
+            error: None,
+        });
+        inputs.push_back(Input {
+            instruction: Vec::from(&b"\x84RETR"[..]),
+            outputs: vec![],
+            error: Some(vec![1, 2]),
+        });
+        inputs.push_back(Input {
+            instruction: Vec::from(&b"\x87PUBLISH"[..]),
+            outputs: vec![],
+            error: None,
+        });
+        let recording = Recording { inputs: inputs };
+        assert_eq!(Recording::decode(&recording.encode()), Some(recording));
+        assert_eq!(Recording::decode(b"\x01"), None);
+    }
+
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Sessions
+//!
+//! Normally, every environment starts with an empty dictionary. Environments
+//! scheduled with the same [`EnvOptions::session`](../struct.EnvOptions.html)
+//! share their top-level dictionary scope instead: it is loaded when an
+//! environment is scheduled and saved once it has terminated (whether
+//! successfully or not), so that words defined with `DEF` and values set with
+//! `SET` by one program are available to the next one. `SESSION/RESET`
+//! clears it.
+//!
+//! If environments of the same session run concurrently, the one to
+//! terminate last determines what is saved.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use super::{Env, Error};
+
+/// Top-level dictionary scope shared by environments
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    dictionary: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl Session {
+    /// Creates a session with an empty dictionary
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// Loads the session's dictionary into the environment's top-level scope
+    pub fn load(&self, env: &mut Env) -> Result<(), Error> {
+        let dictionary = self.dictionary.lock().unwrap();
+        for (key, value) in dictionary.iter() {
+            let key_slice = env.alloc(key.len())?;
+            key_slice.copy_from_slice(key);
+            let value_slice = env.alloc(value.len())?;
+            value_slice.copy_from_slice(value);
+            #[cfg(feature = "scoped_dictionary")]
+            env.dictionary[0].insert(key_slice, value_slice);
+            #[cfg(not(feature = "scoped_dictionary"))]
+            env.dictionary.insert(key_slice, value_slice);
+        }
+        Ok(())
+    }
+
+    /// Saves the environment's top-level scope as the session's dictionary
+    pub fn save(&self, env: &Env) {
+        #[cfg(feature = "scoped_dictionary")]
+        let scope = &env.dictionary[0];
+        #[cfg(not(feature = "scoped_dictionary"))]
+        let scope = &env.dictionary;
+        let mut dictionary = self.dictionary.lock().unwrap();
+        *dictionary = scope.iter().map(|(k, v)| (Vec::from(*k), Vec::from(*v))).collect();
+    }
+
+    /// Returns `true` if the session's dictionary is empty
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use script::Env;
+    use super::Session;
+
+    #[test]
+    fn load_save() {
+        let session = Session::new();
+        assert!(session.is_empty());
+
+        let mut env = Env::new().unwrap();
+        #[cfg(feature = "scoped_dictionary")]
+        env.dictionary[0].insert(&b"\x83FOO"[..], &b"\x01\x01"[..]);
+        #[cfg(not(feature = "scoped_dictionary"))]
+        env.dictionary.insert(&b"\x83FOO"[..], &b"\x01\x01"[..]);
+        session.save(&env);
+        assert!(!session.is_empty());
+
+        let mut env = Env::new().unwrap();
+        session.load(&mut env).unwrap();
+        #[cfg(feature = "scoped_dictionary")]
+        assert_eq!(env.dictionary[0].get(&b"\x83FOO"[..]), Some(&&b"\x01\x01"[..]));
+        #[cfg(not(feature = "scoped_dictionary"))]
+        assert_eq!(env.dictionary.get(&b"\x83FOO"[..]), Some(&&b"\x01\x01"[..]));
+    }
+
+}
@@ -0,0 +1,135 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Child environments
+//!
+//! `SPAWN` schedules a closure as a new environment on one of the
+//! schedulers and pushes its identifier. The result of a child environment
+//! is kept by its parent until it is retrieved with `JOIN`, which
+//! [parks](../park/index.html) the parent until the child has finished.
+//! `SPAWN/DETACHED` schedules an environment whose result is discarded.
+//!
+//! A result is represented by two items: a closure with the child's
+//! stack and its error (an empty closure if it has finished successfully).
+
+use super::{Error, ResponseMessage, Receiver, ERROR_INVALID_VALUE, offset_by_size};
+
+struct Child {
+    id: Vec<u8>,
+    receiver: Receiver<ResponseMessage>,
+    response: Option<ResponseMessage>,
+}
+
+/// Children of an environment that haven't been joined yet
+pub struct Children {
+    children: Vec<Child>,
+}
+
+impl Children {
+    /// Creates an empty list of children
+    pub fn new() -> Self {
+        Children { children: Vec::new() }
+    }
+
+    /// Adds a child that will send its result to the `receiver`
+    pub fn add(&mut self, id: Vec<u8>, receiver: Receiver<ResponseMessage>) {
+        self.children.push(Child {
+            id: id,
+            receiver: receiver,
+            response: None,
+        });
+    }
+
+    /// Returns `true` if there's a child with the identifier
+    pub fn is_child(&self, id: &[u8]) -> bool {
+        self.children.iter().any(|child| child.id.as_slice() == id)
+    }
+
+    /// Returns `true` if the child has finished
+    pub fn finished(&mut self, id: &[u8]) -> bool {
+        match self.children.iter_mut().find(|child| child.id.as_slice() == id) {
+            Some(child) => {
+                if child.response.is_none() {
+                    child.response = child.receiver.try_recv().ok();
+                }
+                child.response.is_some()
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the result of the child if it has finished
+    pub fn take(&mut self, id: &[u8]) -> Option<ResponseMessage> {
+        if !self.finished(id) {
+            return None;
+        }
+        let index = self.children.iter().position(|child| child.id.as_slice() == id);
+        match index {
+            Some(index) => self.children.remove(index).response,
+            None => None,
+        }
+    }
+}
+
+fn error(err: Error) -> Vec<u8> {
+    match err {
+        Error::ProgramError(error) => error,
+        err => error(error_program!(format!("{:?}", err).as_bytes(), "".as_bytes(), ERROR_INVALID_VALUE)),
+    }
+}
+
+fn stack(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut closure = Vec::new();
+    for item in items {
+        write_size_header!(item, closure);
+        closure.extend_from_slice(&item);
+    }
+    closure
+}
+
+/// Returns child's stack and error
+pub fn result(response: ResponseMessage) -> (Vec<u8>, Vec<u8>) {
+    match response {
+        ResponseMessage::EnvTerminated(_, items, _, _, _) => (stack(items), vec![]),
+        ResponseMessage::EnvFailed(_, err, items, _, _, _, _) => {
+            (stack(items.unwrap_or_default()), error(err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use script::{ResponseMessage, EnvId, Error};
+    use std::sync::mpsc;
+    use super::{Children, result};
+
+    #[test]
+    fn join() {
+        let mut children = Children::new();
+        let (sender, receiver) = mpsc::channel();
+        children.add(vec![1], receiver);
+        assert!(children.is_child(&[1]));
+        assert!(!children.is_child(&[2]));
+        assert!(!children.finished(&[1]));
+        assert!(children.take(&[1]).is_none());
+
+        sender.send(ResponseMessage::EnvTerminated(EnvId::new(), vec![vec![1], vec![]], 2, None, None))
+            .unwrap();
+        assert!(children.finished(&[1]));
+        let response = children.take(&[1]).unwrap();
+        assert_eq!(result(response), (vec![1, 1, 0], vec![]));
+        assert!(!children.is_child(&[1]));
+    }
+
+    #[test]
+    fn failed() {
+        let response = ResponseMessage::EnvFailed(EnvId::new(), Error::ProgramError(vec![1, 2, 3]),
+                                                  None, None, None, None, None);
+        assert_eq!(result(response), (vec![], vec![1, 2, 3]));
+    }
+
+}
@@ -0,0 +1,208 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Timers
+//!
+//! `AFTER` and `EVERY` register a closure to be executed once after a delay
+//! or periodically. Timers are kept by the scheduler the registering
+//! environment runs on; when a timer is due, its closure is scheduled as a
+//! new detached environment (see [SPAWN/DETACHED](../spawn/index.html)).
+//!
+//! Closures of timers registered by a seeded environment are seeded too
+//! (see [`EnvOptions::random_seed`](../struct.EnvOptions.html)), every
+//! execution of a periodic timer with a different seed.
+//!
+//! Periodic timers are identified by a handle, which can be used to
+//! cancel them with `CANCEL`. If the scheduler falls behind, missed
+//! executions are skipped rather than scheduled all at once.
+//!
+//! Timers are not persisted by the scheduler itself. `EVERY/PERSISTENT`
+//! (see [mod_storage](../mod_storage/index.html)) records the timer in the
+//! database, along with the capabilities of the environment that started
+//! it, so that it can be registered again with `TIMERS/RESTORE` after a
+//! restart without gaining any capabilities.
+
+use super::super::messaging::PublishedMessageCallback;
+use super::capabilities::Capabilities;
+
+use rand::{Rng, SeedableRng};
+use rand::chacha::ChaChaRng;
+
+use std::time::{Duration, Instant};
+
+/// Registered timer
+pub struct Timer {
+    /// Handle of a periodic timer
+    pub handle: Option<Vec<u8>>,
+    /// Closure to execute
+    pub program: Vec<u8>,
+    /// Time of the next execution
+    pub due: Instant,
+    /// Interval between executions of a periodic timer
+    pub period: Option<Duration>,
+    /// Capabilities of the environment that registered the timer
+    pub capabilities: Capabilities,
+    /// Random seed of the next execution, if the registering environment was seeded
+    pub seed: Option<u64>,
+    /// Published message callback of the environment that registered the timer
+    pub callback: Box<PublishedMessageCallback + Send>,
+}
+
+// Derives the seed of the next execution of a periodic timer
+fn next_seed(seed: u64) -> u64 {
+    ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32]).gen()
+}
+
+/// Timers registered on a scheduler
+pub struct Timers {
+    timers: Vec<Timer>,
+}
+
+impl Timers {
+    /// Creates an empty list of timers
+    pub fn new() -> Self {
+        Timers { timers: Vec::new() }
+    }
+
+    /// Registers a timer, replacing a timer with the same handle
+    pub fn add(&mut self, timer: Timer) {
+        if let Some(ref handle) = timer.handle {
+            self.cancel(handle);
+        }
+        self.timers.push(timer);
+    }
+
+    /// Removes the timer with the handle, returns `true` if it was registered
+    pub fn cancel(&mut self, handle: &[u8]) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| match timer.handle {
+            Some(ref h) => h.as_slice() != handle,
+            None => true,
+        });
+        self.timers.len() != len
+    }
+
+    /// Returns the time the earliest timer is due at
+    pub fn next_due(&self) -> Option<Instant> {
+        self.timers.iter().map(|timer| timer.due).min()
+    }
+
+    /// Takes closures (along with capabilities, seeds and callbacks) of the timers
+    /// due at `now`, periodic timers are kept for their next execution
+    pub fn due(&mut self, now: Instant)
+               -> Vec<(Vec<u8>, Capabilities, Option<u64>, Box<PublishedMessageCallback + Send>)> {
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < self.timers.len() {
+            if self.timers[i].due > now {
+                i += 1;
+                continue;
+            }
+            let period = self.timers[i].period;
+            match period {
+                Some(period) => {
+                    let timer = &mut self.timers[i];
+                    result.push((timer.program.clone(),
+                                 timer.capabilities.clone(),
+                                 timer.seed,
+                                 timer.callback.cloned()));
+                    timer.seed = timer.seed.map(next_seed);
+                    timer.due += period;
+                    if timer.due <= now {
+                        timer.due = now + period;
+                    }
+                    i += 1;
+                }
+                None => {
+                    let timer = self.timers.remove(i);
+                    result.push((timer.program, timer.capabilities, timer.seed, timer.callback));
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use super::{Timer, Timers};
+    use script::capabilities::Capabilities;
+
+    fn timer(handle: Option<&[u8]>, program: &[u8], due: Instant, period: Option<Duration>) -> Timer {
+        let (callback, _) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+        Timer {
+            handle: handle.map(Vec::from),
+            program: Vec::from(program),
+            due: due,
+            period: period,
+            capabilities: Capabilities::new(),
+            seed: None,
+            callback: Box::new(callback),
+        }
+    }
+
+    #[test]
+    fn once() {
+        let mut timers = Timers::new();
+        let now = Instant::now();
+        timers.add(timer(None, b"\x01\x01", now + Duration::from_millis(10), None));
+        assert_eq!(timers.next_due(), Some(now + Duration::from_millis(10)));
+        assert!(timers.due(now).is_empty());
+
+        let due = timers.due(now + Duration::from_millis(10));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, vec![1, 1]);
+        assert_eq!(timers.next_due(), None);
+    }
+
+    #[test]
+    fn periodic() {
+        let mut timers = Timers::new();
+        let now = Instant::now();
+        let period = Duration::from_millis(10);
+        timers.add(timer(Some(&b"1"[..]), b"\x01\x01", now + period, Some(period)));
+        assert_eq!(timers.due(now + period).len(), 1);
+        assert_eq!(timers.next_due(), Some(now + period * 2));
+        // missed executions are skipped
+        assert_eq!(timers.due(now + period * 5).len(), 1);
+        assert_eq!(timers.next_due(), Some(now + period * 6));
+
+        // replaced by a timer with the same handle
+        timers.add(timer(Some(&b"1"[..]), b"\x01\x02", now + period, Some(period)));
+        assert_eq!(timers.next_due(), Some(now + period));
+
+        assert!(!timers.cancel(b"2"));
+        assert!(timers.cancel(b"1"));
+        assert_eq!(timers.next_due(), None);
+    }
+
+    #[test]
+    fn seeded() {
+        let mut timers = Timers::new();
+        let now = Instant::now();
+        let period = Duration::from_millis(10);
+        let mut t = timer(Some(&b"1"[..]), b"\x01\x01", now + period, Some(period));
+        t.seed = Some(42);
+        timers.add(t);
+        let first = timers.due(now + period)[0].2;
+        let second = timers.due(now + period * 2)[0].2;
+        assert_eq!(first, Some(42));
+        assert!(second.is_some());
+        assert!(second != first);
+
+        // executions are seeded the same way every time
+        let mut t = timer(Some(&b"2"[..]), b"\x01\x01", now + period, Some(period));
+        t.seed = Some(42);
+        timers.add(t);
+        timers.cancel(b"1");
+        timers.due(now + period);
+        assert_eq!(timers.due(now + period * 2)[0].2, second);
+    }
+
+}
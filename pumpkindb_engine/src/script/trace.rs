@@ -0,0 +1,398 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Error traces
+//!
+//! When an instruction fails, the scheduler records a trace: the failed
+//! instruction followed by the chain of instructions (dictionary words,
+//! `EVAL`, `IFELSE`, etc.) that led to it, innermost first.
+//!
+//! The trace is only built when it is needed, that is, when the error
+//! terminates the environment or `ERROR/TRACE` is called after `TRY`
+//! caught it. In the latter case, words are looked up in the dictionary
+//! as it is at the time of `ERROR/TRACE`, unless the code involved has
+//! been relocated in the meantime (see `Env::relocate`), which builds the
+//! trace right away.
+//!
+//! Every frame is located by looking at the code that is still to be
+//! executed (`Env::program`): the instruction that precedes each pending
+//! continuation is the one that called into the code above it. Frames
+//! are attributed to the innermost dictionary word whose definition
+//! contains them and, if they are a part of the original program, to
+//! their byte offset in it.
+//!
+//! Code that is neither a part of the original program nor of a dictionary
+//! definition (for example, code generated by `DOWHILE` or built-in words
+//! implemented in PumpkinScript) is not included. Calls that are the last
+//! instruction of their code are not included either, as there is nothing
+//! left to return to.
+
+use pumpkinscript::{binparser, ParseResult};
+use num_bigint::BigUint;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use std::fmt;
+use std::iter;
+
+use super::{Env, offset_by_size, _EMPTY};
+
+/// Single frame of a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Instruction being executed
+    pub instruction: Vec<u8>,
+    /// Dictionary word the instruction belongs to, if any
+    pub word: Option<Vec<u8>>,
+    /// Offset of the instruction in the original program, if it is a part of it
+    pub offset: Option<usize>,
+}
+
+/// Trace of an error, innermost frame first
+pub type Trace = Vec<Frame>;
+
+/// Returns instruction's name without its header
+fn name(instruction: &[u8]) -> String {
+    let header: usize = if instruction.len() > 1 && instruction[0] == 0x80 { 2 } else { 1 };
+    String::from_utf8_lossy(&instruction[header.min(instruction.len())..]).into_owned()
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", name(&self.instruction))?;
+        if let Some(ref word) = self.word {
+            write!(f, " in {}", name(word))?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn contains(code: &[u8], position: usize) -> bool {
+    let start = code.as_ptr() as usize;
+    position >= start && position < start + code.len()
+}
+
+#[cfg(feature = "scoped_dictionary")]
+fn definitions<'a>(env: &Env<'a>) -> Vec<(&'a [u8], &'a [u8])> {
+    env.dictionary.iter().flat_map(|d| d.iter().map(|(k, v)| (*k, *v))).collect()
+}
+
+#[cfg(not(feature = "scoped_dictionary"))]
+fn definitions<'a>(env: &Env<'a>) -> Vec<(&'a [u8], &'a [u8])> {
+    env.dictionary.iter().map(|(k, v)| (*k, *v)).collect()
+}
+
+/// Finds the instruction in `code` that ends right at `position`,
+/// looking into closures
+fn locate<'a>(code: &'a [u8], position: usize) -> Option<&'a [u8]> {
+    let mut input = code;
+    while input.len() > 0 {
+        if let ParseResult::Done(rest, data) = binparser::data(input) {
+            let content = &data[offset_by_size(data.len())..];
+            if contains(content, position) {
+                return locate(content, position);
+            }
+            input = rest;
+        } else if let ParseResult::Done(rest, instruction) =
+            binparser::instruction_or_internal_instruction(input) {
+            if rest.as_ptr() as usize == position {
+                return Some(instruction);
+            }
+            input = rest;
+        } else {
+            return None;
+        }
+    }
+    None
+}
+
+struct Tracer<'a> {
+    origin: &'a [u8],
+    definitions: Vec<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> Tracer<'a> {
+    /// Innermost definition containing the position
+    fn definition(&self, position: usize) -> Option<(&'a [u8], &'a [u8])> {
+        self.definitions.iter()
+            .filter(|&&(_, code)| contains(code, position))
+            .min_by_key(|&&(_, code)| code.len())
+            .map(|d| *d)
+    }
+
+    fn frame(&self, instruction: &'a [u8]) -> Option<Frame> {
+        let position = instruction.as_ptr() as usize;
+        let word = self.definition(position).map(|(word, _)| Vec::from(word));
+        let offset = if contains(self.origin, position) {
+            Some(position - self.origin.as_ptr() as usize)
+        } else {
+            None
+        };
+        if word.is_none() && offset.is_none() {
+            None
+        } else {
+            Some(Frame {
+                instruction: Vec::from(instruction),
+                word: word,
+                offset: offset,
+            })
+        }
+    }
+
+    /// Frame of the instruction that precedes the continuation
+    fn caller(&self, continuation: &'a [u8]) -> Option<Frame> {
+        let position = continuation.as_ptr() as usize;
+        let code = match self.definition(position) {
+            Some((_, code)) => code,
+            None if contains(self.origin, position) => self.origin,
+            None => return None,
+        };
+        locate(code, position).and_then(|instruction| self.frame(instruction))
+    }
+}
+
+/// Failed instruction along with the code that was left to be executed
+/// when it failed
+///
+/// Capturing a failure is cheap, looking up dictionary definitions is
+/// deferred until the trace is actually [built](#method.trace). This way
+/// errors caught by `TRY` don't pay for traces nobody asks for.
+#[derive(Debug, Clone)]
+pub struct Failure<'a> {
+    instruction: &'a [u8],
+    continuations: Vec<&'a [u8]>,
+    // trace that has already been built
+    built: Option<Trace>,
+}
+
+impl<'a> Failure<'a> {
+    /// Captures the failure of the `instruction` that just failed in `env`
+    pub fn new(env: &Env<'a>, instruction: &'a [u8]) -> Self {
+        Failure {
+            instruction: instruction,
+            continuations: env.program.iter()
+                .filter(|continuation| continuation.len() > 0)
+                .map(|continuation| *continuation)
+                .collect(),
+            built: None,
+        }
+    }
+
+    /// Builds the trace using the dictionary `env` has at this point
+    pub fn trace(&self, env: &Env<'a>) -> Trace {
+        match self.built {
+            Some(ref trace) => trace.clone(),
+            None => build(env, self.instruction, self.continuations.iter()),
+        }
+    }
+
+    /// Returns `true` if `pred` matches any of the code captured
+    pub fn references<F: Fn(&[u8]) -> bool>(&self, pred: &F) -> bool {
+        pred(self.instruction) || self.continuations.iter().any(|continuation| pred(continuation))
+    }
+
+    /// Builds the trace right away, so that the failure no longer
+    /// references any code
+    ///
+    /// Frames are located by comparing addresses, so this has to be done
+    /// before the code is moved.
+    pub fn settle(self, env: &Env<'a>) -> Self {
+        let trace = self.trace(env);
+        Failure {
+            instruction: _EMPTY,
+            continuations: vec![],
+            built: Some(trace),
+        }
+    }
+}
+
+/// Builds a trace for the `instruction` that just failed in `env`
+pub fn trace<'a>(env: &Env<'a>, instruction: &'a [u8]) -> Trace {
+    build(env, instruction, env.program.iter())
+}
+
+fn build<'a, 'b, I>(env: &Env<'a>, instruction: &'a [u8], continuations: I) -> Trace
+    where I: DoubleEndedIterator<Item = &'b &'a [u8]>, 'a: 'b {
+    let tracer = Tracer {
+        origin: env.origin,
+        definitions: definitions(env),
+    };
+    let mut trace: Trace = Vec::new();
+    let frames = iter::once(tracer.frame(instruction))
+        .chain(continuations.rev()
+            .filter(|continuation| continuation.len() > 0)
+            .map(|continuation| tracer.caller(*continuation)));
+    for frame in frames {
+        if let Some(frame) = frame {
+            // continuation of the failed code points to the failed instruction
+            // itself, the same applies to repeatedly scheduled code
+            if trace.last() != Some(&frame) {
+                trace.push(frame);
+            }
+        }
+    }
+    trace
+}
+
+/// Encodes a trace as a sequence of `[offset instruction word]` closures,
+/// `offset` and `word` being empty when unknown
+pub fn encode(trace: &[Frame]) -> Vec<u8> {
+    let mut result = Vec::new();
+    for frame in trace {
+        let offset = match frame.offset {
+            Some(offset) => BigUint::from_usize(offset).unwrap().to_bytes_be(),
+            None => vec![],
+        };
+        let word: &[u8] = match frame.word {
+            Some(ref word) => word.as_slice(),
+            None => &[],
+        };
+        let mut item = Vec::new();
+        write_size_header!(offset, item);
+        item.extend_from_slice(&offset);
+        write_size_header!(frame.instruction, item);
+        item.extend_from_slice(&frame.instruction);
+        write_size_header!(word, item);
+        item.extend_from_slice(word);
+        write_size_header!(item, result);
+        result.extend_from_slice(&item);
+    }
+    result
+}
+
+fn decode_item(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    match binparser::data(input) {
+        ParseResult::Done(rest, data) => Some((rest, &data[offset_by_size(data.len())..])),
+        _ => None,
+    }
+}
+
+fn decode_frame(item: &[u8]) -> Option<Frame> {
+    if let Some((item, offset)) = decode_item(item) {
+        if let Some((item, instruction)) = decode_item(item) {
+            if let Some((item, word)) = decode_item(item) {
+                if item.len() > 0 {
+                    return None;
+                }
+                let offset = if offset.len() == 0 {
+                    None
+                } else {
+                    match BigUint::from_bytes_be(offset).to_usize() {
+                        Some(offset) => Some(offset),
+                        None => return None,
+                    }
+                };
+                return Some(Frame {
+                    instruction: Vec::from(instruction),
+                    word: if word.len() == 0 { None } else { Some(Vec::from(word)) },
+                    offset: offset,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Decodes a trace encoded with [`encode`](fn.encode.html)
+pub fn decode(data: &[u8]) -> Option<Trace> {
+    let mut trace = Vec::new();
+    let mut input = data;
+    while input.len() > 0 {
+        match decode_item(input) {
+            Some((rest, item)) => {
+                match decode_frame(item) {
+                    Some(frame) => trace.push(frame),
+                    None => return None,
+                }
+                input = rest;
+            }
+            None => return None,
+        }
+    }
+    Some(trace)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pumpkinscript::{parse, binparser, ParseResult};
+    use script::{Env, offset_by_size};
+    use super::{trace, encode, decode, Frame, Failure};
+
+    #[test]
+    fn nested_word() {
+        let program = parse("[1 DROP DROP DROP] 'FOO DEF FOO 2").unwrap();
+        let mut env = Env::new().unwrap();
+        {
+            let slice = env.alloc(program.len()).unwrap();
+            slice.copy_from_slice(&program);
+            env.origin = slice;
+        }
+        let origin = env.origin;
+
+        let definition = match binparser::data(origin) {
+            ParseResult::Done(_, data) => &data[offset_by_size(data.len())..],
+            _ => unreachable!(),
+        };
+        #[cfg(feature = "scoped_dictionary")]
+        env.dictionary[0].insert(b"\x83FOO", definition);
+        #[cfg(not(feature = "scoped_dictionary"))]
+        env.dictionary.insert(b"\x83FOO", definition);
+
+        // `2` is left to be executed after `FOO`
+        env.program.push(&origin[origin.len() - 2..]);
+        // `DROP` that is left to be executed after the failed one
+        env.program.push(&definition[definition.len() - 5..]);
+        let failed = &definition[definition.len() - 10..definition.len() - 5];
+
+        let trace = trace(&env, failed);
+        assert_eq!(trace,
+                   vec![Frame {
+                            instruction: Vec::from(&b"\x84DROP"[..]),
+                            word: Some(Vec::from(&b"\x83FOO"[..])),
+                            offset: Some(1 + 2 + 5),
+                        },
+                        Frame {
+                            instruction: Vec::from(&b"\x83FOO"[..]),
+                            word: None,
+                            offset: Some(program.len() - 2 - 4),
+                        }]);
+        assert_eq!(format!("{}", trace[0]), "DROP in FOO at 8");
+        assert_eq!(format!("{}", trace[1]), "FOO at 27");
+
+        // the failure outlives the code left to be executed
+        let failure = Failure::new(&env, failed);
+        env.program.clear();
+        assert_eq!(failure.trace(&env), trace);
+
+        // and, once settled, the dictionary as well
+        let failure = failure.settle(&env);
+        #[cfg(feature = "scoped_dictionary")]
+        env.dictionary[0].clear();
+        #[cfg(not(feature = "scoped_dictionary"))]
+        env.dictionary.clear();
+        assert_eq!(failure.trace(&env), trace);
+    }
+
+    #[test]
+    fn encoding() {
+        let trace = vec![Frame {
+                             instruction: Vec::from(&b"\x84DROP"[..]),
+                             word: Some(Vec::from(&b"\x83FOO"[..])),
+                             offset: Some(300),
+                         },
+                         Frame {
+                             instruction: Vec::from(&b"\x83FOO"[..]),
+                             word: None,
+                             offset: None,
+                         }];
+        assert_eq!(decode(&encode(&trace)), Some(trace));
+    }
+
+}
@@ -0,0 +1,215 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Stack effect verification
+//!
+//! Before a program gets scheduled, its stack effects are checked using
+//! instruction signatures (as declared in `instruction!`). The program
+//! is followed from the beginning for as long as stack depth can be
+//! determined: every data item adds one element, every instruction with
+//! a known signature consumes and produces its items, closures passed
+//! directly to `EVAL`, `EVAL/SCOPED`, `READ` and `WRITE` are followed
+//! inline.
+//!
+//! As soon as an instruction with an unknown stack effect is encountered
+//! (a dictionary word, `TRY`, `IFELSE`, etc.), verification stops and the
+//! rest of the program is left for runtime checks.
+//!
+//! This way a certain stack underflow is reported before the program
+//! is executed, pointing to the offending instruction.
+
+use pumpkinscript::{binparser, ParseResult};
+
+use super::{Signature, Error, ERROR_EMPTY_STACK, offset_by_size};
+
+#[cfg(feature = "mod_core")]
+use super::mod_core::EVAL;
+#[cfg(all(feature = "mod_core", feature = "scoped_dictionary"))]
+use super::mod_core::EVAL_SCOPED;
+#[cfg(feature = "mod_storage")]
+use super::mod_storage::{READ, WRITE};
+
+/// Certain stack underflow found by the verifier
+#[derive(Debug, PartialEq)]
+pub struct Underflow<'a> {
+    /// Offset of the instruction in the program
+    pub offset: usize,
+    /// Instruction that will fail
+    pub instruction: &'a [u8],
+    /// Number of items on the stack at that point
+    pub available: usize,
+    /// Number of items instruction's signature requires
+    pub required: usize,
+}
+
+impl<'a> From<Underflow<'a>> for Error {
+    /// Converts underflow into the same `EmptyStack` error the instruction
+    /// would fail with at runtime. The offset and the instruction are
+    /// reported in the error's [trace](../trace/index.html) instead.
+    fn from(_: Underflow<'a>) -> Self {
+        error_empty_stack!()
+    }
+}
+
+/// Verifies program's stack effects, starting with an empty stack.
+///
+/// `signature` is used to look up instruction signatures (typically,
+/// [`Dispatcher::signature`](../dispatcher/trait.Dispatcher.html#method.signature))
+pub fn verify<'a, F>(program: &'a [u8], signature: F) -> Result<(), Underflow<'a>>
+    where F: Fn(&[u8]) -> Option<Signature> {
+    let mut depth = 0;
+    let mut input = program;
+    // code following the closures being verified inline, kept on the heap
+    // so that deeply nested closures can't exhaust scheduler's stack
+    let mut continuations: Vec<&'a [u8]> = Vec::new();
+    // data item pushed right before the current instruction, if any
+    let mut closure: Option<&'a [u8]> = None;
+    loop {
+        if input.len() == 0 {
+            match continuations.pop() {
+                Some(rest) => {
+                    input = rest;
+                    closure = None;
+                    continue;
+                }
+                None => return Ok(()),
+            }
+        }
+        if let ParseResult::Done(rest, data) = binparser::data(input) {
+            depth += 1;
+            closure = Some(&data[offset_by_size(data.len())..]);
+            input = rest;
+        } else if let ParseResult::Done(rest, instruction) =
+            binparser::instruction_or_internal_instruction(input) {
+            match (signature(instruction), closure) {
+                (Some(sig), _) => {
+                    if depth < sig.inputs.len() {
+                        return Err(Underflow {
+                            offset: input.as_ptr() as usize - program.as_ptr() as usize,
+                            instruction: instruction,
+                            available: depth,
+                            required: sig.inputs.len(),
+                        });
+                    }
+                    depth = depth - sig.inputs.len() + sig.outputs.len();
+                    input = rest;
+                }
+                (None, Some(code)) if evaluates_closure(instruction) => {
+                    continuations.push(rest);
+                    depth -= 1;
+                    input = code;
+                }
+                // stack effect can't be determined
+                (None, _) => return Ok(()),
+            }
+            closure = None;
+        } else {
+            // decoding errors are reported at runtime
+            return Ok(());
+        }
+    }
+}
+
+/// Returns `true` if the instruction evaluates the closure on top
+/// of the stack in place
+#[allow(unused_variables)]
+fn evaluates_closure(instruction: &[u8]) -> bool {
+    #[cfg(feature = "mod_core")]
+    {
+        if instruction == EVAL {
+            return true;
+        }
+    }
+    #[cfg(all(feature = "mod_core", feature = "scoped_dictionary"))]
+    {
+        if instruction == EVAL_SCOPED {
+            return true;
+        }
+    }
+    #[cfg(feature = "mod_storage")]
+    {
+        if instruction == READ || instruction == WRITE {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pumpkinscript::parse;
+    use script::{Signature, offset_by_size};
+    use super::verify;
+
+    const DUP: Signature = Signature { inputs: &["a"], outputs: &["a", "a"] };
+    const DROP: Signature = Signature { inputs: &["a"], outputs: &[] };
+
+    fn signature(instruction: &[u8]) -> Option<Signature> {
+        if instruction == b"\x83DUP" {
+            Some(DUP)
+        } else if instruction == b"\x84DROP" {
+            Some(DROP)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn balanced() {
+        let program = parse("1 DUP DROP DROP").unwrap();
+        assert!(verify(&program, signature).is_ok());
+    }
+
+    #[test]
+    fn underflow() {
+        let program = parse("1 DUP DROP DROP DROP").unwrap();
+        let underflow = verify(&program, signature).unwrap_err();
+        assert_eq!(underflow.offset, 2 + 4 + 5 * 2);
+        assert_eq!(underflow.instruction, b"\x84DROP");
+        assert_eq!(underflow.available, 0);
+        assert_eq!(underflow.required, 1);
+    }
+
+    #[test]
+    fn unknown_effect() {
+        let program = parse("UNKNOWN DROP").unwrap();
+        assert!(verify(&program, signature).is_ok());
+    }
+
+    #[test]
+    fn deeply_nested_closures() {
+        const LEVELS: usize = 100_000;
+        let inner = parse("DROP DROP").unwrap();
+        let eval = parse("EVAL").unwrap();
+        // sizes of closures, from the innermost one
+        let mut sizes = vec![inner.len()];
+        for i in 1..LEVELS {
+            let size = sizes[i - 1];
+            sizes.push(offset_by_size(size) + size + eval.len());
+        }
+        let mut program = parse("1").unwrap();
+        for size in sizes.iter().rev() {
+            write_size!(*size, program);
+        }
+        program.extend_from_slice(&inner);
+        for _ in 0..LEVELS {
+            program.extend_from_slice(&eval);
+        }
+        let underflow = verify(&program, signature).unwrap_err();
+        assert_eq!(underflow.available, 0);
+    }
+
+    #[test]
+    fn inline_closure() {
+        let program = parse("1 [DROP DROP] EVAL").unwrap();
+        let underflow = verify(&program, signature).unwrap_err();
+        assert_eq!(underflow.available, 0);
+        // offset of the second DROP: `1`, closure header, first DROP
+        assert_eq!(underflow.offset, 2 + 1 + 5);
+    }
+
+}
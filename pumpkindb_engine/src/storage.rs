@@ -13,7 +13,7 @@ use libc::statvfs;
 use lmdb;
 
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub struct WriteTransactionContainer<'a>(Option<lmdb::WriteTransaction<'a>>, Arc<AtomicBool>);
 
@@ -54,6 +54,8 @@ pub struct Storage<'a> {
     pub db: lmdb::Database<'a>,
     pub env: &'a lmdb::Environment,
     pub write: Arc<AtomicBool>,
+    /// Incremented every time a commit changes stored procedures
+    pub procedures_generation: AtomicUsize,
 }
 
 impl<'a> Storage<'a> {
@@ -66,6 +68,7 @@ impl<'a> Storage<'a> {
             db: lmdb::Database::open(env, None, &lmdb::DatabaseOptions::new(lmdb::db::CREATE))
                 .expect("can't open database"),
             write: Arc::new(AtomicBool::new(false)),
+            procedures_generation: AtomicUsize::new(0),
         }
     }
 
@@ -133,6 +136,13 @@ pub fn create_environment(storage_path: String, map_size: Option<i64>, maxreader
     }
 }
 
+/// Copies the database into an existing empty directory, for example,
+/// to get a scratch copy to [replay](../script/recording/index.html)
+/// environments against
+pub fn copy_environment(env: &lmdb::Environment, path: &str) -> Result<(), lmdb::Error> {
+    env.copy(path, lmdb::copy::Flags::empty())
+}
+
 #[cfg(test)]
 #[allow(unused_variables, unused_must_use, unused_mut)]
 mod tests {
@@ -263,4 +273,40 @@ mod tests {
         });
     }
 
+    #[test]
+    pub fn copy() {
+        let dir = TempDir::new("pumpkindb").unwrap();
+        let path = dir.path().to_str().unwrap();
+        let env = unsafe {
+            lmdb::EnvBuilder::new()
+                .expect("can't create env builder")
+                .open(path, lmdb::open::NOTLS, 0o600)
+                .expect("can't open env")
+        };
+        let db = storage::Storage::new(&env);
+        {
+            let txn = db.write().unwrap().unwrap();
+            {
+                let mut access = txn.access();
+                access.put(&db.db, &b"key"[..], &b"value"[..], lmdb::put::NOOVERWRITE).unwrap();
+            }
+            txn.commit().unwrap();
+        }
+
+        let copy_dir = TempDir::new("pumpkindb").unwrap();
+        let copy_path = copy_dir.path().to_str().unwrap();
+        storage::copy_environment(&env, copy_path).unwrap();
+
+        let copy_env = unsafe {
+            lmdb::EnvBuilder::new()
+                .expect("can't create env builder")
+                .open(copy_path, lmdb::open::NOTLS, 0o600)
+                .expect("can't open env")
+        };
+        let copy = storage::Storage::new(&copy_env);
+        let txn = copy.read().unwrap().unwrap();
+        let access = txn.access();
+        assert_eq!(access.get::<[u8], [u8]>(&copy.db, &b"key"[..]).unwrap(), &b"value"[..]);
+    }
+
 }
\ No newline at end of file
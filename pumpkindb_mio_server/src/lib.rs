@@ -29,19 +29,31 @@ use mio::channel as mio_chan;
 
 use pumpkindb_engine::{script};
 
-pub fn run(port: i64,
+/// Port to accept connections on
+pub struct Listener {
+    pub port: i64,
+    /// Capabilities of the environments scheduled by connections
+    /// accepted on this port
+    pub capabilities: script::capabilities::Capabilities,
+}
+
+pub fn run(listeners: Vec<Listener>,
            senders: Vec<script::Sender<script::RequestMessage>>,
            relay_sender: mio_chan::Sender<server::RelayedPublishedMessage>,
-           relay_receiver: mio_chan::Receiver<server::RelayedPublishedMessage>) {
-    let addr = format!("0.0.0.0:{}", port).parse().unwrap();
+           relay_receiver: mio_chan::Receiver<server::RelayedPublishedMessage>,
+           sessions: bool) {
+    let socks = listeners.into_iter().map(|listener| {
+        let addr = format!("0.0.0.0:{}", listener.port).parse().unwrap();
 
-    info!("Listening on {}", addr);
+        info!("Listening on {}", addr);
 
-    let sock = TcpListener::bind(&addr).expect("Failed to bind address");
+        let sock = TcpListener::bind(&addr).expect("Failed to bind address");
+        (sock, listener.capabilities)
+    }).collect();
 
     let mut poll = Poll::new().expect("Failed to initialize polling");
 
-    let mut server = server::Server::new(sock, relay_sender, relay_receiver, senders);
+    let mut server = server::Server::new(socks, relay_sender, relay_receiver, senders, sessions);
     server.run(&mut poll).expect("Failed to run server");
 
 }
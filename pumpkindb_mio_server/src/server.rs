@@ -19,7 +19,9 @@ use super::connection::Connection;
 type Slab<T> = slab::Slab<T, Token>;
 
 use pumpkindb_engine::messaging;
-use pumpkindb_engine::script::{EnvId, Sender, RequestMessage, ResponseMessage, SchedulerHandle};
+use pumpkindb_engine::script::{EnvId, EnvOptions, Sender, RequestMessage, ResponseMessage, SchedulerHandle};
+use pumpkindb_engine::script::session::Session;
+use pumpkindb_engine::script::capabilities::Capabilities;
 
 use uuid::Uuid;
 
@@ -48,32 +50,39 @@ pub struct Server {
     response_sender: Sender<ResponseMessage>,
     relay_sender: mio_chan::Sender<RelayedPublishedMessage>,
     relay_receiver: mio_chan::Receiver<RelayedPublishedMessage>,
-    sock: TcpListener,
-    token: Token,
+    // listening sockets and capabilities of connections accepted on them
+    socks: Vec<(TcpListener, Capabilities)>,
     conns: Slab<Connection>,
     session_token: BTreeMap<Vec<u8>, Token>,
     token_session: BTreeMap<Token, Vec<u8>>,
+    sessions: Option<BTreeMap<Token, Session>>,
+    token_capabilities: BTreeMap<Token, Capabilities>,
     events: Events,
 }
 
+/// Token of the first listening socket, the rest follow it
+const LISTENER_TOKEN: usize = 10_000_000;
+
 impl Server {
-    pub fn new(sock: TcpListener,
+    pub fn new(socks: Vec<(TcpListener, Capabilities)>,
                relay_sender: mio_chan::Sender<RelayedPublishedMessage>,
                relay_receiver: mio_chan::Receiver<RelayedPublishedMessage>,
-               senders: Vec<Sender<RequestMessage>>)
+               senders: Vec<Sender<RequestMessage>>,
+               sessions: bool)
                -> Server {
         let (response_sender, _) = mpsc::channel();
 
         Server {
-            sock: sock,
+            socks: socks,
             senders: senders,
             response_sender: response_sender,
             relay_sender: relay_sender,
             relay_receiver: relay_receiver,
-            token: Token(10_000_000),
             conns: Slab::with_capacity(128),
             session_token: BTreeMap::new(),
             token_session: BTreeMap::new(),
+            sessions: if sessions { Some(BTreeMap::new()) } else { None },
+            token_capabilities: BTreeMap::new(),
             events: Events::with_capacity(1024),
         }
     }
@@ -106,8 +115,20 @@ impl Server {
     }
 
     pub fn register(&mut self, poll: &mut Poll) -> io::Result<()> {
-        poll.register(&self.sock, self.token, Ready::readable(), PollOpt::edge())
-            .or_else(|e| Err(e))
+        for (i, &(ref sock, _)) in self.socks.iter().enumerate() {
+            poll.register(sock, Token(LISTENER_TOKEN + i), Ready::readable(), PollOpt::edge())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the listening socket registered with the token
+    fn listener(&self, token: Token) -> Option<usize> {
+        match token {
+            Token(t) if t >= LISTENER_TOKEN && t < LISTENER_TOKEN + self.socks.len() => {
+                Some(t - LISTENER_TOKEN)
+            }
+            _ => None,
+        }
     }
 
     fn tick(&mut self, poll: &mut Poll) {
@@ -130,6 +151,10 @@ impl Server {
             if let Some(session) = self.token_session.remove(&token) {
                 let _ = self.session_token.remove(&session);
             }
+            if let Some(ref mut sessions) = self.sessions {
+                let _ = sessions.remove(&token);
+            }
+            let _ = self.token_capabilities.remove(&token);
         }
     }
 
@@ -161,7 +186,7 @@ impl Server {
         }
 
         if event.is_writable() {
-            assert!(self.token != token, "Received writable event for Server");
+            assert!(self.listener(token).is_none(), "Received writable event for Server");
 
             let conn = self.find_connection_by_token(token);
 
@@ -174,8 +199,8 @@ impl Server {
         }
 
         if event.is_readable() {
-            if self.token == token {
-                self.accept(poll);
+            if let Some(listener) = self.listener(token) {
+                self.accept(poll, listener);
             } else {
 
                 if self.find_connection_by_token(token).is_reset() {
@@ -187,14 +212,14 @@ impl Server {
             }
         }
 
-        if self.token != token {
+        if self.listener(token).is_none() {
             self.find_connection_by_token(token).mark_idle();
         }
     }
 
-    fn accept(&mut self, poll: &mut Poll) {
+    fn accept(&mut self, poll: &mut Poll, listener: usize) {
         loop {
-            let sock = match self.sock.accept() {
+            let sock = match self.socks[listener].0.accept() {
                 Ok((sock, _)) => sock,
                 Err(_) => return,
             };
@@ -212,6 +237,11 @@ impl Server {
                     let session = Vec::from(&Uuid::new_v4().as_bytes()[..]);
                     self.session_token.insert(session.clone(), token);
                     self.token_session.insert(token, session);
+                    if let Some(ref mut sessions) = self.sessions {
+                        sessions.insert(token, Session::new());
+                    }
+                    let capabilities = self.socks[listener].1.clone();
+                    self.token_capabilities.insert(token, capabilities);
                 }
                 Err(_) => {
                     self.conns.remove(token);
@@ -224,13 +254,19 @@ impl Server {
         while let Some(message) = self.find_connection_by_token(token).readable()? {
             let id = EnvId::new();
             let session = self.token_session.get(&token).unwrap();
-            let _ = self.senders.schedule_env(id,
-                                                 message,
-                                                 self.response_sender.clone(),
-                                                 Box::new(RelayedPublishedMessageSender {
-                                                     identifier: session.to_vec(),
-                                                     sender: self.relay_sender.clone(),
-                                                 }));
+            let options = EnvOptions {
+                session: self.sessions.as_ref().and_then(|sessions| sessions.get(&token).cloned()),
+                capabilities: self.token_capabilities.get(&token).cloned().unwrap_or_default(),
+                ..Default::default()
+            };
+            let _ = self.senders.schedule_env_with_options(id,
+                                                           message,
+                                                           options,
+                                                           self.response_sender.clone(),
+                                                           Box::new(RelayedPublishedMessageSender {
+                                                               identifier: session.to_vec(),
+                                                               sender: self.relay_sender.clone(),
+                                                           }));
         }
 
         Ok(())
@@ -28,6 +28,7 @@ use std::thread;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::mpsc;
 
 use mio::channel as mio_chan;
 
@@ -69,6 +70,10 @@ pub fn main() {
     let _ = config::merge(config::File::new(args.value_of("config").unwrap(),
                                             config::FileFormat::Toml));
     let _ = config::set_default("server.port", 9981);
+    let _ = config::set_default("server.sessions", false);
+    let _ = config::set_default("server.profile", "all");
+    let _ = config::set_default("scheduler.heap_size", script::env::HEAP_SIZE as i64);
+    let _ = config::set_default("scheduler.heap_pool", 0);
     let _ = config::set_default("storage.path", "pumpkin.db");
     let storage_path = config::get_str("storage.path").unwrap().into_owned();
     fs::create_dir_all(storage_path.as_str()).expect("can't create directory");
@@ -107,6 +112,48 @@ pub fn main() {
 
     info!("Starting up");
 
+    let mut listeners = vec![server::Listener {
+        port: config::get_int("server.port").unwrap(),
+        capabilities: capabilities("server.profile",
+                                   &config::get_str("server.profile").unwrap()),
+    }];
+    // additional ports, each with its own profile
+    for listener in config::get_slice("server.listeners").unwrap_or(&[]) {
+        let listener = match listener.as_map() {
+            Some(listener) => listener,
+            None => {
+                error!("server.listeners: a table with port and profile expected");
+                ::std::process::exit(1);
+            }
+        };
+        let port = match listener.get("port").and_then(|port| port.as_int()) {
+            Some(port) => port,
+            None => {
+                error!("server.listeners: port expected");
+                ::std::process::exit(1);
+            }
+        };
+        let profile = listener.get("profile")
+            .and_then(|profile| profile.as_str())
+            .map(|profile| profile.into_owned())
+            .unwrap_or(String::from("all"));
+        listeners.push(server::Listener {
+            port: port,
+            capabilities: capabilities("server.listeners.profile", &profile),
+        });
+    }
+
+    let heap_size = config::get_int("scheduler.heap_size").unwrap();
+    if heap_size < 1 {
+        error!("scheduler.heap_size can't be less than 1");
+        ::std::process::exit(1);
+    }
+    let heap_pool = config::get_int("scheduler.heap_pool").unwrap();
+    if heap_pool < 0 {
+        error!("scheduler.heap_pool can't be negative");
+        ::std::process::exit(1);
+    }
+
     let mut senders = Vec::new();
 
     let (relay_sender, relay_receiver) = mio_chan::channel();
@@ -127,10 +174,46 @@ pub fn main() {
                 dispatcher::StandardDispatcher::new(storage.clone(),
                                                     publisher_accessor.clone(), subscriber_accessor.clone(),
                                                     timestamp.clone()));
+        scheduler.set_heap(heap_size as usize, heap_pool as usize);
         thread::spawn(move || scheduler.run());
         senders.push(sender);
     }
 
-    server::run(config::get_int("server.port").unwrap(),
-                senders, relay_sender, relay_receiver);
+    // let schedulers spawn environments on each other
+    for sender in senders.iter() {
+        let _ = sender.send(script::RequestMessage::Peers(senders.clone()));
+    }
+
+    // register persistent timers again; this environment is unrestricted,
+    // but every timer is restricted with the capabilities stored along
+    // with it, so timers started from restricted ports stay restricted
+    {
+        use script::SchedulerHandle;
+        let (callback, _) = mpsc::channel::<(Vec<u8>, Vec<u8>)>();
+        let (sender, receiver) = mpsc::channel();
+        senders.schedule_env(script::EnvId::new(), Vec::from(&b"\x8eTIMERS/RESTORE"[..]),
+                             sender, Box::new(callback));
+        match receiver.recv() {
+            Ok(script::ResponseMessage::EnvFailed(_, err, _, _, _, _, _)) => {
+                error!("Can't restore timers: {:?}", err);
+            }
+            _ => (),
+        }
+    }
+
+    server::run(listeners,
+                senders, relay_sender, relay_receiver,
+                config::get_bool("server.sessions").unwrap());
+}
+
+/// Combines comma-separated profiles configured under `key`, exits if
+/// any of them is unknown
+fn capabilities(key: &str, profile: &str) -> script::capabilities::Capabilities {
+    match script::capabilities::Capabilities::profiles(profile) {
+        Ok(capabilities) => capabilities,
+        Err(name) => {
+            error!("{}: unknown profile {}", key, name);
+            ::std::process::exit(1);
+        }
+    }
 }
@@ -50,6 +50,224 @@ fn print_item(s: &mut String, data: &[u8]) {
     }
 }
 
+/// Location of the user's program within the message sent to the server
+struct Program {
+    start: usize,
+    end: usize,
+}
+
+impl Program {
+    /// Makes frame offsets relative to the user's program,
+    /// dropping frames that belong to the code around it
+    fn frames(&self, trace: &[u8]) -> Vec<script::trace::Frame> {
+        script::trace::decode(trace)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|mut frame| match frame.offset {
+                Some(offset) if offset >= self.start && offset < self.end => {
+                    frame.offset = Some(offset - self.start);
+                    Some(frame)
+                }
+                Some(_) => None,
+                None => Some(frame),
+            })
+            .collect()
+    }
+}
+
+/// Result of waiting for a response
+enum Response {
+    /// Program has finished
+    Result,
+    /// Debugger has responded
+    Debugger,
+}
+
+fn items(input: &[u8]) -> Vec<Vec<u8>> {
+    let mut result = vec![];
+    let mut input = input;
+    while input.len() > 0 {
+        match pumpkinscript::binparser::data(input) {
+            pumpkinscript::ParseResult::Done(rest, data) => {
+                let (_, size) = pumpkinscript::binparser::data_size(data).unwrap();
+                result.push(Vec::from(&data[script::offset_by_size(size)..]));
+                input = rest;
+            }
+            e => {
+                panic!("{:?}", e);
+            }
+        }
+    }
+    result
+}
+
+fn instruction_name(instruction: &[u8]) -> String {
+    String::from_utf8_lossy(&instruction[1..]).into_owned()
+}
+
+fn print_debugger_event(program: &Program, event: &[u8]) {
+    let event = items(event);
+    let name = event[0].as_slice();
+    let payload = items(&event[1]);
+    let mut s = String::new();
+    if name == b"PAUSED" {
+        let _ = write!(&mut s, "Paused at ");
+        let item = &payload[0];
+        if pumpkinscript::binparser::instruction(item).is_done() {
+            let _ = write!(&mut s, "{}", instruction_name(item));
+        } else {
+            print_item(&mut s, &items(item)[0]);
+        }
+        if let Some(frame) = program.frames(&payload[1]).first() {
+            if let Some(ref word) = frame.word {
+                let _ = write!(&mut s, " in {}", instruction_name(word));
+            }
+            if let Some(offset) = frame.offset {
+                let _ = write!(&mut s, " at {}", offset);
+            }
+        }
+    } else if name == b"BREAKPOINTS" {
+        let _ = write!(&mut s, "Breakpoints:");
+        for breakpoint in payload {
+            let _ = write!(&mut s, " {}", instruction_name(&breakpoint));
+        }
+    } else if name == b"DICTIONARY" {
+        for entry in payload {
+            let entry = items(&entry);
+            let scope = entry[0].iter().fold(0, |n, b| n * 256 + *b as usize);
+            let _ = write!(&mut s, "{}: {} ", scope, instruction_name(&entry[1]));
+            print_item(&mut s, &entry[2]);
+            let _ = write!(&mut s, "\n");
+        }
+    } else if name == b"UNKNOWN" {
+        let _ = write!(&mut s, "Unknown debugger command");
+    } else {
+        // STACK, QUEUE and TRANSACTIONS
+        for item in payload {
+            print_item(&mut s, &item);
+        }
+    }
+    println!("{}", s.trim_right());
+}
+
+fn print_profile(data: &[u8]) {
+    let profile = match script::profiler::Profile::decode(data) {
+        Some(profile) => profile,
+        None => {
+            println!("Invalid profile");
+            return;
+        }
+    };
+    let mut entries: Vec<_> = profile.entries.iter().collect();
+    entries.sort_by(|&(_, a), &(_, b)| b.time.cmp(&a.time));
+    let nanoseconds = |d: std::time::Duration| d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64;
+    println!("Profile:");
+    println!("{:>8} {:>14} {:>19}  instruction", "count", "time (ns)", "rescheduled (ns)");
+    for (instruction, entry) in entries {
+        println!("{:>8} {:>14} {:>19}  {}{}",
+                 entry.count,
+                 nanoseconds(entry.time),
+                 nanoseconds(entry.rescheduled),
+                 instruction_name(instruction),
+                 if entry.word { " (word)" } else { "" });
+    }
+}
+
+/// Receives messages until the program finishes or the debugger responds
+fn receive(stream: &mut TcpStream, program: &Program, debug_topic: &[u8]) -> Response {
+    let mut response = None;
+    let mut frames: Vec<script::trace::Frame> = vec![];
+
+    while response.is_none() {
+        stream.receive(|r: &[u8]| {
+            if r.starts_with(debug_topic) {
+                print_debugger_event(program, &r[debug_topic.len()..]);
+                response = Some(Response::Debugger);
+            } else if r.starts_with(b"PROFILE") {
+                print_profile(&r[7..]);
+            } else if r[0..5].to_vec() == b"TRACE" {
+                let input = r[5..r.len()].to_vec();
+                let mut s = String::new();
+                if cfg!(target_os = "windows") {
+                    let _ = write!(&mut s, "Trace: ");
+                } else {
+                    let _ = write!(&mut s,
+                                   "{}", Cyan.paint("Trace: "));
+                }
+                match pumpkinscript::binparser::data(&input.clone()) {
+                    pumpkinscript::ParseResult::Done(_, data) => {
+                        let (_, size) = pumpkinscript::binparser::data_size(data)
+                            .unwrap();
+                        let data = &data[script::offset_by_size(size)..];
+                        print_item(&mut s, data);
+                    },
+                    e => {
+                        panic!("{:?}", e);
+                    }
+                }
+                println!("{}", s);
+            } else if r[0..6].to_vec() == b"FRAMES" {
+                frames = program.frames(&r[6..r.len()]);
+            } else if r[0..6].to_vec() == b"RESULT" {
+                let mut input = r[6..r.len()].to_vec();
+                response = Some(Response::Result);
+                let mut top_level = true;
+                let mut s = String::new();
+                while input.len() > 0 {
+                    match pumpkinscript::binparser::data(&input.clone()) {
+                        pumpkinscript::ParseResult::Done(rest, data) => {
+                            let (_, size) = pumpkinscript::binparser::data_size(data)
+                                .unwrap();
+                            let data = &data[script::offset_by_size(size)..];
+
+                            input = Vec::from(rest);
+
+                            if rest.len() == 0 && top_level {
+                                top_level = false;
+                                if data.len() > 0 {
+                                    if cfg!(target_os = "windows") {
+                                        let _ = write!(&mut s, "Error: ");
+                                    } else {
+                                        let _ = write!(&mut s,
+                                                       "{}",
+                                                       Red.paint("Error: "));
+                                    }
+                                    input = Vec::from(data);
+                                }
+                            } else {
+                                print_item(&mut s, data);
+                            }
+                        }
+                        e => {
+                            panic!("{:?}", e);
+                        }
+                    }
+                }
+                for frame in frames.iter() {
+                    let _ = write!(&mut s, "\n  at {}", frame);
+                }
+                println!("{}", s);
+            }
+        }).expect("can't receive");
+    }
+
+    response.unwrap()
+}
+
+/// Publishes a command to the debugger
+fn send_debugger_command(stream: &mut TcpStream, topic: &[u8], command: &str, argument: Option<&str>) {
+    let mut writer = PacketWriter::new(stream);
+    let result = match argument {
+        Some(argument) => writer.send((Closure((command, InstructionRef(argument))),
+                                       topic,
+                                       Instruction("PUBLISH"))),
+        None => writer.send((Closure((command,)),
+                             topic,
+                             Instruction("PUBLISH"))),
+    };
+    result.expect("can't write");
+}
+
 fn main() {
 
     let args = App::new("PumpkinDB Terminal")
@@ -80,6 +298,12 @@ fn main() {
     let mut rl = Editor::<()>::new();
 
     let mut multine = History::new();
+    // debug the programs that are sent
+    let mut debug = false;
+    // profile the programs that are sent
+    let mut profile = false;
+    // program that is paused by the debugger, and the debugger's topic
+    let mut debugged: Option<(Program, Vec<u8>)> = None;
     println!("Connected to PumpkinDB at {}", address);
     println!("To send an expression, end it with `.`");
     println!("Type \\h for help.");
@@ -90,14 +314,60 @@ fn main() {
                 let text_str = text.as_str();
                 let text_bytes = text_str.as_bytes();
                 if text_bytes.len() >= 2 && text_bytes[0] == b'\\' {
-                    if text_bytes[1] == b'h' {
+                    let mut words = text_str[1..].split_whitespace();
+                    let command = words.next().unwrap_or("");
+                    let argument = words.next();
+                    let debugger_command = match command {
+                        "step" => Some("STEP"),
+                        "continue" => Some("CONTINUE"),
+                        "break" => Some("BREAK"),
+                        "stack" => Some("STACK"),
+                        "queue" => Some("QUEUE"),
+                        "dict" => Some("DICTIONARY"),
+                        "txns" => Some("TRANSACTIONS"),
+                        _ => None,
+                    };
+                    if command.starts_with("h") {
                         println!("\nTo send an expression, end it with `.`");
                         println!("To trace a value in the script use TRACE instruction");
+                        println!("To debug expressions, toggle debugging with \\debug, then use:");
+                        println!("  \\step to execute the next instruction");
+                        println!("  \\continue to run until a breakpoint");
+                        println!("  \\break WORD to set a breakpoint");
+                        println!("  \\stack, \\queue, \\dict and \\txns to inspect the stack, \
+                                  the queue, the dictionary and open transactions");
+                        println!("To profile expressions, toggle profiling with \\profile");
                         println!("To quit, hit ^D");
                         println!("Further help online at http://pumpkindb.org/doc/");
                         println!("Missing a feature? Let us know at \
                                   https://github.com/PumpkinDB/PumpkinDB/issues/\n");
+                    } else if command == "debug" {
+                        debug = !debug;
+                        println!("Debugging is {}", if debug { "on" } else { "off" });
+                    } else if command == "profile" {
+                        profile = !profile;
+                        println!("Profiling is {}", if profile { "on" } else { "off" });
+                    } else if let Some(debugger_command) = debugger_command {
+                        let finished = match debugged {
+                            Some((ref program, ref topic)) => {
+                                send_debugger_command(&mut stream, topic, debugger_command, argument);
+                                match receive(&mut stream, program, topic) {
+                                    Response::Result => true,
+                                    Response::Debugger => false,
+                                }
+                            }
+                            None => {
+                                println!("No expression is being debugged");
+                                false
+                            }
+                        };
+                        if finished {
+                            debugged = None;
+                            current_prompt = formatted_prompt.as_str();
+                        }
                     }
+                } else if debugged.is_some() {
+                    println!("An expression is being debugged, use \\continue to finish it");
                 } else if text_str.len() > 0 && text_bytes[text_str.len() - 1] == 46u8 {
                     let rest = str::from_utf8(&text.as_bytes()[..text_str.len() - 1]).unwrap();
                     multine.add(&rest);
@@ -117,7 +387,9 @@ fn main() {
                         Ok(compiled) => {
                             let uuid = Uuid::new_v4();
                             let uuid_slice = &uuid.as_bytes()[..];
-                            let msg = (
+                            let mut debug_topic = Vec::from("DEBUG");
+                            debug_topic.extend_from_slice(uuid_slice);
+                            let prologue = (
                                                             uuid_slice,
                                                             Instruction("SUBSCRIBE"),
                                                             InstructionRef("___subscription___"),
@@ -132,8 +404,49 @@ fn main() {
                                                             )),
                                                             InstructionRef("TRACE"),
                                                             Instruction("DEF"),
-                                                            compiled,
+                            );
+                            // the program (or the program evaluated by DEBUG, or
+                            // PROFILE, or both) is evaluated within TRY
+                            let debugged_body = if debug {
+                                (compiled.clone(), debug_topic.as_slice(), Instruction("DEBUG")).encode()
+                            } else {
+                                compiled.clone()
+                            };
+                            let body = if profile {
+                                (debugged_body.clone(),
+                                 Instruction("PROFILE"),
+                                 "PROFILE",
+                                 Instruction("SWAP"),
+                                 Instruction("CONCAT"),
+                                 uuid_slice,
+                                 Instruction("PUBLISH")).encode()
+                            } else {
+                                debugged_body.clone()
+                            };
+                            // error traces refer to offsets in the entire message,
+                            // the program starts right after the prologue and its header
+                            let mut program_start = prologue.encode().len() +
+                                                    script::offset_by_size(body.len());
+                            if profile {
+                                program_start += script::offset_by_size(debugged_body.len());
+                            }
+                            if debug {
+                                program_start += script::offset_by_size(compiled.len());
+                            }
+                            let program = Program {
+                                start: program_start,
+                                end: program_start + compiled.len(),
+                            };
+                            let msg = (
+                                                            prologue,
+                                                            body,
                                                             Instruction("TRY"),
+                                                            Instruction("ERROR/TRACE"),
+                                                            "FRAMES",
+                                                            Instruction("SWAP"),
+                                                            Instruction("CONCAT"),
+                                                            uuid_slice,
+                                                            Instruction("PUBLISH"),
                                                             Instruction("STACK"),
                                                             "RESULT",
                                                             Instruction("SWAP"),
@@ -149,69 +462,12 @@ fn main() {
                                 writer.send(msg).expect("can't write");
                             }
 
-                            let mut done = false;
-
-                            while !done {
-                                (&mut stream).receive(|r: &[u8]| {
-                                    if r[0..5].to_vec() == b"TRACE" {
-                                        let input = r[5..r.len()].to_vec();
-                                        let mut s = String::new();
-                                        if cfg!(target_os = "windows") {
-                                            let _ = write!(&mut s, "Trace: ");
-                                        } else {
-                                            let _ = write!(&mut s,
-                                                           "{}", Cyan.paint("Trace: "));
-                                        }
-                                        match pumpkinscript::binparser::data(&input.clone()) {
-                                            pumpkinscript::ParseResult::Done(_, data) => {
-                                                let (_, size) = pumpkinscript::binparser::data_size(data)
-                                                    .unwrap();
-                                                let data = &data[script::offset_by_size(size)..];
-                                                print_item(&mut s, data);
-                                            },
-                                            e => {
-                                                panic!("{:?}", e);
-                                            }
-                                        }
-                                        println!("{}", s);
-                                    } else if r[0..6].to_vec() == b"RESULT" {
-                                        let mut input = r[6..r.len()].to_vec();
-                                        done = true;
-                                        let mut top_level = true;
-                                        let mut s = String::new();
-                                        while input.len() > 0 {
-                                            match pumpkinscript::binparser::data(&input.clone()) {
-                                                pumpkinscript::ParseResult::Done(rest, data) => {
-                                                    let (_, size) = pumpkinscript::binparser::data_size(data)
-                                                        .unwrap();
-                                                    let data = &data[script::offset_by_size(size)..];
-
-                                                    input = Vec::from(rest);
-
-                                                    if rest.len() == 0 && top_level {
-                                                        top_level = false;
-                                                        if data.len() > 0 {
-                                                            if cfg!(target_os = "windows") {
-                                                                let _ = write!(&mut s, "Error: ");
-                                                            } else {
-                                                                let _ = write!(&mut s,
-                                                                               "{}",
-                                                                               Red.paint("Error: "));
-                                                            }
-                                                            input = Vec::from(data);
-                                                        }
-                                                    } else {
-                                                        print_item(&mut s, data);
-                                                    }
-                                                }
-                                                e => {
-                                                    panic!("{:?}", e);
-                                                }
-                                            }
-                                        }
-                                        println!("{}", s);
-                                    }
-                                }).expect("can't receive");
+                            match receive(&mut stream, &program, &debug_topic) {
+                                Response::Result => (),
+                                Response::Debugger => {
+                                    debugged = Some((program, debug_topic));
+                                    current_prompt = "debug> ";
+                                }
                             }
                         },
                         Err(err) => {
@@ -0,0 +1,399 @@
+// Copyright (c) 2017, All Contributors (see CONTRIBUTORS file)
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! # Decimal
+//!
+//! Fixed-point decimal numbers: an arbitrary-precision unscaled integer
+//! and a scale (number of digits after the decimal point, up to 255).
+//!
+//! ## Encoding
+//!
+//! Decimals are encoded in a way that preserves their numeric order when
+//! compared byte by byte:
+//!
+//! * Zero is encoded as `0x01` followed by the scale
+//! * Positive numbers are encoded as `0x02`, followed by the exponent (the
+//!   position of the decimal point relative to the most significant digit,
+//!   biased, 4 bytes big-endian), followed by significant digits packed two
+//!   per byte (each stored as `digit + 1`, terminated with a zero nibble),
+//!   followed by the scale
+//! * Negative numbers are encoded as `0x00`, followed by the inverted
+//!   encoding of their absolute value (without the leading `0x02`)
+//!
+//! Numerically equal decimals of different scale (`12.5` and `12.50`) differ
+//! only in the trailing scale byte.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use byteorder::{BigEndian, ByteOrder};
+use num_bigint::BigInt;
+use num_traits::{Zero, One, Signed, FromPrimitive};
+
+use super::{Packable, Unpackable};
+
+const NEGATIVE: u8 = 0x00;
+const ZERO: u8 = 0x01;
+const POSITIVE: u8 = 0x02;
+
+const EXPONENT_BIAS: i64 = 0x8000_0000;
+const MAX_TRAILING_ZEROS: i64 = 0xFFFF;
+
+/// Rounding applied when a result has more digits than its scale allows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// Towards negative infinity
+    Floor,
+    /// Towards positive infinity
+    Ceil,
+    /// To the nearest, half-way cases away from zero
+    Round,
+    /// To the nearest, half-way cases to the even neighbour
+    Even,
+    /// Towards zero
+    Trunc,
+}
+
+/// Fixed-point decimal number
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    unscaled: BigInt,
+    scale: u8,
+}
+
+/// Error returned when a string can't be parsed as a decimal
+#[derive(Debug, PartialEq)]
+pub struct ParseDecimalError;
+
+fn pow10(exp: usize) -> BigInt {
+    let ten = BigInt::from_u8(10).unwrap();
+    let mut result = BigInt::one();
+    for _ in 0..exp {
+        result = result * &ten;
+    }
+    result
+}
+
+/// Divides `a` by `b` (which must not be zero), rounding the quotient
+fn div_round(a: &BigInt, b: &BigInt, rounding: Rounding) -> BigInt {
+    let q = a / b;
+    let r = a % b;
+    if r.is_zero() {
+        return q;
+    }
+    let negative = a.is_negative() != b.is_negative();
+    let away = match rounding {
+        Rounding::Trunc => false,
+        Rounding::Floor => negative,
+        Rounding::Ceil => !negative,
+        Rounding::Round | Rounding::Even => {
+            let twice = r.abs() * BigInt::from_u8(2).unwrap();
+            match twice.cmp(&b.abs()) {
+                Ordering::Less => false,
+                Ordering::Greater => true,
+                Ordering::Equal => rounding == Rounding::Round ||
+                                   !(&q % BigInt::from_u8(2).unwrap()).is_zero(),
+            }
+        }
+    };
+    match (away, negative) {
+        (false, _) => q,
+        (true, false) => q + BigInt::one(),
+        (true, true) => q - BigInt::one(),
+    }
+}
+
+impl Decimal {
+    /// Creates a decimal equal to `unscaled * 10^-scale`
+    pub fn new(unscaled: BigInt, scale: u8) -> Self {
+        Decimal { unscaled: unscaled, scale: scale }
+    }
+
+    /// Returns the unscaled integer
+    pub fn unscaled(&self) -> &BigInt {
+        &self.unscaled
+    }
+
+    /// Returns the number of digits after the decimal point
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Returns the unscaled integer of this decimal at a greater or equal scale
+    fn unscaled_at(&self, scale: u8) -> BigInt {
+        &self.unscaled * pow10((scale - self.scale) as usize)
+    }
+
+    /// Changes the scale, rounding if digits have to be dropped
+    pub fn rescale(&self, scale: u8, rounding: Rounding) -> Decimal {
+        if scale >= self.scale {
+            Decimal::new(self.unscaled_at(scale), scale)
+        } else {
+            let divisor = pow10((self.scale - scale) as usize);
+            Decimal::new(div_round(&self.unscaled, &divisor, rounding), scale)
+        }
+    }
+
+    /// Adds two decimals, the scale of the result is the greater of the two
+    pub fn add(&self, other: &Decimal) -> Decimal {
+        let scale = ::std::cmp::max(self.scale, other.scale);
+        Decimal::new(self.unscaled_at(scale) + other.unscaled_at(scale), scale)
+    }
+
+    /// Subtracts two decimals, the scale of the result is the greater of the two
+    pub fn sub(&self, other: &Decimal) -> Decimal {
+        let scale = ::std::cmp::max(self.scale, other.scale);
+        Decimal::new(self.unscaled_at(scale) - other.unscaled_at(scale), scale)
+    }
+
+    /// Multiplies two decimals, the scale of the result is the sum of the two.
+    /// Returns `None` if the scale would exceed 255.
+    pub fn mul(&self, other: &Decimal) -> Option<Decimal> {
+        self.scale.checked_add(other.scale)
+            .map(|scale| Decimal::new(&self.unscaled * &other.unscaled, scale))
+    }
+
+    /// Divides two decimals, rounding the result to `scale`.
+    /// Returns `None` if `other` is zero.
+    pub fn div(&self, other: &Decimal, scale: u8, rounding: Rounding) -> Option<Decimal> {
+        if other.unscaled.is_zero() {
+            return None;
+        }
+        // self / other = (a * 10^-sa) / (b * 10^-sb), scaled by 10^scale:
+        // (a * 10^(scale + sb)) / (b * 10^sa)
+        let dividend = &self.unscaled * pow10(scale as usize + other.scale as usize);
+        let divisor = &other.unscaled * pow10(self.scale as usize);
+        Some(Decimal::new(div_round(&dividend, &divisor, rounding), scale))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let scale = ::std::cmp::max(self.scale, other.scale);
+        self.unscaled_at(scale).cmp(&other.unscaled_at(scale))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.unscaled.abs().to_str_radix(10);
+        let scale = self.scale as usize;
+        let digits = if digits.len() <= scale {
+            format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits)
+        } else {
+            digits
+        };
+        let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+        if self.unscaled.is_negative() {
+            write!(f, "-")?;
+        }
+        if scale == 0 {
+            write!(f, "{}", int_part)
+        } else {
+            write!(f, "{}.{}", int_part, frac_part)
+        }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, s) = match s.as_bytes().first() {
+            Some(&b'-') => (true, &s[1..]),
+            Some(&b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (int_part, frac_part) = match s.find('.') {
+            Some(dot) => (&s[..dot], &s[dot + 1..]),
+            None => (s, ""),
+        };
+        if int_part.is_empty() || (s.contains('.') && frac_part.is_empty()) ||
+           frac_part.len() > 255 ||
+           !int_part.bytes().chain(frac_part.bytes()).all(|c| c >= b'0' && c <= b'9') {
+            return Err(ParseDecimalError);
+        }
+        let digits = format!("{}{}", int_part, frac_part);
+        let unscaled = BigInt::parse_bytes(digits.as_bytes(), 10).ok_or(ParseDecimalError)?;
+        let unscaled = if negative { -unscaled } else { unscaled };
+        Ok(Decimal::new(unscaled, frac_part.len() as u8))
+    }
+}
+
+impl Packable for Decimal {
+    fn pack(&self) -> Vec<u8> {
+        if self.unscaled.is_zero() {
+            return vec![ZERO, self.scale];
+        }
+        let digits = self.unscaled.abs().to_str_radix(10);
+        let exponent = digits.len() as i64 - self.scale as i64;
+        let digits = digits.trim_right_matches('0').as_bytes();
+
+        let mut body = vec![0; 4];
+        BigEndian::write_u32(&mut body, (exponent + EXPONENT_BIAS) as u32);
+        for pair in digits.chunks(2) {
+            let hi = pair[0] - b'0' + 1;
+            let lo = if pair.len() > 1 { pair[1] - b'0' + 1 } else { 0 };
+            body.push(hi << 4 | lo);
+        }
+        if digits.len() % 2 == 0 {
+            body.push(0);
+        }
+        body.push(self.scale);
+
+        let mut bytes = Vec::with_capacity(body.len() + 1);
+        if self.unscaled.is_negative() {
+            bytes.push(NEGATIVE);
+            bytes.extend(body.iter().map(|b| !b));
+        } else {
+            bytes.push(POSITIVE);
+            bytes.extend_from_slice(&body);
+        }
+        bytes
+    }
+}
+
+/// Decodes an encoded absolute value (without the sign byte)
+fn unpack_body(body: &[u8]) -> Option<(BigInt, u8)> {
+    if body.len() < 6 {
+        return None;
+    }
+    let exponent = BigEndian::read_u32(&body[..4]) as i64 - EXPONENT_BIAS;
+    let mut digits = Vec::new();
+    let mut rest = &body[4..];
+    loop {
+        match rest.split_first() {
+            Some((&byte, tail)) => {
+                rest = tail;
+                let (hi, lo) = (byte >> 4, byte & 0x0f);
+                if hi == 0 || hi > 10 || lo > 10 {
+                    return None;
+                }
+                digits.push(b'0' + hi - 1);
+                if lo == 0 {
+                    break;
+                }
+                digits.push(b'0' + lo - 1);
+            }
+            None => return None,
+        }
+    }
+    if rest.len() != 1 {
+        return None;
+    }
+    let scale = rest[0];
+    // unscaled = digits * 10^(exponent - number of digits + scale),
+    // the shift is the number of trailing zeros and is limited to keep
+    // malformed input from producing enormous numbers
+    let shift = exponent - digits.len() as i64 + scale as i64;
+    if shift < 0 || shift > MAX_TRAILING_ZEROS {
+        return None;
+    }
+    BigInt::parse_bytes(&digits, 10).map(|d| (d * pow10(shift as usize), scale))
+}
+
+impl<'a> Unpackable<Decimal> for &'a [u8] {
+    fn unpack(&self) -> Option<Decimal> {
+        let decimal = match self.split_first() {
+            Some((&ZERO, scale)) if scale.len() == 1 => Some(Decimal::new(BigInt::zero(), scale[0])),
+            Some((&POSITIVE, body)) => unpack_body(body)
+                .map(|(unscaled, scale)| Decimal::new(unscaled, scale)),
+            Some((&NEGATIVE, body)) => {
+                let body: Vec<u8> = body.iter().map(|b| !b).collect();
+                unpack_body(&body).map(|(unscaled, scale)| Decimal::new(-unscaled, scale))
+            }
+            _ => None,
+        };
+        // only accept the canonical encoding
+        match decimal {
+            Some(decimal) => if decimal.pack().as_slice() == *self { Some(decimal) } else { None },
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(dec("12.50").to_string(), "12.50");
+        assert_eq!(dec("-0.05").to_string(), "-0.05");
+        assert_eq!(dec("+7").to_string(), "7");
+        assert_eq!(dec("0.000").to_string(), "0.000");
+        assert_eq!(dec("12.50").scale(), 2);
+        assert!(Decimal::from_str("12.").is_err());
+        assert!(Decimal::from_str(".5").is_err());
+        assert!(Decimal::from_str("1e5").is_err());
+    }
+
+    #[test]
+    fn pack_unpack() {
+        for s in &["0", "0.00", "1", "12.50", "-12.50", "100", "-0.001", "123456789.987654321"] {
+            let d = dec(s);
+            let unpacked: Decimal = d.pack().as_slice().unpack().unwrap();
+            assert_eq!(unpacked.to_string(), d.to_string());
+        }
+        let invalid: Option<Decimal> = (&[0x03u8][..]).unpack();
+        assert!(invalid.is_none());
+    }
+
+    #[test]
+    fn order_preserving() {
+        let values = ["-1000", "-12.51", "-12.5", "-12.05", "-1", "-0.001", "0", "0.001",
+                      "0.01", "1", "1.1", "12", "12.05", "12.5", "12.51", "100", "1000.5"];
+        for pair in values.windows(2) {
+            assert!(dec(pair[0]).pack() < dec(pair[1]).pack(), "{} < {}", pair[0], pair[1]);
+            assert!(dec(pair[0]) < dec(pair[1]));
+        }
+        assert_eq!(dec("12.5"), dec("12.50"));
+        assert!(dec("12.5").pack() != dec("12.50").pack());
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(dec("12.50").add(&dec("0.125")).to_string(), "12.625");
+        assert_eq!(dec("12.50").sub(&dec("20")).to_string(), "-7.50");
+        assert_eq!(dec("1.5").mul(&dec("-0.25")).unwrap().to_string(), "-0.375");
+        assert_eq!(dec("10").div(&dec("3"), 2, Rounding::Round).unwrap().to_string(), "3.33");
+        assert_eq!(dec("10").div(&dec("3"), 2, Rounding::Ceil).unwrap().to_string(), "3.34");
+        assert_eq!(dec("-10").div(&dec("3"), 2, Rounding::Floor).unwrap().to_string(), "-3.34");
+        assert_eq!(dec("-10").div(&dec("3"), 2, Rounding::Trunc).unwrap().to_string(), "-3.33");
+        assert!(dec("1").div(&dec("0.00"), 2, Rounding::Round).is_none());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(dec("2.5").rescale(0, Rounding::Round).to_string(), "3");
+        assert_eq!(dec("-2.5").rescale(0, Rounding::Round).to_string(), "-3");
+        assert_eq!(dec("2.5").rescale(0, Rounding::Even).to_string(), "2");
+        assert_eq!(dec("3.5").rescale(0, Rounding::Even).to_string(), "4");
+        assert_eq!(dec("-2.5").rescale(0, Rounding::Even).to_string(), "-2");
+        assert_eq!(dec("2.45").rescale(1, Rounding::Trunc).to_string(), "2.4");
+        assert_eq!(dec("2.4").rescale(3, Rounding::Trunc).to_string(), "2.400");
+    }
+}
@@ -79,6 +79,9 @@ pub use self::textparser::parse;
 mod packable;
 pub use packable::{Packable, Unpackable};
 
+pub mod decimal;
+pub use decimal::Decimal;
+
 pub mod encodables;
 
 pub use self::encodables::{Encodable, Instruction, InstructionRef, Closure, Receivable};
@@ -13,7 +13,7 @@ use num_traits::Zero;
 use core::str::FromStr;
 use std::str;
 
-use super::{Program, Packable, ParseError};
+use super::{Program, Packable, ParseError, Decimal};
 
 fn prefix_instruction(instruction: &[u8]) -> Vec<u8> {
     let mut vec = Vec::new();
@@ -352,6 +352,24 @@ named!(float64<Vec<u8>>,
                    (sized_vec(val.pack()))
                })));
 
+
+named!(decimal_str<String>,
+       do_parse!(
+           int_part: int_str                                                  >>
+           frac_part: opt!(preceded!(char!('.'), take_while1!(is_digit)))    >>
+               ({
+                   match frac_part {
+                       Some(frac) => int_part + "." + str::from_utf8(frac).unwrap(),
+                       None => int_part,
+                   }
+               })));
+
+named!(decimal<Vec<u8>>,
+       do_parse!(
+           dec: map_res!(decimal_str, |s: String| Decimal::from_str(&s)) >>
+           tag!("d")                                                     >>
+           delim_or_end                                                  >>
+           (sized_vec(dec.pack()))));
     
 named!(instruction<Vec<u8>>, do_parse!(
                         instruction: take_while1!(is_instruction_char)  >>
@@ -375,7 +393,7 @@ named!(comment_, do_parse!(
                                (&[])));
 named!(comment<Vec<u8>>, do_parse!(comment_ >> (vec![])));
 named!(item<Vec<u8>>, alt!(comment | uint | binary | string | sint | int_sized | float32 |
-                           float64 | wrap | instructionref | instruction));
+                           float64 | decimal | wrap | instructionref | instruction));
 
 fn unwrap_instruction(mut instruction: Vec<u8>) -> Vec<u8> {
     let mut vec = Vec::new();
@@ -701,6 +719,15 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_decimal() {
+        assert_eq!(parse("+12.50d").unwrap(), parse("12.50d").unwrap());
+        assert_eq!(parse("12.50d").unwrap(), vec![8, 2, 128, 0, 0, 2, 0x23, 0x60, 2]);
+        assert_eq!(parse("-12.50d").unwrap(), vec![8, 0, 127, 255, 255, 253, 0xdc, 0x9f, 253]);
+        assert_eq!(parse("0d").unwrap(), vec![2, 1, 0]);
+        assert_eq!(parse("12d").unwrap(), vec![8, 2, 128, 0, 0, 2, 0x23, 0, 0]);
+    }
+
     #[test]
     fn test_number_prefixed_instruction() {
         let script = parse("2DUP").unwrap();
@@ -58,7 +58,7 @@ fn eval(name: &[u8], script: &[u8], timestamp: Arc<timestamp::Timestamp<nvmem::M
         sender.schedule_env(EnvId::new(), Vec::from(script), callback,
                                                         Box::new(sender0));
         match receiver.recv() {
-            Ok(ResponseMessage::EnvTerminated(_, stack, _)) => {
+            Ok(ResponseMessage::EnvTerminated(_, stack, _, _, _)) => {
                 sender.shutdown();
                 simple_accessor.shutdown();
                 let mut stack_ = Vec::with_capacity(stack.len());
@@ -73,10 +73,14 @@ fn eval(name: &[u8], script: &[u8], timestamp: Arc<timestamp::Timestamp<nvmem::M
                            &name);
                 println!(" * {}", &name);
             }
-            Ok(ResponseMessage::EnvFailed(_, err, _, _)) => {
+            Ok(ResponseMessage::EnvFailed(_, err, _, _, trace, _, _)) => {
                 sender.shutdown();
                 simple_accessor.shutdown();
-                panic!("Error while executing {:?}: {:?}", &name, err)
+                let mut frames = String::new();
+                for frame in trace.unwrap_or_default() {
+                    frames.push_str(&format!("\n  at {}", frame));
+                }
+                panic!("Error while executing {:?}: {:?}{}", &name, err, frames)
             }
             Err(err) => {
                 sender.shutdown();